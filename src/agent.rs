@@ -0,0 +1,916 @@
+//! Agent turn loop.
+//!
+//! Drives an [`LlmProvider`] and a set of [`ToolProvider`]s until the
+//! model ends its turn: streams a completion, executes requested tools
+//! according to their [`ToolKind`], appends results to the
+//! [`Conversation`] and repeats.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::compaction::{Compaction, Compactor};
+use crate::conversation::{Conversation, MessageMetadata, Provenance};
+use crate::error::{AgentError, Result};
+use crate::permission::PermissionClient;
+use crate::provider::{
+    Backend, CompletionEvent, CompletionRequest, CompletionResponse, CompletionStream, LlmProvider,
+    PromptCache, StreamCollector,
+};
+use crate::tools::loop_detection::{LoopDetector, LoopSeverity};
+use crate::tools::{ToolKind, ToolProvider};
+use crate::types::{Content, ContentBlock, Message, Role, StopReason, Tool, Usage};
+use crate::usage::UsageLedger;
+
+/// Default maximum tokens per completion.
+const DEFAULT_MAX_TOKENS: u32 = 8192;
+
+/// Default maximum model round-trips per run.
+const DEFAULT_MAX_ITERATIONS: usize = 25;
+
+/// Note appended to tool output when a loop is likely.
+const LOOP_WARNING: &str = "\n\n[This tool call has repeated with the same result several times. \
+Try a different approach instead of repeating it.]";
+
+/// Tool result reported for calls cut short by cancellation.
+const CANCELLED_OUTPUT: &str = "tool call cancelled by the user";
+
+/// Configuration for an agent run.
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    /// Model identifier.
+    pub model: String,
+    /// Maximum tokens to generate per completion.
+    pub max_tokens: u32,
+    /// Maximum number of model round-trips before giving up.
+    pub max_iterations: usize,
+    /// Token budget for extended thinking, if enabled.
+    pub thinking_budget: Option<u32>,
+    /// Prompt caching strategy for each completion.
+    pub cache: Option<PromptCache>,
+}
+
+impl AgentConfig {
+    /// Create a config for a model with default limits.
+    #[must_use]
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            thinking_budget: None,
+            cache: None,
+        }
+    }
+}
+
+/// Event emitted while the agent runs.
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    /// A chunk of assistant text.
+    TextDelta(String),
+    /// A chunk of model reasoning.
+    ThinkingDelta(String),
+    /// A tool call is about to execute.
+    ToolCallStart {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// A tool call has finished.
+    ToolCallEnd {
+        id: String,
+        name: String,
+        output: String,
+        is_error: bool,
+    },
+    /// A repeating tool call pattern was detected.
+    LoopDetected {
+        name: String,
+        severity: LoopSeverity,
+    },
+    /// Older turns were summarized to fit the context window.
+    Compacted(Compaction),
+    /// A model round-trip has completed.
+    TurnComplete {
+        iteration: usize,
+        stop_reason: Option<StopReason>,
+        usage: Option<Usage>,
+    },
+}
+
+/// Summary of a completed agent run.
+#[derive(Debug, Clone)]
+pub struct AgentRun {
+    /// Stop reason of the final completion.
+    pub stop_reason: Option<StopReason>,
+    /// Number of model round-trips performed.
+    pub iterations: usize,
+    /// Text of the final assistant message.
+    pub text: String,
+}
+
+/// An assistant message made of content blocks.
+const fn assistant(blocks: Vec<ContentBlock>) -> Message {
+    Message {
+        role: Role::Assistant,
+        content: Content::Blocks(blocks),
+    }
+}
+
+/// A tool call requested by the model.
+#[derive(Debug, Clone)]
+struct ToolCall {
+    id: String,
+    name: String,
+    input: serde_json::Value,
+}
+
+/// Result of executing a single tool call.
+#[derive(Debug, Clone)]
+struct ToolOutcome {
+    output: String,
+    is_error: bool,
+}
+
+/// Reusable agent that owns the completion/tool-execution loop.
+pub struct Agent {
+    provider: Arc<dyn LlmProvider>,
+    tools: Vec<Arc<dyn ToolProvider>>,
+    permissions: Option<PermissionClient>,
+    events: Option<mpsc::UnboundedSender<AgentEvent>>,
+    loop_detector: LoopDetector,
+    ledger: Option<(Arc<UsageLedger>, String)>,
+    compactor: Option<Compactor>,
+    cancel: CancellationToken,
+    config: AgentConfig,
+}
+
+impl std::fmt::Debug for Agent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Agent")
+            .field("provider", &self.provider.name())
+            .field("tools", &self.tools.len())
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Agent {
+    /// Create an agent for a provider and config.
+    #[must_use]
+    pub fn new(provider: Arc<dyn LlmProvider>, config: AgentConfig) -> Self {
+        Self {
+            provider,
+            tools: Vec::new(),
+            permissions: None,
+            events: None,
+            loop_detector: LoopDetector::default(),
+            ledger: None,
+            compactor: None,
+            cancel: CancellationToken::new(),
+            config,
+        }
+    }
+
+    /// Add a tool provider.
+    #[must_use]
+    pub fn with_tools(mut self, tools: Arc<dyn ToolProvider>) -> Self {
+        self.tools.push(tools);
+        self
+    }
+
+    /// Consult a permission client before executing tools.
+    #[must_use]
+    pub fn with_permissions(mut self, permissions: PermissionClient) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Record token usage in a ledger under a session name.
+    ///
+    /// The ledger budget is checked before every completion, so a run
+    /// stops with [`AgentError::BudgetExceeded`] once a ceiling is hit.
+    #[must_use]
+    pub fn with_usage_ledger(
+        mut self,
+        ledger: Arc<UsageLedger>,
+        session: impl Into<String>,
+    ) -> Self {
+        self.ledger = Some((ledger, session.into()));
+        self
+    }
+
+    /// Compact the conversation before a completion that would near the
+    /// context window.
+    #[must_use]
+    pub fn with_compactor(mut self, compactor: Compactor) -> Self {
+        self.compactor = Some(compactor);
+        self
+    }
+
+    /// Abort runs when a token is cancelled.
+    ///
+    /// Cancelling aborts the in-flight completion and running tools. Tool
+    /// calls that were cut short are reported to the model as cancelled
+    /// before the run returns [`AgentError::Cancelled`], so the
+    /// conversation can be resumed.
+    #[must_use]
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Subscribe to agent events.
+    ///
+    /// Returns the receiving end of the event channel. Calling this again
+    /// replaces the previous subscriber.
+    pub fn subscribe(&mut self) -> mpsc::UnboundedReceiver<AgentEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.events = Some(tx);
+        rx
+    }
+
+    /// Get the agent config.
+    #[must_use]
+    pub const fn config(&self) -> &AgentConfig {
+        &self.config
+    }
+
+    /// Run the agent until the model ends its turn.
+    ///
+    /// The conversation must already contain the user's prompt. Assistant
+    /// messages and tool results are appended as the loop progresses.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the provider fails, the stream reports an error,
+    /// the loop detector trips its circuit breaker, the usage budget is
+    /// exhausted, the run is cancelled, or `max_iterations` is reached.
+    pub async fn run(&mut self, conversation: &mut Conversation) -> Result<AgentRun> {
+        let (definitions, routes) = self.tool_routes();
+
+        for iteration in 1..=self.config.max_iterations {
+            self.check_limits()?;
+            self.compact(conversation).await?;
+
+            let request = CompletionRequest {
+                model: self.config.model.clone(),
+                max_tokens: self.config.max_tokens,
+                messages: conversation.messages().to_vec(),
+                system: conversation.system().map(String::from),
+                tools: if definitions.is_empty() {
+                    None
+                } else {
+                    Some(definitions.clone())
+                },
+                thinking_budget: self.config.thinking_budget,
+                cache: self.config.cache.clone(),
+                cancel: Some(self.cancel.clone()),
+                ..Default::default()
+            };
+
+            let started = Instant::now();
+            let stream = self.provider.stream(request).await?;
+            let CompletionResponse {
+                message,
+                stop_reason,
+                usage,
+                backend,
+            } = self.consume(stream).await?;
+            let metadata = self.record_completion(usage.as_ref(), backend, started.elapsed());
+            let blocks = match message.content {
+                Content::Blocks(blocks) => blocks,
+                Content::Text(text) => vec![ContentBlock::Text { text }],
+            };
+
+            self.emit(AgentEvent::TurnComplete {
+                iteration,
+                stop_reason,
+                usage,
+            });
+
+            let calls: Vec<ToolCall> = blocks
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::ToolUse { id, name, input } => Some(ToolCall {
+                        id: id.clone(),
+                        name: name.clone(),
+                        input: input.clone(),
+                    }),
+                    _ => None,
+                })
+                .collect();
+
+            if calls.is_empty() {
+                let text = blocks
+                    .iter()
+                    .filter_map(|b| match b {
+                        ContentBlock::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<String>();
+                if !blocks.is_empty() {
+                    conversation.add_message(assistant(blocks), metadata);
+                }
+                return Ok(AgentRun {
+                    stop_reason,
+                    iterations: iteration,
+                    text,
+                });
+            }
+
+            conversation.add_message(assistant(blocks), metadata);
+
+            let outcomes = self.execute_batch(&calls, &routes).await;
+
+            let mut tripped = None;
+            for (call, mut outcome) in calls.into_iter().zip(outcomes) {
+                let params = call.input.to_string();
+                let severity = self
+                    .loop_detector
+                    .record(&call.name, &params, &outcome.output);
+
+                if severity >= LoopSeverity::Warning {
+                    tracing::warn!(tool = %call.name, ?severity, "agent: tool loop detected");
+                    self.emit(AgentEvent::LoopDetected {
+                        name: call.name.clone(),
+                        severity,
+                    });
+                }
+                if severity >= LoopSeverity::Critical {
+                    outcome.output.push_str(LOOP_WARNING);
+                }
+                if severity == LoopSeverity::CircuitBreaker {
+                    tripped = Some(call.name.clone());
+                }
+
+                conversation.add_tool_result(call.id, outcome.output, outcome.is_error);
+            }
+
+            if self.cancel.is_cancelled() {
+                return Err(AgentError::Cancelled);
+            }
+            if let Some(name) = tripped {
+                return Err(AgentError::LoopDetected(format!(
+                    "tool '{name}' repeated too many times"
+                )));
+            }
+        }
+
+        Err(AgentError::MaxIterations(self.config.max_iterations))
+    }
+
+    /// Refuse to start a completion once cancelled or over budget.
+    fn check_limits(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(AgentError::Cancelled);
+        }
+        match &self.ledger {
+            Some((ledger, _)) => ledger.check_budget(),
+            None => Ok(()),
+        }
+    }
+
+    /// Summarize older turns if the conversation nears the context window.
+    async fn compact(&self, conversation: &mut Conversation) -> Result<()> {
+        if let Some(compactor) = &self.compactor
+            && let Some(compaction) = compactor.compact_if_needed(conversation).await?
+        {
+            self.emit(AgentEvent::Compacted(compaction));
+        }
+        Ok(())
+    }
+
+    /// Record a completion's usage and describe the message it produced.
+    ///
+    /// Usage goes to the ledger, if one is attached. It is attributed to
+    /// the backend that served the request when the provider reports one.
+    /// A completion without usage is logged, since budgets cannot see it.
+    fn record_completion(
+        &self,
+        usage: Option<&Usage>,
+        backend: Option<Backend>,
+        latency: Duration,
+    ) -> MessageMetadata {
+        let (provider, model) = backend.map_or_else(
+            || (self.provider.name().to_string(), self.config.model.clone()),
+            |b| (b.provider, b.model),
+        );
+        if let Some((ledger, session)) = &self.ledger {
+            if let Some(usage) = usage {
+                ledger.record(
+                    session.clone(),
+                    provider.clone(),
+                    model.clone(),
+                    usage.clone(),
+                );
+            } else {
+                tracing::warn!(%provider, %model, "agent: completion reported no usage, not recorded in the ledger");
+            }
+        }
+        MessageMetadata::new(Provenance::Model)
+            .with_model(provider, model)
+            .with_usage(usage.cloned())
+            .with_latency(latency)
+    }
+
+    /// Collect tool definitions and map each tool name to its provider.
+    fn tool_routes(&self) -> (Vec<Tool>, HashMap<String, Arc<dyn ToolProvider>>) {
+        let mut definitions = Vec::new();
+        let mut routes = HashMap::new();
+
+        for provider in &self.tools {
+            for tool in provider.definitions() {
+                if routes.contains_key(&tool.name) {
+                    tracing::warn!(tool = %tool.name, "agent: duplicate tool name, keeping first");
+                    continue;
+                }
+                routes.insert(tool.name.clone(), Arc::clone(provider));
+                definitions.push(tool);
+            }
+        }
+
+        (definitions, routes)
+    }
+
+    /// Drain a completion stream into a response, forwarding deltas.
+    async fn consume(&self, mut stream: CompletionStream) -> Result<CompletionResponse> {
+        let mut collector = StreamCollector::new();
+
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            match &event {
+                CompletionEvent::TextDelta(text) => self.emit(AgentEvent::TextDelta(text.clone())),
+                CompletionEvent::ThinkingDelta(thinking) => {
+                    self.emit(AgentEvent::ThinkingDelta(thinking.clone()));
+                }
+                _ => {}
+            }
+            collector.push(event)?;
+        }
+
+        collector.finish()
+    }
+
+    /// Execute a batch of tool calls, returning outcomes in call order.
+    ///
+    /// Reads run in parallel with each other and with mutations; mutations
+    /// run one at a time in order. Interactive tools split the batch: all
+    /// earlier calls finish first, then the interactive call runs alone.
+    async fn execute_batch(
+        &self,
+        calls: &[ToolCall],
+        routes: &HashMap<String, Arc<dyn ToolProvider>>,
+    ) -> Vec<ToolOutcome> {
+        let mut outcomes: Vec<Option<ToolOutcome>> = vec![None; calls.len()];
+        let mut segment = Vec::new();
+
+        for (i, call) in calls.iter().enumerate() {
+            let kind = routes
+                .get(&call.name)
+                .map_or(ToolKind::Mutate, |p| p.kind(&call.name));
+
+            if kind == ToolKind::Interactive {
+                for (j, outcome) in self.execute_segment(calls, &segment, routes).await {
+                    outcomes[j] = Some(outcome);
+                }
+                segment.clear();
+                outcomes[i] = Some(self.execute_call(call, routes).await);
+            } else {
+                segment.push((i, kind));
+            }
+        }
+
+        for (j, outcome) in self.execute_segment(calls, &segment, routes).await {
+            outcomes[j] = Some(outcome);
+        }
+
+        outcomes
+            .into_iter()
+            .map(|o| {
+                o.unwrap_or_else(|| ToolOutcome {
+                    output: "tool call was not executed".to_string(),
+                    is_error: true,
+                })
+            })
+            .collect()
+    }
+
+    /// Execute non-interactive calls: reads concurrently, mutations in order.
+    async fn execute_segment(
+        &self,
+        calls: &[ToolCall],
+        segment: &[(usize, ToolKind)],
+        routes: &HashMap<String, Arc<dyn ToolProvider>>,
+    ) -> Vec<(usize, ToolOutcome)> {
+        let reads = futures::future::join_all(
+            segment
+                .iter()
+                .filter(|(_, kind)| *kind == ToolKind::Read)
+                .map(|&(i, _)| async move { (i, self.execute_call(&calls[i], routes).await) }),
+        );
+
+        let mutations = async {
+            let mut out = Vec::new();
+            for &(i, kind) in segment {
+                if kind != ToolKind::Read {
+                    out.push((i, self.execute_call(&calls[i], routes).await));
+                }
+            }
+            out
+        };
+
+        let (mut reads, mutations) = futures::join!(reads, mutations);
+        reads.extend(mutations);
+        reads
+    }
+
+    /// Check permissions for and execute a single tool call.
+    async fn execute_call(
+        &self,
+        call: &ToolCall,
+        routes: &HashMap<String, Arc<dyn ToolProvider>>,
+    ) -> ToolOutcome {
+        self.emit(AgentEvent::ToolCallStart {
+            id: call.id.clone(),
+            name: call.name.clone(),
+            input: call.input.clone(),
+        });
+
+        let outcome = match routes.get(&call.name) {
+            None => ToolOutcome {
+                output: format!("unknown tool: {}", call.name),
+                is_error: true,
+            },
+            Some(provider) => {
+                let arguments = call.input.to_string();
+                match self
+                    .check_permission(provider.as_ref(), call, &arguments)
+                    .await
+                {
+                    Err(output) => ToolOutcome {
+                        output,
+                        is_error: true,
+                    },
+                    Ok(()) if self.cancel.is_cancelled() => ToolOutcome {
+                        output: CANCELLED_OUTPUT.to_string(),
+                        is_error: true,
+                    },
+                    Ok(()) => match provider.execute(&call.name, &arguments, &self.cancel).await {
+                        Ok(output) => ToolOutcome {
+                            output,
+                            is_error: false,
+                        },
+                        Err(e) if matches!(e.downcast_ref(), Some(AgentError::Cancelled)) => {
+                            ToolOutcome {
+                                output: CANCELLED_OUTPUT.to_string(),
+                                is_error: true,
+                            }
+                        }
+                        Err(e) => ToolOutcome {
+                            output: e.to_string(),
+                            is_error: true,
+                        },
+                    },
+                }
+            }
+        };
+
+        self.emit(AgentEvent::ToolCallEnd {
+            id: call.id.clone(),
+            name: call.name.clone(),
+            output: outcome.output.clone(),
+            is_error: outcome.is_error,
+        });
+
+        outcome
+    }
+
+    /// Ask the permission client whether a tool call may run.
+    ///
+    /// Returns the message to report back to the model when it may not.
+    async fn check_permission(
+        &self,
+        provider: &dyn ToolProvider,
+        call: &ToolCall,
+        arguments: &str,
+    ) -> std::result::Result<(), String> {
+        let Some(client) = &self.permissions else {
+            return Ok(());
+        };
+        let Some((action, context)) = provider.permission(&call.name, arguments) else {
+            return Ok(());
+        };
+
+        match client.request(&call.name, action, context).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("permission denied for tool '{}'", call.name)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Send an event to the subscriber, if any.
+    fn emit(&self, event: AgentEvent) {
+        if let Some(tx) = &self.events {
+            let _ = tx.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use async_trait::async_trait;
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::permission::{
+        AgentPermissions, PermissionAction, PermissionContext, PermissionPreset,
+    };
+    use crate::providers::MockProvider;
+    use crate::types::Role;
+
+    /// Tool provider that echoes its input and records calls.
+    #[derive(Default)]
+    struct EchoTools {
+        calls: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ToolProvider for EchoTools {
+        fn definitions(&self) -> Vec<Tool> {
+            vec![Tool {
+                name: "echo".to_string(),
+                description: "Echo input".to_string(),
+                input_schema: serde_json::json!({"type": "object"}),
+            }]
+        }
+
+        async fn execute(
+            &self,
+            name: &str,
+            arguments: &str,
+            _cancel: &CancellationToken,
+        ) -> anyhow::Result<String> {
+            self.calls.lock().push(arguments.to_string());
+            Ok(format!("{name}: {arguments}"))
+        }
+
+        fn permission(
+            &self,
+            _name: &str,
+            arguments: &str,
+        ) -> Option<(PermissionAction, PermissionContext)> {
+            Some((
+                PermissionAction::Execute,
+                PermissionContext::Bash {
+                    command: arguments.to_string(),
+                    working_dir: PathBuf::from("/tmp"),
+                },
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_tools_until_end_turn() {
+        let provider = MockProvider::new()
+            .with_tool_use("call_1", "echo", serde_json::json!({"x": 1}))
+            .with_text("all done");
+        let tools = Arc::new(EchoTools::default());
+        let mut agent = Agent::new(Arc::new(provider), AgentConfig::new("test"))
+            .with_tools(Arc::clone(&tools) as Arc<dyn ToolProvider>);
+
+        let mut conv = Conversation::new();
+        conv.add_user_message("hi");
+
+        let run = agent.run(&mut conv).await.unwrap();
+
+        assert_eq!(run.iterations, 2);
+        assert_eq!(run.text, "all done");
+        assert_eq!(run.stop_reason, Some(StopReason::EndTurn));
+        assert_eq!(tools.calls.lock().as_slice(), [r#"{"x":1}"#]);
+        // user, assistant tool use, tool result, assistant text
+        assert_eq!(conv.messages().len(), 4);
+        assert_eq!(conv.messages()[3].role, Role::Assistant);
+        let metadata = conv.metadata(conv.head().unwrap()).unwrap();
+        assert_eq!(metadata.source, Some(Provenance::Model));
+        assert_eq!(metadata.provider.as_deref(), Some("mock"));
+        assert_eq!(metadata.model.as_deref(), Some("test"));
+        assert!(metadata.latency_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn compacts_before_completion() {
+        let summarizer = Arc::new(MockProvider::new().with_text("greeted"));
+        let compactor = Compactor::new(summarizer, "small")
+            .with_context_window(1)
+            .with_keep_turns(1);
+        let provider = Arc::new(MockProvider::new().with_text("done"));
+        let mut agent = Agent::new(
+            Arc::clone(&provider) as Arc<dyn LlmProvider>,
+            AgentConfig::new("test"),
+        )
+        .with_compactor(compactor);
+        let mut events = agent.subscribe();
+
+        let mut conv = Conversation::new();
+        conv.add_user_message("hello");
+        conv.add_assistant_message("hi");
+        conv.add_user_message("bye");
+
+        agent.run(&mut conv).await.unwrap();
+
+        assert!(matches!(
+            events.recv().await,
+            Some(AgentEvent::Compacted(Compaction { summarized: 2, .. }))
+        ));
+        let sent = &provider.requests()[0].messages;
+        let roles: Vec<Role> = sent.iter().map(|m| m.role).collect();
+        assert_eq!(roles, [Role::User, Role::Assistant, Role::User]);
+        assert!(sent[0].content.text().ends_with("greeted"));
+        assert_eq!(sent[2].content.text(), "bye");
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_iterations() {
+        let provider = (0..5).fold(MockProvider::new(), |provider, i| {
+            provider.with_tool_use(format!("call_{i}"), "echo", serde_json::json!({"i": i}))
+        });
+        let config = AgentConfig {
+            max_iterations: 3,
+            ..AgentConfig::new("test")
+        };
+        let mut agent = Agent::new(Arc::new(provider), config)
+            .with_tools(Arc::new(EchoTools::default()) as Arc<dyn ToolProvider>);
+
+        let mut conv = Conversation::new();
+        conv.add_user_message("loop");
+
+        let result = agent.run(&mut conv).await;
+        assert!(matches!(result, Err(AgentError::MaxIterations(3))));
+    }
+
+    #[tokio::test]
+    async fn budget_stops_run_before_next_completion() {
+        let provider = MockProvider::new().with_response(vec![
+            CompletionEvent::ContentBlockDone {
+                index: 0,
+                block: ContentBlock::ToolUse {
+                    id: "call_1".into(),
+                    name: "echo".into(),
+                    input: serde_json::json!({}),
+                },
+            },
+            CompletionEvent::Done {
+                stop_reason: Some(StopReason::ToolUse),
+                usage: Some(Usage {
+                    input_tokens: 100,
+                    output_tokens: 50,
+                    ..Default::default()
+                }),
+            },
+        ]);
+        let ledger = Arc::new(
+            UsageLedger::new().with_budget(crate::usage::Budget::new().with_max_tokens(100)),
+        );
+        let mut agent = Agent::new(Arc::new(provider), AgentConfig::new("test"))
+            .with_tools(Arc::new(EchoTools::default()) as Arc<dyn ToolProvider>)
+            .with_usage_ledger(Arc::clone(&ledger), "session-1");
+
+        let mut conv = Conversation::new();
+        conv.add_user_message("hi");
+
+        let result = agent.run(&mut conv).await;
+
+        assert!(matches!(result, Err(AgentError::BudgetExceeded(_))));
+        let records = ledger.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].provider, "mock");
+        assert_eq!(records[0].model, "test");
+        assert_eq!(ledger.session_totals("session-1").total_tokens(), 150);
+        // The tool result was appended before stopping
+        assert_eq!(conv.messages().len(), 3);
+    }
+
+    /// Tool provider that blocks until the run is cancelled.
+    struct BlockingTools;
+
+    #[async_trait]
+    impl ToolProvider for BlockingTools {
+        fn definitions(&self) -> Vec<Tool> {
+            vec![Tool {
+                name: "wait".to_string(),
+                description: "Wait forever".to_string(),
+                input_schema: serde_json::json!({"type": "object"}),
+            }]
+        }
+
+        async fn execute(
+            &self,
+            _name: &str,
+            _arguments: &str,
+            cancel: &CancellationToken,
+        ) -> anyhow::Result<String> {
+            cancel.cancelled().await;
+            Err(AgentError::Cancelled.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn cancellation_reports_tool_result_and_stops() {
+        let provider = MockProvider::new().with_tool_use("call_1", "wait", serde_json::json!({}));
+        let cancel = CancellationToken::new();
+        let mut agent = Agent::new(Arc::new(provider), AgentConfig::new("test"))
+            .with_tools(Arc::new(BlockingTools) as Arc<dyn ToolProvider>)
+            .with_cancellation(cancel.clone());
+        let mut events = agent.subscribe();
+
+        let trigger = tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                if matches!(event, AgentEvent::ToolCallStart { .. }) {
+                    cancel.cancel();
+                }
+            }
+        });
+
+        let mut conv = Conversation::new();
+        conv.add_user_message("hi");
+        let result = agent.run(&mut conv).await;
+        drop(agent);
+        trigger.await.unwrap();
+
+        assert!(matches!(result, Err(AgentError::Cancelled)));
+        // user, assistant tool use, cancelled tool result
+        assert_eq!(conv.messages().len(), 3);
+        let Content::Blocks(blocks) = &conv.messages()[2].content else {
+            panic!("expected tool result blocks");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::ToolResult {
+                is_error: Some(true),
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn denied_permission_reports_error_result() {
+        let provider = MockProvider::new()
+            .with_tool_use("call_1", "echo", serde_json::json!({}))
+            .with_text("ok");
+        let tools = Arc::new(EchoTools::default());
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let permissions = PermissionClient::with_presets(
+            "session".to_string(),
+            tx,
+            AgentPermissions {
+                bash_write: PermissionPreset::Deny,
+                ..Default::default()
+            },
+        );
+        let mut agent = Agent::new(Arc::new(provider), AgentConfig::new("test"))
+            .with_tools(Arc::clone(&tools) as Arc<dyn ToolProvider>)
+            .with_permissions(permissions);
+        let mut events = agent.subscribe();
+
+        let mut conv = Conversation::new();
+        conv.add_user_message("hi");
+        agent.run(&mut conv).await.unwrap();
+
+        assert!(tools.calls.lock().is_empty());
+        let mut saw_denied = false;
+        while let Ok(event) = events.try_recv() {
+            if let AgentEvent::ToolCallEnd { is_error, .. } = event {
+                saw_denied = is_error;
+            }
+        }
+        assert!(saw_denied);
+    }
+
+    #[tokio::test]
+    async fn unknown_tool_is_reported_to_model() {
+        let provider = MockProvider::new()
+            .with_tool_use("call_1", "missing", serde_json::json!({}))
+            .with_text("ok");
+        let mut agent = Agent::new(Arc::new(provider), AgentConfig::new("test"));
+
+        let mut conv = Conversation::new();
+        conv.add_user_message("hi");
+        agent.run(&mut conv).await.unwrap();
+
+        let Content::Blocks(blocks) = &conv.messages()[2].content else {
+            panic!("expected tool result blocks");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::ToolResult { is_error: Some(true), content, .. } if content.text().contains("unknown tool")
+        ));
+    }
+}
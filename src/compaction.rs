@@ -0,0 +1,560 @@
+//! Conversation compaction.
+//!
+//! A [`Compactor`] estimates the prompt size of a [`Conversation`] and,
+//! once it nears the model's context window, replaces older turns with a
+//! summary written by an [`LlmProvider`]. The system prompt, pinned
+//! messages and the most recent turns are kept verbatim, and a tool call
+//! is never separated from its result.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::conversation::{BranchEntry, Conversation, MessageId, MessageMetadata, Provenance};
+use crate::error::{AgentError, Result};
+use crate::provider::{CompletionRequest, LlmProvider};
+use crate::registry::ModelInfo;
+use crate::types::{Content, ContentBlock, Message, Role};
+
+/// Context window assumed when the model's is unknown.
+const DEFAULT_CONTEXT_WINDOW: u32 = 128_000;
+
+/// Default fraction of the context window that triggers compaction.
+const DEFAULT_THRESHOLD: f32 = 0.8;
+
+/// Default number of recent turns kept verbatim.
+const DEFAULT_KEEP_TURNS: usize = 4;
+
+/// Default token limit for the summary.
+const DEFAULT_SUMMARY_MAX_TOKENS: u32 = 2048;
+
+/// Characters of each tool result shown to the summarizer.
+const MAX_TOOL_RESULT_CHARS: usize = 2000;
+
+/// Text that starts every summary message.
+pub const SUMMARY_PREFIX: &str = "[Summary of the earlier conversation]\n\n";
+
+/// System prompt for the summarizer.
+const SUMMARY_SYSTEM_PROMPT: &str = "\
+You summarize the earlier part of a conversation between a user and an AI \
+assistant so the assistant can continue without the full transcript. \
+Preserve the user's goals and constraints, decisions made, facts learned, \
+files, commands and identifiers mentioned, tool results that still matter, \
+and any open tasks. Omit pleasantries and superseded details. Write the \
+summary as concise notes, without preamble.";
+
+/// Outcome of a compaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    /// Number of messages replaced by the summary.
+    pub summarized: usize,
+    /// Estimated tokens before compaction.
+    pub tokens_before: u32,
+    /// Estimated tokens after compaction.
+    pub tokens_after: u32,
+}
+
+/// Summarizes older turns when a conversation nears the context window.
+pub struct Compactor {
+    provider: Arc<dyn LlmProvider>,
+    model: String,
+    context_window: u32,
+    threshold: f32,
+    keep_turns: usize,
+    summary_max_tokens: u32,
+}
+
+impl std::fmt::Debug for Compactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compactor")
+            .field("provider", &self.provider.name())
+            .field("model", &self.model)
+            .field("context_window", &self.context_window)
+            .field("threshold", &self.threshold)
+            .field("keep_turns", &self.keep_turns)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Compactor {
+    /// Create a compactor that summarizes with `model` on `provider`.
+    #[must_use]
+    pub fn new(provider: Arc<dyn LlmProvider>, model: impl Into<String>) -> Self {
+        Self {
+            provider,
+            model: model.into(),
+            context_window: DEFAULT_CONTEXT_WINDOW,
+            threshold: DEFAULT_THRESHOLD,
+            keep_turns: DEFAULT_KEEP_TURNS,
+            summary_max_tokens: DEFAULT_SUMMARY_MAX_TOKENS,
+        }
+    }
+
+    /// Set the context window that the threshold applies to.
+    #[must_use]
+    pub const fn with_context_window(mut self, tokens: u32) -> Self {
+        self.context_window = tokens;
+        self
+    }
+
+    /// Use the input budget of the model the conversation runs on.
+    ///
+    /// `max_tokens` is reserved from the window for each completion. The
+    /// default window stays in place if the catalog does not know it.
+    #[must_use]
+    pub fn with_model_info(mut self, info: &ModelInfo, max_tokens: u32) -> Self {
+        if let Some(budget) = info.input_budget(max_tokens) {
+            self.context_window = budget;
+        }
+        self
+    }
+
+    /// Set the fraction of the context window that triggers compaction.
+    ///
+    /// Values are clamped to `0.0..=1.0`.
+    #[must_use]
+    pub const fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the number of recent turns kept verbatim.
+    ///
+    /// A turn starts at a user message that is not a tool result.
+    #[must_use]
+    pub const fn with_keep_turns(mut self, turns: usize) -> Self {
+        self.keep_turns = turns;
+        self
+    }
+
+    /// Set the token limit for the summary.
+    #[must_use]
+    pub const fn with_summary_max_tokens(mut self, tokens: u32) -> Self {
+        self.summary_max_tokens = tokens;
+        self
+    }
+
+    /// Estimated tokens at which compaction triggers.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn threshold_tokens(&self) -> u32 {
+        (self.context_window as f32 * self.threshold) as u32
+    }
+
+    /// Check whether a conversation has reached the threshold.
+    #[must_use]
+    pub fn needs_compaction(&self, conversation: &Conversation) -> bool {
+        conversation.estimated_tokens() >= self.threshold_tokens()
+    }
+
+    /// Compact the conversation if it has reached the threshold.
+    ///
+    /// Returns `None` if the conversation is below the threshold or has
+    /// nothing old enough to summarize.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the summary completion fails.
+    pub async fn compact_if_needed(
+        &self,
+        conversation: &mut Conversation,
+    ) -> Result<Option<Compaction>> {
+        if !self.needs_compaction(conversation) {
+            return Ok(None);
+        }
+        self.compact(conversation).await
+    }
+
+    /// Summarize everything before the most recent turns.
+    ///
+    /// The summary becomes the first message, followed by the older
+    /// pinned messages and the recent turns, on a new branch. Kept
+    /// messages are moved onto it with their IDs, and the summarized
+    /// ones stay in the tree as the old branch. Short synthetic messages
+    /// are placed between neighbours with the same role so roles keep
+    /// alternating. Returns `None` if there is nothing to summarize; the
+    /// conversation is unchanged on error.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the summary completion fails or is empty.
+    pub async fn compact(&self, conversation: &mut Conversation) -> Result<Option<Compaction>> {
+        let pinned: BTreeSet<MessageId> = conversation.pinned().collect();
+        let Some(plan) = plan(
+            conversation.messages(),
+            conversation.message_ids(),
+            &pinned,
+            self.keep_turns,
+        ) else {
+            return Ok(None);
+        };
+
+        let tokens_before = conversation.estimated_tokens();
+        let messages = conversation.messages();
+        let summary = self
+            .summarize(plan.summarize.iter().map(|&i| &messages[i]))
+            .await?;
+
+        let len = messages.len();
+        let mut compacted = Vec::with_capacity(len - plan.summarize.len() + 1);
+        compacted.push(summary);
+        compacted.extend(
+            (0..len)
+                .filter(|index| *index >= plan.cutoff || plan.keep.contains(index))
+                .map(|index| conversation.branch_entry(index)),
+        );
+
+        conversation.replace_messages(alternate(compacted));
+        Ok(Some(Compaction {
+            summarized: plan.summarize.len(),
+            tokens_before,
+            tokens_after: conversation.estimated_tokens(),
+        }))
+    }
+
+    /// Write the summary message for older turns.
+    async fn summarize(&self, messages: impl Iterator<Item = &Message>) -> Result<BranchEntry> {
+        let mut transcript = String::from("Summarize this conversation:\n\n");
+        for message in messages {
+            render(&mut transcript, message);
+        }
+
+        let request = CompletionRequest {
+            model: self.model.clone(),
+            max_tokens: self.summary_max_tokens,
+            messages: vec![Message {
+                role: Role::User,
+                content: Content::Text(transcript),
+            }],
+            system: Some(SUMMARY_SYSTEM_PROMPT.to_string()),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let response = self.provider.complete(request).await?;
+        let text = response.message.content.text();
+        let summary = text.trim();
+        if summary.is_empty() {
+            return Err(AgentError::Parse(
+                "compaction summary was empty".to_string(),
+            ));
+        }
+
+        let (provider, model) = response.backend.map_or_else(
+            || (self.provider.name().to_string(), self.model.clone()),
+            |b| (b.provider, b.model),
+        );
+        Ok(BranchEntry {
+            id: None,
+            message: Message {
+                role: Role::User,
+                content: Content::Text(format!("{SUMMARY_PREFIX}{summary}")),
+            },
+            metadata: MessageMetadata::new(Provenance::Summary)
+                .with_model(provider, model)
+                .with_usage(response.usage)
+                .with_latency(started.elapsed()),
+            pinned: false,
+        })
+    }
+}
+
+/// Insert a synthetic message between neighbours with the same role.
+///
+/// Kept messages need not alternate once the summary replaces what was
+/// between them, and providers reject consecutive messages of one role.
+fn alternate(entries: Vec<BranchEntry>) -> Vec<BranchEntry> {
+    let mut alternating: Vec<BranchEntry> = Vec::with_capacity(entries.len() + 1);
+    for entry in entries {
+        if let Some(previous) = alternating.last()
+            && previous.message.role == entry.message.role
+        {
+            let (role, text) = match entry.message.role {
+                Role::User => (Role::Assistant, "Understood."),
+                Role::Assistant => (Role::User, "Continue."),
+            };
+            alternating.push(BranchEntry {
+                id: None,
+                message: Message {
+                    role,
+                    content: Content::Text(text.to_string()),
+                },
+                metadata: MessageMetadata::new(Provenance::Synthetic),
+                pinned: false,
+            });
+        }
+        alternating.push(entry);
+    }
+    alternating
+}
+
+/// Messages to summarize and keep.
+#[derive(Debug, PartialEq, Eq)]
+struct Plan {
+    /// Index of the first message kept because it is recent.
+    cutoff: usize,
+    /// Older messages kept because they are pinned or paired with one.
+    keep: BTreeSet<usize>,
+    /// Older messages replaced by the summary.
+    summarize: Vec<usize>,
+}
+
+/// Decide which messages a compaction replaces.
+///
+/// `ids` are the IDs of `messages`, used to find the pinned ones.
+fn plan(
+    messages: &[Message],
+    ids: &[MessageId],
+    pinned: &BTreeSet<MessageId>,
+    keep_turns: usize,
+) -> Option<Plan> {
+    let turn_starts: Vec<usize> = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| is_turn_start(m))
+        .map(|(i, _)| i)
+        .collect();
+    let mut cutoff = if keep_turns == 0 {
+        messages.len()
+    } else {
+        *turn_starts.get(turn_starts.len().checked_sub(keep_turns)?)?
+    };
+
+    // Move the cutoff back past any tool call whose result is recent
+    loop {
+        let recent: HashSet<&str> = messages[cutoff..].iter().flat_map(tool_ids).collect();
+        match (0..cutoff).find(|&i| tool_ids(&messages[i]).any(|id| recent.contains(id))) {
+            Some(index) => cutoff = index,
+            None => break,
+        }
+    }
+
+    // Keep the partners of pinned tool calls and results with them
+    let mut keep: BTreeSet<usize> = (0..cutoff).filter(|&i| pinned.contains(&ids[i])).collect();
+    loop {
+        let ids: HashSet<&str> = keep.iter().flat_map(|&i| tool_ids(&messages[i])).collect();
+        let partners: Vec<usize> = (0..cutoff)
+            .filter(|i| !keep.contains(i))
+            .filter(|&i| tool_ids(&messages[i]).any(|id| ids.contains(id)))
+            .collect();
+        if partners.is_empty() {
+            break;
+        }
+        keep.extend(partners);
+    }
+
+    let summarize: Vec<usize> = (0..cutoff).filter(|i| !keep.contains(i)).collect();
+    if summarize.is_empty() {
+        return None;
+    }
+    Some(Plan {
+        cutoff,
+        keep,
+        summarize,
+    })
+}
+
+/// Whether a message opens a turn: a user message that is not a tool result.
+fn is_turn_start(message: &Message) -> bool {
+    message.role == Role::User
+        && match &message.content {
+            Content::Text(_) => true,
+            Content::Blocks(blocks) => !blocks
+                .iter()
+                .any(|b| matches!(b, ContentBlock::ToolResult { .. })),
+        }
+}
+
+/// Tool call IDs a message uses or answers.
+fn tool_ids(message: &Message) -> impl Iterator<Item = &str> {
+    let blocks = match &message.content {
+        Content::Blocks(blocks) => blocks.as_slice(),
+        Content::Text(_) => &[],
+    };
+    blocks.iter().filter_map(|b| match b {
+        ContentBlock::ToolUse { id, .. } => Some(id.as_str()),
+        ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.as_str()),
+        _ => None,
+    })
+}
+
+/// Append a message to the summarizer transcript.
+fn render(out: &mut String, message: &Message) {
+    let speaker = match message.role {
+        Role::User => "User",
+        Role::Assistant => "Assistant",
+    };
+    let blocks = match &message.content {
+        Content::Text(text) => {
+            let _ = writeln!(out, "{speaker}: {text}\n");
+            return;
+        }
+        Content::Blocks(blocks) => blocks,
+    };
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text } => {
+                let _ = writeln!(out, "{speaker}: {text}");
+            }
+            ContentBlock::ToolUse { name, input, .. } => {
+                let _ = writeln!(out, "{speaker} called tool {name} with {input}");
+            }
+            ContentBlock::ToolResult {
+                content, is_error, ..
+            } => {
+                let label = if *is_error == Some(true) {
+                    "Tool error"
+                } else {
+                    "Tool result"
+                };
+                let text = content.text();
+                match text.char_indices().nth(MAX_TOOL_RESULT_CHARS) {
+                    Some((end, _)) => {
+                        let _ = writeln!(out, "{label}: {} [truncated]", &text[..end]);
+                    }
+                    None => {
+                        let _ = writeln!(out, "{label}: {text}");
+                    }
+                }
+            }
+            ContentBlock::Image { .. } => {
+                let _ = writeln!(out, "{speaker}: [image]");
+            }
+            ContentBlock::Document { title, .. } => {
+                let _ = writeln!(
+                    out,
+                    "{speaker}: [document {}]",
+                    title.as_deref().unwrap_or("untitled")
+                );
+            }
+            ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
+        }
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::MockProvider;
+
+    /// Three turns, the second with a tool call answered in the third.
+    fn conversation() -> Conversation {
+        let mut conv = Conversation::with_system("Be brief.");
+        conv.add_user_message("My name is Ada");
+        conv.add_assistant_message("Hello Ada");
+        conv.add_user_message("List the files");
+        conv.add_assistant_blocks(vec![ContentBlock::ToolUse {
+            id: "call_1".into(),
+            name: "ls".into(),
+            input: serde_json::json!({}),
+        }]);
+        conv.add_tool_result("call_1".into(), "a.txt".into(), false);
+        conv.add_assistant_message("One file");
+        conv.add_user_message("Thanks");
+        conv.add_assistant_message("Anytime");
+        conv
+    }
+
+    #[test]
+    fn plan_keeps_tool_pairs_together() {
+        let conv = conversation();
+        let messages = conv.messages();
+        let ids = conv.message_ids();
+
+        let recent = plan(messages, ids, &BTreeSet::new(), 2).unwrap();
+        assert_eq!(recent.cutoff, 2);
+        assert_eq!(recent.summarize, vec![0, 1]);
+
+        // Pinning the call keeps its result too
+        let pinned = plan(messages, ids, &BTreeSet::from([ids[3]]), 1).unwrap();
+        assert_eq!(pinned.cutoff, 6);
+        assert_eq!(pinned.keep, BTreeSet::from([3, 4]));
+        assert_eq!(pinned.summarize, vec![0, 1, 2, 5]);
+
+        // A result after the cutoff pulls its call back with it
+        let mut split = messages.to_vec();
+        let result = split.remove(4);
+        split.insert(6, result);
+        assert_eq!(plan(&split, ids, &BTreeSet::new(), 1).unwrap().cutoff, 3);
+
+        // Nothing to summarize when every older message is pinned
+        assert!(plan(messages, ids, &BTreeSet::from([ids[0], ids[1]]), 2).is_none());
+    }
+
+    #[tokio::test]
+    async fn compacts_older_turns_into_a_summary() {
+        let provider = Arc::new(MockProvider::new().with_text("User is Ada; listed a.txt."));
+        let compactor = Compactor::new(provider.clone(), "small")
+            .with_context_window(10)
+            .with_keep_turns(1);
+        let mut conv = conversation();
+        let before = conv.message_ids().to_vec();
+        conv.pin(before[0]);
+
+        let compaction = compactor
+            .compact_if_needed(&mut conv)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(compaction.summarized, 5);
+        assert!(compaction.tokens_after < compaction.tokens_before);
+        assert_eq!(conv.system(), Some("Be brief."));
+        let texts: Vec<String> = conv.messages().iter().map(|m| m.content.text()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                format!("{SUMMARY_PREFIX}User is Ada; listed a.txt."),
+                "Understood.".to_string(),
+                "My name is Ada".to_string(),
+                "Understood.".to_string(),
+                "Thanks".to_string(),
+                "Anytime".to_string(),
+            ]
+        );
+        assert!(conv.validate().is_empty());
+        assert_eq!(conv.pinned().collect::<Vec<_>>(), vec![before[0]]);
+
+        // Kept messages are moved, not copied
+        let ids = conv.message_ids().to_vec();
+        assert_eq!([ids[2], ids[4], ids[5]], [before[0], before[6], before[7]]);
+        assert_eq!(conv.nodes().len(), before.len() + 3);
+        let restored: Conversation =
+            serde_json::from_str(&serde_json::to_string(&conv).unwrap()).unwrap();
+        assert_eq!(restored.message_ids(), ids);
+        let summary = conv.metadata(conv.message_ids()[0]).unwrap();
+        assert_eq!(summary.source, Some(Provenance::Summary));
+        assert_eq!(summary.model.as_deref(), Some("small"));
+        let bridge = conv.metadata(conv.message_ids()[1]).unwrap();
+        assert_eq!(bridge.source, Some(Provenance::Synthetic));
+        let kept = conv.metadata(conv.message_ids()[2]).unwrap();
+        assert_eq!(kept.source, Some(Provenance::User));
+
+        let request = &provider.requests()[0];
+        let transcript = request.messages[0].content.text();
+        assert!(transcript.contains("Assistant called tool ls with {}"));
+        assert!(transcript.contains("Tool result: a.txt"));
+        assert!(!transcript.contains("My name is Ada"));
+    }
+
+    #[tokio::test]
+    async fn skips_conversations_below_the_threshold() {
+        let provider = Arc::new(MockProvider::new());
+        let compactor = Compactor::new(provider, "small");
+        let mut conv = conversation();
+
+        assert!(
+            compactor
+                .compact_if_needed(&mut conv)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(conv.messages().len(), 8);
+    }
+}
@@ -1,16 +1,334 @@
 //! Conversation state management.
+//!
+//! History is a tree: every message has a stable [`MessageId`] and a
+//! parent, so earlier prompts can be edited and retried and alternative
+//! answers explored side by side. The active branch, from a root to the
+//! head, is what [`Conversation::messages`] returns and what providers
+//! see.
 
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::path::Path;
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::types::{Content, ContentBlock, Message, Role};
+use crate::session::write_atomic;
+use crate::types::{Content, ContentBlock, Message, Role, Usage};
+use crate::usage::estimate_tokens;
+
+/// Result text synthesized for tool calls that never completed.
+const INTERRUPTED_TOOL_RESULT: &str = "tool call did not complete";
+
+/// A problem that makes providers reject a conversation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversationIssue {
+    /// A tool call has no result in the following user message.
+    OrphanedToolUse { index: usize, id: String },
+    /// A tool result answers no call in the preceding assistant message.
+    OrphanedToolResult { index: usize, id: String },
+    /// A tool call or result repeats an ID already used.
+    DuplicateToolId { index: usize, id: String },
+    /// A message has the same role as the one before it.
+    ConsecutiveRole { index: usize, role: Role },
+    /// A message has no non-empty content.
+    EmptyContent { index: usize },
+}
+
+impl fmt::Display for ConversationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OrphanedToolUse { index, id } => {
+                write!(f, "message {index}: tool call '{id}' has no result")
+            }
+            Self::OrphanedToolResult { index, id } => {
+                write!(f, "message {index}: tool result '{id}' answers no call")
+            }
+            Self::DuplicateToolId { index, id } => {
+                write!(f, "message {index}: tool ID '{id}' is used more than once")
+            }
+            Self::ConsecutiveRole { index, role } => {
+                write!(f, "message {index}: second consecutive {role:?} message")
+            }
+            Self::EmptyContent { index } => write!(f, "message {index}: empty content"),
+        }
+    }
+}
+
+/// Stable identifier of a message in a conversation tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageId(u64);
+
+impl MessageId {
+    /// Raw value of the ID that follows this one.
+    pub(crate) const fn next(self) -> u64 {
+        self.0 + 1
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Where a message came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provenance {
+    /// Written by the user.
+    User,
+    /// Generated by a model.
+    Model,
+    /// Output of a tool call.
+    Tool,
+    /// Injected from a knowledge base.
+    Knowledge,
+    /// Injected from long-term memory.
+    Memory,
+    /// Summary of earlier turns written by compaction.
+    Summary,
+    /// Synthesized by the library, such as results added by repair.
+    Synthetic,
+}
+
+impl Provenance {
+    /// Whether the message was injected rather than exchanged.
+    #[must_use]
+    pub const fn is_injected(self) -> bool {
+        !matches!(self, Self::User | Self::Model | Self::Tool)
+    }
+}
+
+/// Details recorded alongside a message.
+///
+/// Metadata is saved with the conversation but never sent to providers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageMetadata {
+    /// When the message was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Where the message came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Provenance>,
+    /// Provider that generated the message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Model that generated the message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Token usage of the completion that generated the message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Completion time in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl MessageMetadata {
+    /// Create metadata stamped with the current time.
+    #[must_use]
+    pub fn new(source: Provenance) -> Self {
+        Self {
+            created_at: Some(Utc::now()),
+            source: Some(source),
+            ..Self::default()
+        }
+    }
+
+    /// Set the provider and model that generated the message.
+    #[must_use]
+    pub fn with_model(mut self, provider: impl Into<String>, model: impl Into<String>) -> Self {
+        self.provider = Some(provider.into());
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Set the token usage.
+    #[must_use]
+    pub const fn with_usage(mut self, usage: Option<Usage>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Set the completion time.
+    #[must_use]
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = Some(u64::try_from(latency.as_millis()).unwrap_or(u64::MAX));
+        self
+    }
+
+    /// Get the completion time.
+    #[must_use]
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency_ms.map(Duration::from_millis)
+    }
+
+    /// Whether nothing is recorded.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A message and its place in the tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Node {
+    pub id: MessageId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<MessageId>,
+    #[serde(flatten)]
+    pub message: Message,
+    #[serde(default, skip_serializing_if = "MessageMetadata::is_empty")]
+    pub metadata: MessageMetadata,
+}
+
+/// Changes not yet written to a session log.
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    /// Messages added or modified.
+    pub upserted: BTreeSet<MessageId>,
+    /// Messages deleted.
+    pub removed: BTreeSet<MessageId>,
+    pub head: bool,
+    pub pinned: bool,
+    pub system: bool,
+}
+
+impl Journal {
+    fn remove(&mut self, id: MessageId) {
+        self.upserted.remove(&id);
+        self.removed.insert(id);
+    }
+}
+
+/// A message written onto a new branch by compaction or repair.
+pub(crate) struct BranchEntry {
+    /// Message the entry was copied from, so it can keep its ID.
+    pub id: Option<MessageId>,
+    pub message: Message,
+    pub metadata: MessageMetadata,
+    pub pinned: bool,
+}
 
 /// Manages multi-turn conversation state.
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(try_from = "StoredConversation")]
 pub struct Conversation {
+    system: Option<String>,
+    /// Every message ever added, ordered by ID.
+    nodes: Vec<Node>,
+    /// Last message of the active branch.
+    head: Option<MessageId>,
+    next_id: u64,
+    /// Messages that compaction keeps verbatim.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pinned: BTreeSet<MessageId>,
+    /// IDs along the active branch, from the root to the head.
+    #[serde(skip)]
+    path: Vec<MessageId>,
+    /// Messages along the active branch.
+    #[serde(skip)]
     messages: Vec<Message>,
+    #[serde(skip)]
+    journal: Journal,
+}
+
+/// On-disk form, also accepting the flat message list of older saves.
+#[derive(Deserialize)]
+pub(crate) struct StoredConversation {
+    #[serde(default)]
     system: Option<String>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    head: Option<MessageId>,
+    #[serde(default)]
+    next_id: u64,
+    #[serde(default)]
+    pinned: BTreeSet<MessageId>,
+    #[serde(default)]
+    messages: Vec<Message>,
+}
+
+impl StoredConversation {
+    /// Parts of a conversation replayed from a session log.
+    pub(crate) const fn from_parts(
+        system: Option<String>,
+        nodes: Vec<Node>,
+        head: Option<MessageId>,
+        next_id: u64,
+        pinned: BTreeSet<MessageId>,
+    ) -> Self {
+        Self {
+            system,
+            nodes,
+            head,
+            next_id,
+            pinned,
+            messages: Vec::new(),
+        }
+    }
+}
+
+impl TryFrom<StoredConversation> for Conversation {
+    type Error = String;
+
+    fn try_from(stored: StoredConversation) -> Result<Self, Self::Error> {
+        let mut conversation = Self {
+            system: stored.system,
+            nodes: stored.nodes,
+            head: stored.head,
+            next_id: stored.next_id,
+            pinned: stored.pinned,
+            ..Self::default()
+        };
+        if conversation.nodes.is_empty() {
+            // Flat history: message IDs, and pins, are list positions
+            for message in stored.messages {
+                conversation.push(message, MessageMetadata::default());
+            }
+            return Ok(conversation);
+        }
+
+        conversation.nodes.sort_by_key(|node| node.id);
+        for pair in conversation.nodes.windows(2) {
+            if pair[0].id == pair[1].id {
+                return Err(format!("duplicate message ID {}", pair[0].id));
+            }
+        }
+        // Moved messages can have older IDs than their parents, so check
+        // that every chain of parents ends at a root
+        let mut rooted = HashSet::new();
+        for node in &conversation.nodes {
+            let mut chain = Vec::new();
+            let mut cursor = node;
+            while !rooted.contains(&cursor.id) {
+                if chain.contains(&cursor.id) {
+                    return Err(format!("message {} is its own ancestor", cursor.id));
+                }
+                chain.push(cursor.id);
+                let Some(parent) = cursor.parent else {
+                    break;
+                };
+                cursor = conversation
+                    .node(parent)
+                    .ok_or_else(|| format!("message {} has invalid parent {parent}", cursor.id))?;
+            }
+            rooted.extend(chain);
+        }
+        if let Some(head) = conversation.head
+            && conversation.node(head).is_none()
+        {
+            return Err(format!("head message {head} does not exist"));
+        }
+        let last = conversation.nodes.last().map_or(0, |node| node.id.0 + 1);
+        conversation.next_id = conversation.next_id.max(last);
+        conversation.sync_path();
+        Ok(conversation)
+    }
 }
 
 impl Conversation {
@@ -24,8 +342,8 @@ impl Conversation {
     #[must_use]
     pub fn with_system(system: impl Into<String>) -> Self {
         Self {
-            messages: Vec::new(),
             system: Some(system.into()),
+            ..Self::default()
         }
     }
 
@@ -38,6 +356,7 @@ impl Conversation {
     /// Set or replace the system prompt.
     pub fn set_system(&mut self, system: impl Into<String>) {
         self.system = Some(system.into());
+        self.journal.system = true;
     }
 
     /// Get all messages.
@@ -46,64 +365,536 @@ impl Conversation {
         &self.messages
     }
 
+    /// Get the IDs of the messages on the active branch.
+    #[must_use]
+    pub fn message_ids(&self) -> &[MessageId] {
+        &self.path
+    }
+
+    /// Get the last message ID on the active branch.
+    #[must_use]
+    pub const fn head(&self) -> Option<MessageId> {
+        self.head
+    }
+
+    /// Get any message in the tree by ID.
+    #[must_use]
+    pub fn message(&self, id: MessageId) -> Option<&Message> {
+        self.node(id).map(|node| &node.message)
+    }
+
+    /// Get the metadata of any message in the tree by ID.
+    #[must_use]
+    pub fn metadata(&self, id: MessageId) -> Option<&MessageMetadata> {
+        self.node(id).map(|node| &node.metadata)
+    }
+
+    /// Get the metadata of a message for updating.
+    pub fn metadata_mut(&mut self, id: MessageId) -> Option<&mut MessageMetadata> {
+        let node = self.nodes.binary_search_by_key(&id, |node| node.id).ok()?;
+        self.journal.upserted.insert(id);
+        Some(&mut self.nodes[node].metadata)
+    }
+
+    /// Get the parent of a message, or `None` for a root or unknown ID.
+    #[must_use]
+    pub fn parent(&self, id: MessageId) -> Option<MessageId> {
+        self.node(id).and_then(|node| node.parent)
+    }
+
+    /// Get the messages that continue from a message, oldest first.
+    #[must_use]
+    pub fn children(&self, id: MessageId) -> Vec<MessageId> {
+        self.children_of(Some(id))
+    }
+
+    /// Get the alternatives to a message, including itself, oldest first.
+    ///
+    /// Returns an empty list for an unknown ID.
+    #[must_use]
+    pub fn siblings(&self, id: MessageId) -> Vec<MessageId> {
+        self.node(id)
+            .map(|node| self.children_of(node.parent))
+            .unwrap_or_default()
+    }
+
+    /// Add a message with its metadata.
+    ///
+    /// Use this for injected content, such as knowledge or memory, so its
+    /// provenance is recorded.
+    pub fn add_message(&mut self, message: Message, metadata: MessageMetadata) -> MessageId {
+        self.push(message, metadata)
+    }
+
     /// Add a user message.
-    pub fn add_user_message(&mut self, text: impl Into<String>) {
-        self.messages.push(Message {
-            role: Role::User,
-            content: Content::Text(text.into()),
-        });
+    pub fn add_user_message(&mut self, text: impl Into<String>) -> MessageId {
+        self.push(
+            Message {
+                role: Role::User,
+                content: Content::Text(text.into()),
+            },
+            MessageMetadata::new(Provenance::User),
+        )
     }
 
     /// Add an assistant message.
-    pub fn add_assistant_message(&mut self, text: impl Into<String>) {
-        self.messages.push(Message {
-            role: Role::Assistant,
-            content: Content::Text(text.into()),
-        });
+    pub fn add_assistant_message(&mut self, text: impl Into<String>) -> MessageId {
+        self.push(
+            Message {
+                role: Role::Assistant,
+                content: Content::Text(text.into()),
+            },
+            MessageMetadata::new(Provenance::Model),
+        )
     }
 
     /// Add an assistant message with content blocks (for tool use).
-    pub fn add_assistant_blocks(&mut self, blocks: Vec<ContentBlock>) {
-        self.messages.push(Message {
-            role: Role::Assistant,
-            content: Content::Blocks(blocks),
-        });
+    pub fn add_assistant_blocks(&mut self, blocks: Vec<ContentBlock>) -> MessageId {
+        self.push(
+            Message {
+                role: Role::Assistant,
+                content: Content::Blocks(blocks),
+            },
+            MessageMetadata::new(Provenance::Model),
+        )
     }
 
     /// Add a tool result.
-    pub fn add_tool_result(&mut self, tool_use_id: String, content: String, is_error: bool) {
+    ///
+    /// Returns the ID of the user message that holds the result.
+    pub fn add_tool_result(
+        &mut self,
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+    ) -> MessageId {
+        self.add_tool_result_content(tool_use_id, Content::Text(content), is_error)
+    }
+
+    /// Add a tool result with structured content (e.g. text and images).
+    ///
+    /// Returns the ID of the user message that holds the result.
+    pub fn add_tool_result_content(
+        &mut self,
+        tool_use_id: String,
+        content: Content,
+        is_error: bool,
+    ) -> MessageId {
         let block = ContentBlock::ToolResult {
             tool_use_id,
             content,
             is_error: if is_error { Some(true) } else { None },
         };
 
+        // Results of one batch of calls share a user message
+        if let Some(head) = self.head
+            && let Some(Message {
+                role: Role::User,
+                content: Content::Blocks(blocks),
+            }) = self.messages.last_mut()
+            && blocks
+                .iter()
+                .all(|b| matches!(b, ContentBlock::ToolResult { .. }))
+        {
+            blocks.push(block);
+            let message = self.messages[self.messages.len() - 1].clone();
+            if let Some(node) = self.node_mut(head) {
+                node.message = message;
+            }
+            self.journal.upserted.insert(head);
+            return head;
+        }
+
         // Tool results go in user messages
-        self.messages.push(Message {
-            role: Role::User,
-            content: Content::Blocks(vec![block]),
-        });
+        self.push(
+            Message {
+                role: Role::User,
+                content: Content::Blocks(vec![block]),
+            },
+            MessageMetadata::new(Provenance::Tool),
+        )
     }
 
-    /// Clear all messages.
+    /// Clear all messages, including other branches.
     pub fn clear(&mut self) {
-        self.messages.clear();
+        for node in std::mem::take(&mut self.nodes) {
+            self.journal.remove(node.id);
+        }
+        self.head = None;
+        self.pinned.clear();
+        self.journal.pinned = true;
+        self.sync_path();
+    }
+
+    /// Start a new branch beside a message.
+    ///
+    /// The active branch ends at the message's parent, so the next message
+    /// added becomes its sibling. The message and everything after it are
+    /// kept. Returns `false` for an unknown ID.
+    pub fn fork_at(&mut self, id: MessageId) -> bool {
+        let Some(node) = self.node(id) else {
+            return false;
+        };
+        self.head = node.parent;
+        self.sync_path();
+        true
+    }
+
+    /// Rewind the active branch to end at a message.
+    ///
+    /// Messages that followed it on the active branch are deleted along
+    /// with any branches below them; other branches are kept. Returns
+    /// `false` if the message is not on the active branch.
+    pub fn rewind_to(&mut self, id: MessageId) -> bool {
+        let Some(position) = self.path.iter().position(|&p| p == id) else {
+            return false;
+        };
+        if let Some(&next) = self.path.get(position + 1) {
+            let mut doomed = HashSet::from([next]);
+            let mut pending = vec![next];
+            while let Some(parent) = pending.pop() {
+                for node in &self.nodes {
+                    if node.parent == Some(parent) && doomed.insert(node.id) {
+                        pending.push(node.id);
+                    }
+                }
+            }
+            self.nodes.retain(|node| !doomed.contains(&node.id));
+            self.pinned.retain(|id| !doomed.contains(id));
+            for id in doomed {
+                self.journal.remove(id);
+            }
+            self.journal.pinned = true;
+        }
+        self.head = Some(id);
+        self.sync_path();
+        true
+    }
+
+    /// Make the branch through a message active.
+    ///
+    /// The branch continues past the message along the newest child at
+    /// each step. Returns `false` for an unknown ID.
+    pub fn switch_to(&mut self, id: MessageId) -> bool {
+        if self.node(id).is_none() {
+            return false;
+        }
+        let mut head = id;
+        while let Some(&child) = self.children(head).last() {
+            head = child;
+        }
+        self.head = Some(head);
+        self.sync_path();
+        true
+    }
+
+    /// Pin a message so compaction keeps it verbatim.
+    ///
+    /// Returns `false` if there is no message with this ID.
+    pub fn pin(&mut self, id: MessageId) -> bool {
+        if self.node(id).is_none() {
+            return false;
+        }
+        self.pinned.insert(id);
+        self.journal.pinned = true;
+        true
+    }
+
+    /// Unpin a message.
+    pub fn unpin(&mut self, id: MessageId) {
+        if self.pinned.remove(&id) {
+            self.journal.pinned = true;
+        }
+    }
+
+    /// Check whether a message is pinned.
+    #[must_use]
+    pub fn is_pinned(&self, id: MessageId) -> bool {
+        self.pinned.contains(&id)
+    }
+
+    /// Get the IDs of pinned messages on the active branch in order.
+    pub fn pinned(&self) -> impl Iterator<Item = MessageId> + '_ {
+        self.path
+            .iter()
+            .copied()
+            .filter(|id| self.pinned.contains(id))
+    }
+
+    /// Estimate the prompt tokens of the system prompt and messages.
+    ///
+    /// Counts the serialized form, so tool inputs and structural overhead
+    /// are included. Tool definitions are not part of the conversation
+    /// and must be budgeted separately.
+    #[must_use]
+    pub fn estimated_tokens(&self) -> u32 {
+        let system = self.system.as_deref().map_or(0, estimate_tokens);
+        let messages = serde_json::to_string(&self.messages).unwrap_or_default();
+        system.saturating_add(estimate_tokens(&messages))
+    }
+
+    /// Get the entry for a message on the active branch, to rewrite it.
+    pub(crate) fn branch_entry(&self, index: usize) -> BranchEntry {
+        let id = self.path[index];
+        BranchEntry {
+            id: Some(id),
+            message: self.messages[index].clone(),
+            metadata: self.metadata(id).cloned().unwrap_or_default(),
+            pinned: self.pinned.contains(&id),
+        }
+    }
+
+    /// Replace the active branch, as compaction and repair do.
+    ///
+    /// Entries copied unchanged from existing messages keep their IDs,
+    /// metadata and pins. The branch is shared up to the first changed
+    /// entry; from there changed entries become new messages beside the
+    /// ones they replace and unchanged ones are moved after them, so the
+    /// replaced history stays reachable through
+    /// [`siblings`](Self::siblings) without being copied.
+    pub(crate) fn replace_messages(&mut self, entries: Vec<BranchEntry>) {
+        let mut placed = HashSet::new();
+        let mut head = None;
+        let mut diverged = false;
+        for (index, entry) in entries.into_iter().enumerate() {
+            let unchanged = entry
+                .id
+                .filter(|id| !placed.contains(id))
+                .filter(|&id| self.message(id) == Some(&entry.message));
+            let id = if let Some(id) = unchanged {
+                diverged |= self.path.get(index) != Some(&id);
+                if diverged
+                    && let Some(node) = self.node_mut(id)
+                    && node.parent != head
+                {
+                    node.parent = head;
+                    self.journal.upserted.insert(id);
+                }
+                id
+            } else {
+                diverged = true;
+                let id = self.insert(head, entry.message, entry.metadata);
+                if entry.pinned {
+                    self.pinned.insert(id);
+                    self.journal.pinned = true;
+                }
+                id
+            };
+            placed.insert(id);
+            head = Some(id);
+        }
+        self.head = head;
+        self.sync_path();
+    }
+
+    /// Take the changes made since the last call.
+    pub(crate) fn take_journal(&mut self) -> Journal {
+        std::mem::take(&mut self.journal)
+    }
+
+    /// Get the pinned messages on every branch.
+    pub(crate) const fn pinned_ids(&self) -> &BTreeSet<MessageId> {
+        &self.pinned
+    }
+
+    /// Get every message in the tree, ordered by ID.
+    pub(crate) fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Append a message to the active branch.
+    fn push(&mut self, message: Message, metadata: MessageMetadata) -> MessageId {
+        let id = self.insert(self.head, message.clone(), metadata);
+        self.path.push(id);
+        self.messages.push(message);
+        self.head = Some(id);
+        self.journal.head = true;
+        id
+    }
+
+    /// Add a message below `parent` with the next ID.
+    fn insert(
+        &mut self,
+        parent: Option<MessageId>,
+        message: Message,
+        metadata: MessageMetadata,
+    ) -> MessageId {
+        let id = MessageId(self.next_id);
+        self.next_id += 1;
+        self.nodes.push(Node {
+            id,
+            parent,
+            message,
+            metadata,
+        });
+        self.journal.upserted.insert(id);
+        id
+    }
+
+    pub(crate) fn node(&self, id: MessageId) -> Option<&Node> {
+        self.nodes
+            .binary_search_by_key(&id, |node| node.id)
+            .ok()
+            .map(|index| &self.nodes[index])
+    }
+
+    fn node_mut(&mut self, id: MessageId) -> Option<&mut Node> {
+        self.nodes
+            .binary_search_by_key(&id, |node| node.id)
+            .ok()
+            .map(|index| &mut self.nodes[index])
+    }
+
+    fn children_of(&self, parent: Option<MessageId>) -> Vec<MessageId> {
+        self.nodes
+            .iter()
+            .filter(|node| node.parent == parent)
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// Rebuild the active branch from the head.
+    fn sync_path(&mut self) {
+        let mut path = Vec::new();
+        let mut cursor = self.head;
+        while let Some(node) = cursor.and_then(|id| self.node(id)) {
+            path.push(node.id);
+            cursor = node.parent;
+        }
+        path.reverse();
+        self.messages = path
+            .iter()
+            .filter_map(|&id| self.message(id).cloned())
+            .collect();
+        self.path = path;
+        self.journal.head = true;
+    }
+
+    /// Check the conversation for problems providers reject.
+    ///
+    /// Tool calls must be answered by results in the next message, tool
+    /// IDs must be unique, roles must alternate and every message needs
+    /// content. Returns the problems found in message order.
+    #[must_use]
+    pub fn validate(&self) -> Vec<ConversationIssue> {
+        let mut issues = Vec::new();
+        let mut seen_uses = HashSet::new();
+        let mut seen_results = HashSet::new();
+
+        for (index, message) in self.messages.iter().enumerate() {
+            if is_empty_content(&message.content) {
+                issues.push(ConversationIssue::EmptyContent { index });
+            }
+            if index > 0 && self.messages[index - 1].role == message.role {
+                issues.push(ConversationIssue::ConsecutiveRole {
+                    index,
+                    role: message.role,
+                });
+            }
+
+            for id in tool_use_ids(message) {
+                if !seen_uses.insert(id) {
+                    issues.push(ConversationIssue::DuplicateToolId {
+                        index,
+                        id: id.to_string(),
+                    });
+                }
+                let answered = self
+                    .messages
+                    .get(index + 1)
+                    .is_some_and(|next| tool_result_ids(next).any(|r| r == id));
+                if !answered {
+                    issues.push(ConversationIssue::OrphanedToolUse {
+                        index,
+                        id: id.to_string(),
+                    });
+                }
+            }
+
+            for id in tool_result_ids(message) {
+                if !seen_results.insert(id) {
+                    issues.push(ConversationIssue::DuplicateToolId {
+                        index,
+                        id: id.to_string(),
+                    });
+                }
+                let called = index
+                    .checked_sub(1)
+                    .is_some_and(|prev| tool_use_ids(&self.messages[prev]).any(|u| u == id));
+                if !called {
+                    issues.push(ConversationIssue::OrphanedToolResult {
+                        index,
+                        id: id.to_string(),
+                    });
+                }
+            }
+        }
+
+        issues
+    }
+
+    /// Fix the problems [`validate`](Self::validate) reports.
+    ///
+    /// Empty text is dropped, adjacent messages of the same role are
+    /// merged with tool results first, duplicate tool calls get fresh IDs
+    /// along with their results, stray results become text and calls
+    /// without results get error results. Pins follow their messages.
+    /// Returns the problems found before repair.
+    pub fn repair(&mut self) -> Vec<ConversationIssue> {
+        let issues = self.validate();
+        if issues.is_empty() {
+            return issues;
+        }
+
+        let mut entries: Vec<BranchEntry> = Vec::with_capacity(self.messages.len());
+        for index in 0..self.messages.len() {
+            let mut entry = self.branch_entry(index);
+            if let Content::Blocks(blocks) = &mut entry.message.content {
+                blocks.retain(
+                    |b| !matches!(b, ContentBlock::Text { text } if text.trim().is_empty()),
+                );
+            }
+            if is_empty_content(&entry.message.content) {
+                continue;
+            }
+            match entries.last_mut() {
+                Some(last) if last.message.role == entry.message.role => {
+                    merge_into(&mut last.message, entry.message);
+                    last.pinned |= entry.pinned;
+                }
+                _ => entries.push(entry),
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut index = 0;
+        while index < entries.len() {
+            if entries[index].message.role == Role::Assistant {
+                pair_results(&mut entries, index, &mut seen);
+            } else if index == 0 || entries[index - 1].message.role != Role::Assistant {
+                // Results with no preceding call at all
+                strand_results(&mut entries[index].message, &HashSet::new());
+            }
+            index += 1;
+        }
+
+        self.replace_messages(entries);
+        issues
     }
 
     /// Save conversation to a file.
     ///
+    /// The file is replaced atomically, so a crash leaves either the old
+    /// or the new contents. Every save rewrites the whole history; use a
+    /// [`SessionLog`](crate::session::SessionLog) to persist each turn
+    /// incrementally.
+    ///
     /// # Errors
     ///
     /// Returns an error if the file cannot be written.
     pub fn save(&self, path: &Path) -> anyhow::Result<()> {
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
         let json = serde_json::to_string_pretty(self)?;
-        std::fs::write(path, json)?;
-        Ok(())
+        write_atomic(path, json.as_bytes())
     }
 
     /// Load conversation from a file.
@@ -130,6 +921,186 @@ impl Conversation {
     }
 }
 
+/// Whether a message has nothing to send.
+fn is_empty_content(content: &Content) -> bool {
+    match content {
+        Content::Text(text) => text.trim().is_empty(),
+        Content::Blocks(blocks) => blocks
+            .iter()
+            .all(|b| matches!(b, ContentBlock::Text { text } if text.trim().is_empty())),
+    }
+}
+
+fn tool_use_ids(message: &Message) -> impl Iterator<Item = &str> {
+    blocks(&message.content).iter().filter_map(|b| match b {
+        ContentBlock::ToolUse { id, .. } => Some(id.as_str()),
+        _ => None,
+    })
+}
+
+fn tool_result_ids(message: &Message) -> impl Iterator<Item = &str> {
+    blocks(&message.content).iter().filter_map(|b| match b {
+        ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.as_str()),
+        _ => None,
+    })
+}
+
+fn blocks(content: &Content) -> &[ContentBlock] {
+    match content {
+        Content::Blocks(blocks) => blocks,
+        Content::Text(_) => &[],
+    }
+}
+
+/// Get a message's content as blocks, converting plain text.
+fn blocks_mut(content: &mut Content) -> &mut Vec<ContentBlock> {
+    if let Content::Text(text) = content {
+        *content = Content::Blocks(vec![ContentBlock::Text {
+            text: std::mem::take(text),
+        }]);
+    }
+    match content {
+        Content::Blocks(blocks) => blocks,
+        Content::Text(_) => unreachable!("converted to blocks above"),
+    }
+}
+
+/// Append a message to one of the same role, keeping tool results first.
+fn merge_into(target: &mut Message, message: Message) {
+    let incoming = match message.content {
+        Content::Text(text) => vec![ContentBlock::Text { text }],
+        Content::Blocks(blocks) => blocks,
+    };
+    let blocks = blocks_mut(&mut target.content);
+    blocks.extend(incoming);
+    // Stable, so results and other content each keep their order
+    blocks.sort_by_key(|b| !matches!(b, ContentBlock::ToolResult { .. }));
+}
+
+/// Match the results after an assistant message to its tool calls.
+///
+/// Duplicate call IDs are renamed along with the result that answers
+/// them, and calls left unanswered get error results.
+fn pair_results(entries: &mut Vec<BranchEntry>, index: usize, seen: &mut HashSet<String>) {
+    let has_results = entries
+        .get(index + 1)
+        .is_some_and(|next| next.message.role == Role::User);
+    let mut renames: Vec<(String, String)> = Vec::new();
+    let mut calls = Vec::new();
+
+    if let Content::Blocks(blocks) = &mut entries[index].message.content {
+        for block in blocks.iter_mut() {
+            let ContentBlock::ToolUse { id, .. } = block else {
+                continue;
+            };
+            if !seen.insert(id.clone()) {
+                let mut n = 2;
+                let fresh = loop {
+                    let candidate = format!("{id}_{n}");
+                    if !seen.contains(&candidate) {
+                        break candidate;
+                    }
+                    n += 1;
+                };
+                seen.insert(fresh.clone());
+                renames.push((id.clone(), fresh.clone()));
+                *id = fresh;
+            }
+            calls.push(id.clone());
+        }
+    }
+    if calls.is_empty() {
+        if has_results {
+            strand_results(&mut entries[index + 1].message, &HashSet::new());
+        }
+        return;
+    }
+
+    if !has_results {
+        entries.insert(
+            index + 1,
+            BranchEntry {
+                id: None,
+                message: Message {
+                    role: Role::User,
+                    content: Content::Blocks(Vec::new()),
+                },
+                metadata: MessageMetadata::new(Provenance::Synthetic),
+                pinned: false,
+            },
+        );
+    }
+    let results = blocks_mut(&mut entries[index + 1].message.content);
+
+    // Each call claims the next unclaimed result with its original ID,
+    // so renamed duplicates take over the results that follow the first
+    let mut claims: HashMap<&str, usize> = HashMap::new();
+    for call in &calls {
+        let original = renames
+            .iter()
+            .find(|(_, fresh)| fresh == call)
+            .map_or(call.as_str(), |(old, _)| old.as_str());
+        let skip = claims.entry(original).or_insert(0);
+        if let Some(ContentBlock::ToolResult { tool_use_id, .. }) = results
+            .iter_mut()
+            .filter(|b| matches!(b, ContentBlock::ToolResult { tool_use_id, .. } if tool_use_id == original))
+            .nth(*skip)
+        {
+            tool_use_id.clone_from(call);
+        }
+        *skip += 1;
+    }
+
+    let answered: HashSet<&str> = calls.iter().map(String::as_str).collect();
+    strand_results(&mut entries[index + 1].message, &answered);
+
+    let results = blocks_mut(&mut entries[index + 1].message.content);
+    let present: HashSet<String> = results
+        .iter()
+        .filter_map(|b| match b {
+            ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+            _ => None,
+        })
+        .collect();
+    let missing = calls
+        .iter()
+        .filter(|call| !present.contains(*call))
+        .map(|call| ContentBlock::ToolResult {
+            tool_use_id: call.clone(),
+            content: Content::Text(INTERRUPTED_TOOL_RESULT.to_string()),
+            is_error: Some(true),
+        });
+    let position = results
+        .iter()
+        .take_while(|b| matches!(b, ContentBlock::ToolResult { .. }))
+        .count();
+    results.splice(position..position, missing);
+}
+
+/// Turn tool results that answer none of `calls`, or repeat an answer,
+/// into text so their output is kept.
+fn strand_results(message: &mut Message, calls: &HashSet<&str>) {
+    if tool_result_ids(message).next().is_none() {
+        return;
+    }
+    let mut answered = HashSet::new();
+    let blocks = blocks_mut(&mut message.content);
+    for block in blocks.iter_mut() {
+        if let ContentBlock::ToolResult {
+            tool_use_id,
+            content,
+            ..
+        } = block
+            && (!calls.contains(tool_use_id.as_str()) || !answered.insert(tool_use_id.clone()))
+        {
+            *block = ContentBlock::Text {
+                text: format!("Result of tool call {tool_use_id}: {}", content.text()),
+            };
+        }
+    }
+    blocks.sort_by_key(|b| !matches!(b, ContentBlock::ToolResult { .. }));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,4 +1125,242 @@ mod tests {
 
         assert!(conv.messages().is_empty());
     }
+
+    fn tool_use(id: &str) -> ContentBlock {
+        ContentBlock::ToolUse {
+            id: id.into(),
+            name: "ls".into(),
+            input: serde_json::json!({}),
+        }
+    }
+
+    fn tool_result(id: &str) -> ContentBlock {
+        ContentBlock::ToolResult {
+            tool_use_id: id.into(),
+            content: Content::Text(format!("{id} output")),
+            is_error: None,
+        }
+    }
+
+    fn message(role: Role, blocks: Vec<ContentBlock>) -> Message {
+        Message {
+            role,
+            content: Content::Blocks(blocks),
+        }
+    }
+
+    #[test]
+    fn tool_results_share_a_message() {
+        let mut conv = Conversation::new();
+        conv.add_user_message("list twice");
+        conv.add_assistant_blocks(vec![tool_use("a"), tool_use("b")]);
+        conv.add_tool_result("a".into(), "one".into(), false);
+        conv.add_tool_result("b".into(), "two".into(), true);
+
+        assert_eq!(conv.messages().len(), 3);
+        assert!(conv.validate().is_empty());
+    }
+
+    #[test]
+    fn validate_and_repair_broken_history() {
+        let mut conv = Conversation::new();
+        conv.add_user_message("hi");
+        for message in [
+            message(Role::Assistant, vec![tool_use("a"), tool_use("b")]),
+            message(Role::User, vec![tool_result("a")]),
+            message(Role::User, vec![tool_result("x")]),
+            message(
+                Role::Assistant,
+                vec![ContentBlock::Text { text: " ".into() }],
+            ),
+            message(Role::Assistant, vec![tool_use("a")]),
+        ] {
+            conv.push(message, MessageMetadata::default());
+        }
+        conv.pin(conv.message_ids()[3]);
+
+        let issues = vec![
+            ConversationIssue::OrphanedToolUse {
+                index: 1,
+                id: "b".into(),
+            },
+            ConversationIssue::ConsecutiveRole {
+                index: 3,
+                role: Role::User,
+            },
+            ConversationIssue::OrphanedToolResult {
+                index: 3,
+                id: "x".into(),
+            },
+            ConversationIssue::EmptyContent { index: 4 },
+            ConversationIssue::ConsecutiveRole {
+                index: 5,
+                role: Role::Assistant,
+            },
+            ConversationIssue::DuplicateToolId {
+                index: 5,
+                id: "a".into(),
+            },
+            ConversationIssue::OrphanedToolUse {
+                index: 5,
+                id: "a".into(),
+            },
+        ];
+        assert_eq!(conv.validate(), issues);
+        assert_eq!(
+            issues[0].to_string(),
+            "message 1: tool call 'b' has no result"
+        );
+
+        let ids = conv.message_ids().to_vec();
+        assert_eq!(conv.repair(), issues);
+        assert!(conv.validate().is_empty());
+        // Messages before the first repair keep their IDs
+        assert_eq!(conv.message_ids()[..2], ids[..2]);
+        assert_ne!(conv.message_ids()[2], ids[2]);
+        assert!(conv.repair().is_empty());
+
+        let messages = conv.messages();
+        assert_eq!(messages.len(), 5);
+        let Content::Blocks(results) = &messages[2].content else {
+            panic!("expected blocks");
+        };
+        assert!(
+            matches!(&results[0], ContentBlock::ToolResult { tool_use_id, .. } if tool_use_id == "a")
+        );
+        assert!(matches!(
+            &results[1],
+            ContentBlock::ToolResult { tool_use_id, is_error: Some(true), .. } if tool_use_id == "b"
+        ));
+        assert_eq!(
+            messages[2].content.text(),
+            "Result of tool call x: x output"
+        );
+        assert!(conv.is_pinned(conv.message_ids()[2]));
+        assert_eq!(tool_use_ids(&messages[3]).collect::<Vec<_>>(), ["a_2"]);
+        assert_eq!(tool_result_ids(&messages[4]).collect::<Vec<_>>(), ["a_2"]);
+    }
+
+    fn texts(conv: &Conversation) -> Vec<String> {
+        conv.messages().iter().map(|m| m.content.text()).collect()
+    }
+
+    #[test]
+    fn forks_switches_and_rewinds_branches() {
+        let mut conv = Conversation::new();
+        let question = conv.add_user_message("q");
+        let first = conv.add_assistant_message("a1");
+
+        assert!(conv.fork_at(first));
+        assert_eq!(conv.head(), Some(question));
+        let second = conv.add_assistant_message("a2");
+        assert_eq!(conv.siblings(first), vec![first, second]);
+        assert_eq!(conv.children(question), vec![first, second]);
+        assert_eq!(texts(&conv), ["q", "a2"]);
+
+        assert!(conv.switch_to(first));
+        let follow_up = conv.add_user_message("more");
+        assert!(conv.switch_to(second));
+        assert_eq!(texts(&conv), ["q", "a2"]);
+        assert!(conv.switch_to(question));
+        assert_eq!(texts(&conv), ["q", "a2"]);
+        assert!(conv.switch_to(first));
+        assert_eq!(conv.message_ids(), [question, first, follow_up]);
+
+        assert!(conv.rewind_to(first));
+        assert_eq!(texts(&conv), ["q", "a1"]);
+        assert!(conv.message(follow_up).is_none());
+        assert!(!conv.rewind_to(follow_up));
+        assert_eq!(conv.siblings(first), vec![first, second]);
+
+        // IDs are never reused
+        assert!(conv.add_user_message("again") > follow_up);
+    }
+
+    #[test]
+    fn saves_the_whole_tree() {
+        let mut conv = Conversation::with_system("Be brief.");
+        let question = conv.add_user_message("q");
+        let first = conv.add_assistant_message("a1");
+        conv.fork_at(first);
+        conv.add_assistant_message("a2");
+        conv.pin(question);
+
+        let json = serde_json::to_string(&conv).unwrap();
+        let mut loaded: Conversation = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(texts(&loaded), ["q", "a2"]);
+        assert!(loaded.is_pinned(question));
+        assert_eq!(loaded.system(), Some("Be brief."));
+        assert!(loaded.switch_to(first));
+        assert_eq!(loaded.parent(first), Some(question));
+
+        let legacy = r#"{"messages":[{"role":"user","content":"hi"}],"system":null,"pinned":[0]}"#;
+        let loaded: Conversation = serde_json::from_str(legacy).unwrap();
+        assert_eq!(texts(&loaded), ["hi"]);
+        assert!(loaded.is_pinned(loaded.message_ids()[0]));
+
+        let dangling = r#"{"nodes":[{"id":1,"parent":0,"role":"user","content":"hi"}],"head":1}"#;
+        assert!(serde_json::from_str::<Conversation>(dangling).is_err());
+    }
+
+    #[test]
+    fn metadata_is_saved_but_not_sent() {
+        let mut conv = Conversation::new();
+        let question = conv.add_user_message("q");
+        let fact = conv.add_message(
+            Message {
+                role: Role::User,
+                content: Content::Text("Paris is in France".into()),
+            },
+            MessageMetadata::new(Provenance::Knowledge),
+        );
+        let answer = conv.add_assistant_message("a");
+        *conv.metadata_mut(answer).unwrap() = MessageMetadata::new(Provenance::Model)
+            .with_model("anthropic", "claude")
+            .with_usage(Some(Usage {
+                input_tokens: 10,
+                output_tokens: 2,
+                ..Default::default()
+            }))
+            .with_latency(Duration::from_millis(250));
+
+        let json = serde_json::to_string(&conv).unwrap();
+        let loaded: Conversation = serde_json::from_str(&json).unwrap();
+
+        let metadata = loaded.metadata(answer).unwrap();
+        assert_eq!(metadata, conv.metadata(answer).unwrap());
+        assert_eq!(metadata.model.as_deref(), Some("claude"));
+        assert_eq!(metadata.latency(), Some(Duration::from_millis(250)));
+        assert!(metadata.created_at.is_some());
+        assert_eq!(
+            loaded.metadata(question).unwrap().source,
+            Some(Provenance::User)
+        );
+        assert!(loaded.metadata(fact).unwrap().source.unwrap().is_injected());
+
+        let wire = serde_json::to_string(loaded.messages()).unwrap();
+        assert!(!wire.contains("created_at"));
+        assert!(!wire.contains("claude"));
+    }
+
+    #[test]
+    fn pins_existing_messages_only() {
+        let mut conv = Conversation::with_system("Be brief.");
+        let first = conv.add_user_message("Remember this");
+        conv.add_assistant_message("Noted");
+
+        assert!(conv.pin(first));
+        assert!(!conv.pin(MessageId(2)));
+        assert!(conv.is_pinned(first));
+        assert!(conv.estimated_tokens() > 0);
+
+        let json = serde_json::to_string(&conv).unwrap();
+        let loaded: Conversation = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.pinned().collect::<Vec<_>>(), vec![first]);
+
+        conv.clear();
+        assert!(!conv.is_pinned(first));
+        assert!(!conv.pin(first));
+    }
 }
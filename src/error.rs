@@ -27,6 +27,13 @@ pub enum AgentError {
     #[error("tool execution failed: {0}")]
     ToolExecution(String),
 
+    /// Request uses a feature the provider cannot honor.
+    #[error("{provider} does not support {feature}")]
+    Unsupported {
+        provider: &'static str,
+        feature: &'static str,
+    },
+
     /// Stream ended unexpectedly.
     #[error("stream ended unexpectedly")]
     StreamEnded,
@@ -39,6 +46,18 @@ pub enum AgentError {
     #[error("loop detected: {0}")]
     LoopDetected(String),
 
+    /// Agent reached its iteration limit without finishing.
+    #[error("agent stopped after {0} iterations")]
+    MaxIterations(usize),
+
+    /// Operation cancelled through a cancellation token.
+    #[error("operation cancelled")]
+    Cancelled,
+
+    /// Usage budget reached.
+    #[error("usage budget exceeded: {0}")]
+    BudgetExceeded(String),
+
     /// Web fetch error
     #[error("web fetch error: {0}")]
     WebFetch(String),
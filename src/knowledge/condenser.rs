@@ -1,8 +1,14 @@
 //! Condense multi-turn conversation into a standalone retrieval query
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use thiserror::Error;
 
+use crate::provider::{CompletionRequest, LlmProvider};
+use crate::providers::OpenAiProvider;
+use crate::types::{Content, Message, Role};
+
 /// System prompt that instructs the LLM to produce a search query
 const CONDENSE_SYSTEM_PROMPT: &str = "\
 You are a search-query rewriter. Given a conversation history and the user's \
@@ -36,10 +42,8 @@ pub trait QueryCondenser: Send + Sync {
 /// Uses a fast/cheap model to rewrite the latest user message as a
 /// standalone retrieval query by resolving anaphoric references
 pub struct LlmCondenser {
-    client: reqwest::Client,
-    api_key: String,
+    provider: Arc<dyn LlmProvider>,
     model: String,
-    base_url: String,
 }
 
 impl LlmCondenser {
@@ -49,21 +53,18 @@ impl LlmCondenser {
     ///
     /// Returns error if API key is empty
     pub fn new(api_key: String) -> Result<Self, CondenseError> {
-        if api_key.is_empty() {
-            return Err(CondenseError::Request(
-                "API key required for query condensation".to_string(),
-            ));
-        }
-
-        Ok(Self {
-            client: reqwest::Client::new(),
+        Self::with_config(
             api_key,
-            model: "gpt-4o-mini".to_string(),
-            base_url: "https://api.openai.com/v1".to_string(),
-        })
+            "gpt-4o-mini".to_string(),
+            "https://api.openai.com/v1".to_string(),
+        )
     }
 
     /// Create a condenser with a custom model and base URL
+    ///
+    /// # Errors
+    ///
+    /// Returns error if API key is empty
     pub fn with_config(
         api_key: String,
         model: String,
@@ -75,47 +76,24 @@ impl LlmCondenser {
             ));
         }
 
-        Ok(Self {
-            client: reqwest::Client::new(),
-            api_key,
-            model,
-            base_url,
-        })
+        let provider = OpenAiProvider::with_config(Some(api_key), Some(base_url))
+            .map_err(|e| CondenseError::Request(e.to_string()))?;
+        Ok(Self::with_provider(Arc::new(provider), model))
+    }
+
+    /// Create a condenser backed by any provider
+    #[must_use]
+    pub fn with_provider(provider: Arc<dyn LlmProvider>, model: impl Into<String>) -> Self {
+        Self {
+            provider,
+            model: model.into(),
+        }
     }
 }
 
 #[async_trait]
 impl QueryCondenser for LlmCondenser {
     async fn condense(&self, current: &str, history: &[&str]) -> Result<String, CondenseError> {
-        #[derive(serde::Serialize)]
-        struct ChatMessage<'a> {
-            role: &'a str,
-            content: &'a str,
-        }
-
-        #[derive(serde::Serialize)]
-        struct ChatRequest<'a> {
-            model: &'a str,
-            messages: Vec<ChatMessage<'a>>,
-            max_tokens: u32,
-            temperature: f32,
-        }
-
-        #[derive(serde::Deserialize)]
-        struct ChatResponse {
-            choices: Vec<ChatChoice>,
-        }
-
-        #[derive(serde::Deserialize)]
-        struct ChatChoice {
-            message: ChatChoiceMessage,
-        }
-
-        #[derive(serde::Deserialize)]
-        struct ChatChoiceMessage {
-            content: Option<String>,
-        }
-
         // No history means no rewriting needed
         if history.is_empty() {
             return Ok(current.to_string());
@@ -131,49 +109,25 @@ impl QueryCondenser for LlmCondenser {
         user_prompt.push_str("\nLatest message: ");
         user_prompt.push_str(current);
 
-        let request = ChatRequest {
-            model: &self.model,
-            messages: vec![
-                ChatMessage {
-                    role: "system",
-                    content: CONDENSE_SYSTEM_PROMPT,
-                },
-                ChatMessage {
-                    role: "user",
-                    content: &user_prompt,
-                },
-            ],
+        let request = CompletionRequest {
+            model: self.model.clone(),
             max_tokens: 150,
-            temperature: 0.0,
+            messages: vec![Message {
+                role: Role::User,
+                content: Content::Text(user_prompt),
+            }],
+            system: Some(CONDENSE_SYSTEM_PROMPT.to_string()),
+            temperature: Some(0.0),
+            ..Default::default()
         };
 
-        let url = format!("{}/chat/completions", self.base_url);
-
         let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| CondenseError::Request(e.to_string()))?;
-
-        if !response.status().is_success() {
-            let body = response.text().await.unwrap_or_default();
-            return Err(CondenseError::Request(format!("API error: {body}")));
-        }
-
-        let result: ChatResponse = response
-            .json()
+            .provider
+            .complete(request)
             .await
             .map_err(|e| CondenseError::Request(e.to_string()))?;
 
-        result
-            .choices
-            .into_iter()
-            .next()
-            .and_then(|c| c.message.content)
-            .map(|s| s.trim().to_string())
+        Some(response.text().trim().to_string())
             .filter(|s| !s.is_empty())
             .ok_or_else(|| CondenseError::Request("empty response from condenser".to_string()))
     }
@@ -267,6 +221,23 @@ mod tests {
         assert!(result.contains("tell me more"));
     }
 
+    #[tokio::test]
+    async fn llm_condenser_uses_provider_completion() {
+        let provider =
+            Arc::new(crate::providers::MockProvider::new().with_text("  MCG token supply \n"));
+        let condenser = LlmCondenser::with_provider(provider.clone(), "small-model");
+
+        let query = condenser
+            .condense("how much of it exists?", &["what is MCG?"])
+            .await
+            .unwrap();
+
+        assert_eq!(query, "MCG token supply");
+        let request = &provider.requests()[0];
+        assert_eq!(request.model, "small-model");
+        assert!(request.messages[0].content.text().contains("what is MCG?"));
+    }
+
     #[test]
     fn empty_api_key_rejected() {
         let result = LlmCondenser::new(String::new());
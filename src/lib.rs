@@ -1,5 +1,6 @@
 //! Reusable AI agent library for Omni.
 
+pub mod compaction;
 pub mod conversation;
 pub mod error;
 pub mod knowledge;
@@ -8,7 +9,18 @@ pub mod plan;
 pub mod provider;
 pub mod providers;
 pub mod registry;
+pub mod session;
+pub mod sse;
 pub mod types;
+pub mod usage;
+
+#[cfg(test)]
+mod test_support;
+
+pub use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "tools")]
+pub mod agent;
 
 #[cfg(feature = "memory")]
 pub mod memory;
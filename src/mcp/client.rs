@@ -8,6 +8,7 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
 use tokio::sync::{Mutex, oneshot};
+use tokio_util::sync::CancellationToken;
 
 use super::types::{
     InitializeResult, JsonRpcRequest, JsonRpcResponse, McpServerConfig, McpTool, McpToolResult,
@@ -17,6 +18,9 @@ use super::types::{
 /// Shared state for pending request tracking
 type PendingMap = Arc<Mutex<HashMap<u64, oneshot::Sender<JsonRpcResponse>>>>;
 
+/// How long to wait for a response before giving up
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
 /// Client for a single MCP server process
 pub struct McpClient {
     config: McpServerConfig,
@@ -170,6 +174,9 @@ impl McpClient {
 
     /// Call a tool on this server
     ///
+    /// When `cancel` fires first the server is sent
+    /// `notifications/cancelled` and the result is marked cancelled
+    ///
     /// # Errors
     ///
     /// Returns error if the tool call fails
@@ -177,9 +184,10 @@ impl McpClient {
         &self,
         name: &str,
         arguments: serde_json::Value,
+        cancel: &CancellationToken,
     ) -> Result<McpToolResult, String> {
-        let result = self
-            .send_request::<ToolCallResult>(
+        let (id, rx) = self
+            .write_request(
                 "tools/call",
                 Some(serde_json::json!({
                     "name": name,
@@ -188,6 +196,33 @@ impl McpClient {
             )
             .await?;
 
+        let response = tokio::select! {
+            response = tokio::time::timeout(REQUEST_TIMEOUT, rx) => response,
+            () = cancel.cancelled() => {
+                self.pending.lock().await.remove(&id);
+                // The caller asked to cancel, so a failed notice is not an error
+                if let Err(e) = self
+                    .send_notification(
+                        "notifications/cancelled",
+                        Some(serde_json::json!({
+                            "requestId": id,
+                            "reason": "cancelled by client"
+                        })),
+                    )
+                    .await
+                {
+                    tracing::warn!(server = %self.config.name, error = %e, "MCP cancel notification failed");
+                }
+                tracing::debug!(server = %self.config.name, tool = %name, "MCP tool call cancelled");
+                return Ok(McpToolResult {
+                    text: "tool call cancelled".to_string(),
+                    is_error: true,
+                    cancelled: true,
+                });
+            }
+        };
+        let result = Self::read_response::<ToolCallResult>("tools/call", response)?;
+
         let text = result
             .content
             .into_iter()
@@ -201,6 +236,7 @@ impl McpClient {
         Ok(McpToolResult {
             text,
             is_error: result.is_error,
+            cancelled: false,
         })
     }
 
@@ -225,6 +261,18 @@ impl McpClient {
         method: &str,
         params: Option<serde_json::Value>,
     ) -> Result<T, String> {
+        let (_, rx) = self.write_request(method, params).await?;
+        Self::read_response(method, tokio::time::timeout(REQUEST_TIMEOUT, rx).await)
+    }
+
+    /// Write a JSON-RPC request and register it as pending
+    ///
+    /// Returns the request ID and the channel its response arrives on
+    async fn write_request(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+    ) -> Result<(u64, oneshot::Receiver<JsonRpcResponse>), String> {
         let id = self.next_id.fetch_add(1, Ordering::Relaxed);
 
         let request = JsonRpcRequest {
@@ -256,9 +304,24 @@ impl McpClient {
                 .map_err(|e| format!("flush to MCP server failed: {e}"))?;
         }
 
-        let response = tokio::time::timeout(std::time::Duration::from_secs(30), rx)
-            .await
-            .map_err(|_| format!("MCP request '{method}' timed out after 30s"))?
+        Ok((id, rx))
+    }
+
+    /// Decode the response to a request, or the reason it never arrived
+    fn read_response<T: serde::de::DeserializeOwned>(
+        method: &str,
+        response: Result<
+            Result<JsonRpcResponse, oneshot::error::RecvError>,
+            tokio::time::error::Elapsed,
+        >,
+    ) -> Result<T, String> {
+        let response = response
+            .map_err(|_| {
+                format!(
+                    "MCP request '{method}' timed out after {}s",
+                    REQUEST_TIMEOUT.as_secs()
+                )
+            })?
             .map_err(|_| "MCP response channel dropped".to_string())?;
 
         if let Some(error) = response.error {
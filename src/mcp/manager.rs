@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
 use super::client::McpClient;
 use super::types::{McpServerConfig, McpTool, McpToolResult};
@@ -137,6 +138,7 @@ impl McpServerManager {
         &self,
         scoped_name: &str,
         arguments: serde_json::Value,
+        cancel: &CancellationToken,
     ) -> Result<McpToolResult, String> {
         let (server_name, tool_name) = if let Some(rest) = scoped_name.strip_prefix("mcp_") {
             rest.split_once('/')
@@ -148,10 +150,13 @@ impl McpServerManager {
                 .ok_or_else(|| format!("no MCP server for tool: {scoped_name}"))?
                 .clone();
             drop(routes);
-            return self.call_on_server(&server, scoped_name, arguments).await;
+            return self
+                .call_on_server(&server, scoped_name, arguments, cancel)
+                .await;
         };
 
-        self.call_on_server(server_name, tool_name, arguments).await
+        self.call_on_server(server_name, tool_name, arguments, cancel)
+            .await
     }
 
     /// Call a tool on a specific server
@@ -160,6 +165,7 @@ impl McpServerManager {
         server_name: &str,
         tool_name: &str,
         arguments: serde_json::Value,
+        cancel: &CancellationToken,
     ) -> Result<McpToolResult, String> {
         let servers = self.servers.lock().await;
         let client = servers
@@ -168,7 +174,7 @@ impl McpServerManager {
         let client = Arc::clone(client);
         drop(servers);
 
-        client.call_tool(tool_name, arguments).await
+        client.call_tool(tool_name, arguments, cancel).await
     }
 
     /// List running server names
@@ -2,8 +2,12 @@
 
 mod client;
 mod manager;
+#[cfg(feature = "tools")]
+mod tools;
 mod types;
 
 pub use client::McpClient;
 pub use manager::McpServerManager;
+#[cfg(feature = "tools")]
+pub use tools::McpToolProvider;
 pub use types::{McpServerConfig, McpTool, McpToolResult};
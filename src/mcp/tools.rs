@@ -0,0 +1,135 @@
+//! Adapter exposing MCP server tools to the agent
+
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+
+use super::manager::McpServerManager;
+use super::types::McpTool;
+use crate::error::AgentError;
+use crate::tools::ToolProvider;
+
+/// Tool provider backed by the servers of an [`McpServerManager`]
+///
+/// Definitions are a snapshot taken when the provider is created, so
+/// servers started later need a new provider
+pub struct McpToolProvider {
+    manager: Arc<McpServerManager>,
+    tools: Vec<McpTool>,
+}
+
+impl McpToolProvider {
+    /// Snapshot the tools of the manager's running servers
+    pub async fn new(manager: Arc<McpServerManager>) -> Self {
+        let tools = manager.all_tools().await;
+        Self { manager, tools }
+    }
+}
+
+#[async_trait::async_trait]
+impl ToolProvider for McpToolProvider {
+    fn definitions(&self) -> Vec<crate::types::Tool> {
+        self.tools
+            .iter()
+            .map(|tool| crate::types::Tool {
+                name: tool.name.clone(),
+                description: tool.description.clone().unwrap_or_default(),
+                input_schema: tool.input_schema.clone(),
+            })
+            .collect()
+    }
+
+    async fn execute(
+        &self,
+        name: &str,
+        arguments: &str,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
+        let arguments = if arguments.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(arguments)?
+        };
+
+        let result = self
+            .manager
+            .call_tool(name, arguments, cancel)
+            .await
+            .map_err(anyhow::Error::msg)?;
+        if result.cancelled {
+            return Err(AgentError::Cancelled.into());
+        }
+        if result.is_error {
+            anyhow::bail!(result.text);
+        }
+        Ok(result.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mcp::McpServerConfig;
+
+    /// MCP server that never answers tool calls and logs notifications
+    const SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*)
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2024-11-05\"}}" ;;
+    *'"tools/list"'*)
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[{\"name\":\"wait\",\"inputSchema\":{\"type\":\"object\"}}]}}" ;;
+    *'notifications/cancelled'*)
+      echo "$line" >> "$NOTICES" ;;
+  esac
+done
+"#;
+
+    #[tokio::test]
+    async fn cancelled_calls_notify_the_server_and_fail_as_cancelled() {
+        let notices = std::env::temp_dir().join(format!("mcp-{}.log", uuid::Uuid::new_v4()));
+        let manager = Arc::new(McpServerManager::new());
+        manager
+            .start_server(McpServerConfig {
+                name: "slow".to_string(),
+                command: "sh".to_string(),
+                args: vec!["-c".to_string(), SERVER.to_string()],
+                env: HashMap::from([("NOTICES".to_string(), notices.display().to_string())]),
+            })
+            .await
+            .unwrap();
+        let provider = McpToolProvider::new(Arc::clone(&manager)).await;
+        assert_eq!(provider.definitions()[0].name, "mcp_slow/wait");
+
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            trigger.cancel();
+        });
+        let error = provider
+            .execute("mcp_slow/wait", "{}", &cancel)
+            .await
+            .unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(AgentError::Cancelled)));
+
+        let mut logged = String::new();
+        for _ in 0..50 {
+            logged = std::fs::read_to_string(&notices).unwrap_or_default();
+            if !logged.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let notice: serde_json::Value = serde_json::from_str(logged.trim()).unwrap();
+        assert_eq!(notice["method"], "notifications/cancelled");
+        assert_eq!(notice["params"]["requestId"], 3);
+
+        manager.stop_all().await;
+        let _ = std::fs::remove_file(&notices);
+    }
+}
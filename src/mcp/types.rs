@@ -37,6 +37,8 @@ pub struct McpToolResult {
     pub text: String,
     /// Whether the tool reported an error
     pub is_error: bool,
+    /// Whether the call was cancelled before the server answered
+    pub cancelled: bool,
 }
 
 // --- JSON-RPC types for the MCP protocol ---
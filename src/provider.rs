@@ -1,15 +1,23 @@
 //! LLM provider abstraction for BYOM (Bring Your Own Model).
 
+use std::collections::BTreeMap;
 use std::pin::Pin;
 
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 
-use super::error::Result;
-use super::types::{ContentBlock, Message, StopReason, Tool, Usage};
+use super::error::{AgentError, Result};
+use super::types::{Content, ContentBlock, Message, Role, StopReason, Tool, Usage};
 
 /// Configuration for an LLM request.
-#[derive(Debug, Clone)]
+///
+/// Optional fields left as `None` use the provider's default. Providers
+/// that cannot honor a field that is set return
+/// [`AgentError::Unsupported`] instead of ignoring it.
+#[derive(Debug, Clone, Default)]
 pub struct CompletionRequest {
     /// Model identifier.
     pub model: String,
@@ -21,13 +29,104 @@ pub struct CompletionRequest {
     pub system: Option<String>,
     /// Available tools.
     pub tools: Option<Vec<Tool>>,
+    /// Sampling temperature.
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability mass.
+    pub top_p: Option<f32>,
+    /// Sequences that stop generation when produced.
+    pub stop_sequences: Option<Vec<String>>,
+    /// How the model should choose among the available tools.
+    pub tool_choice: Option<ToolChoice>,
+    /// Whether the model may request several tool calls in one turn.
+    pub parallel_tool_calls: Option<bool>,
+    /// Token budget for extended thinking, enabling it when set.
+    pub thinking_budget: Option<u32>,
+    /// Prompt caching strategy.
+    ///
+    /// Caching never changes the output, so providers that cache
+    /// automatically or not at all ignore this.
+    pub cache: Option<PromptCache>,
+    /// Require the response text to be JSON matching a schema.
+    pub response_format: Option<JsonSchema>,
+    /// Token that aborts the request and its stream when cancelled.
+    ///
+    /// Providers stop reading, drop the connection and end the stream
+    /// with [`AgentError::Cancelled`].
+    pub cancel: Option<CancellationToken>,
+}
+
+/// A named JSON schema for structured output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonSchema {
+    /// Schema name, also used as the tool name when forcing a tool call.
+    pub name: String,
+    /// The JSON schema document.
+    pub schema: serde_json::Value,
+}
+
+impl JsonSchema {
+    /// Create a named schema.
+    #[must_use]
+    pub fn new(name: impl Into<String>, schema: serde_json::Value) -> Self {
+        Self {
+            name: name.into(),
+            schema,
+        }
+    }
+}
+
+/// Where to place prompt cache breakpoints.
+///
+/// Each breakpoint caches the whole prompt prefix up to and including it,
+/// in the order tools, system prompt, messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromptCache {
+    /// Cache the stable prefix: tools, system prompt and all messages.
+    Auto,
+    /// Cache up to explicitly chosen points.
+    Breakpoints {
+        /// After the last tool definition.
+        tools: bool,
+        /// After the system prompt.
+        system: bool,
+        /// After each message at these indices.
+        messages: Vec<usize>,
+    },
+}
+
+/// Map a thinking token budget to a reasoning effort level.
+#[must_use]
+pub const fn reasoning_effort(budget: u32) -> &'static str {
+    match budget {
+        0..4096 => "low",
+        4096..16384 => "medium",
+        _ => "high",
+    }
+}
+
+/// Tool selection strategy for a completion.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// Model decides whether to call tools.
+    #[default]
+    Auto,
+    /// Model must call at least one tool.
+    Any,
+    /// Model must not call tools.
+    None,
+    /// Model must call the named tool.
+    Tool(String),
 }
 
 /// A streaming event from the LLM.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CompletionEvent {
     /// A chunk of text content.
     TextDelta(String),
+    /// A chunk of model reasoning.
+    ThinkingDelta(String),
     /// Start of a tool use block.
     ToolUseStart {
         index: usize,
@@ -43,13 +142,266 @@ pub enum CompletionEvent {
         stop_reason: Option<StopReason>,
         usage: Option<Usage>,
     },
+    /// The backend that is serving the completion.
+    Backend(Backend),
     /// An error occurred.
     Error(String),
 }
 
+/// A provider and model pair that served a completion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backend {
+    /// Provider name.
+    pub provider: String,
+    /// Model identifier.
+    pub model: String,
+}
+
 /// Stream of completion events.
 pub type CompletionStream = Pin<Box<dyn Stream<Item = Result<CompletionEvent>> + Send>>;
 
+/// A fully assembled completion.
+#[derive(Debug, Clone)]
+pub struct CompletionResponse {
+    /// The assistant message.
+    pub message: Message,
+    /// Why the completion stopped.
+    pub stop_reason: Option<StopReason>,
+    /// Token usage, if the provider reported it.
+    pub usage: Option<Usage>,
+    /// Backend that served the completion, if the provider reported it.
+    pub backend: Option<Backend>,
+}
+
+impl CompletionResponse {
+    /// Get the text content of the message.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.message.content.text()
+    }
+}
+
+/// A content block that is still being streamed.
+#[derive(Debug)]
+enum PartialBlock {
+    /// Tool use with accumulated input JSON.
+    ToolUse {
+        id: String,
+        name: String,
+        json: String,
+    },
+    /// A block the provider reported as complete.
+    Done(ContentBlock),
+}
+
+/// Accumulates completion events into a finished assistant message.
+///
+/// Providers differ in which events they emit: some report complete tool
+/// blocks, others only stream input fragments. The collector folds
+/// `ToolInputDelta` fragments back into tool blocks by index, so the
+/// result is the same regardless of provider.
+#[derive(Debug, Default)]
+pub struct StreamCollector {
+    blocks: BTreeMap<usize, PartialBlock>,
+    thinking: String,
+    text: String,
+    stop_reason: Option<StopReason>,
+    usage: Option<Usage>,
+    backend: Option<Backend>,
+}
+
+impl StreamCollector {
+    /// Create an empty collector.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed an event into the collector.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the event is a provider-reported `Error`.
+    pub fn push(&mut self, event: CompletionEvent) -> Result<()> {
+        match event {
+            CompletionEvent::TextDelta(text) => self.text.push_str(&text),
+            CompletionEvent::ThinkingDelta(thinking) => self.thinking.push_str(&thinking),
+            CompletionEvent::ToolUseStart { index, id, name } => {
+                self.blocks.insert(
+                    index,
+                    PartialBlock::ToolUse {
+                        id,
+                        name,
+                        json: String::new(),
+                    },
+                );
+            }
+            CompletionEvent::ToolInputDelta {
+                index,
+                partial_json,
+            } => {
+                if let Some(PartialBlock::ToolUse { json, .. }) = self.blocks.get_mut(&index) {
+                    json.push_str(&partial_json);
+                }
+            }
+            CompletionEvent::ContentBlockDone { index, block } => {
+                let block = match (block, self.blocks.remove(&index)) {
+                    // Fill in input the provider did not fold back in
+                    (
+                        ContentBlock::ToolUse { id, name, input },
+                        Some(PartialBlock::ToolUse { json, .. }),
+                    ) if is_empty_input(&input) && !json.is_empty() => ContentBlock::ToolUse {
+                        id,
+                        name,
+                        input: parse_tool_input(&json)?,
+                    },
+                    (block @ ContentBlock::Text { .. }, _) => {
+                        // The finished block supersedes streamed deltas
+                        self.text.clear();
+                        block
+                    }
+                    (block @ ContentBlock::Thinking { .. }, _) => {
+                        self.thinking.clear();
+                        block
+                    }
+                    (block, _) => block,
+                };
+                self.blocks.insert(index, PartialBlock::Done(block));
+            }
+            CompletionEvent::Done { stop_reason, usage } => {
+                self.stop_reason = stop_reason;
+                if usage.is_some() {
+                    self.usage = usage;
+                }
+            }
+            CompletionEvent::Backend(backend) => self.backend = Some(backend),
+            CompletionEvent::Error(message) => return Err(AgentError::Transport(message)),
+        }
+        Ok(())
+    }
+
+    /// Assemble the final response.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if accumulated tool input is not valid JSON.
+    pub fn finish(self) -> Result<CompletionResponse> {
+        let mut blocks = Vec::with_capacity(self.blocks.len() + 2);
+
+        // Reasoning and text that never got a completed block lead the message
+        if !self.thinking.is_empty() {
+            blocks.push(ContentBlock::Thinking {
+                thinking: self.thinking,
+                signature: None,
+            });
+        }
+        if !self.text.is_empty() {
+            blocks.push(ContentBlock::Text { text: self.text });
+        }
+
+        for block in self.blocks.into_values() {
+            blocks.push(match block {
+                PartialBlock::ToolUse { id, name, json } => ContentBlock::ToolUse {
+                    id,
+                    name,
+                    input: parse_tool_input(&json)?,
+                },
+                PartialBlock::Done(block) => block,
+            });
+        }
+
+        Ok(CompletionResponse {
+            message: Message {
+                role: Role::Assistant,
+                content: Content::Blocks(blocks),
+            },
+            stop_reason: self.stop_reason,
+            usage: self.usage,
+            backend: self.backend,
+        })
+    }
+}
+
+/// Run a future unless the token is cancelled first.
+///
+/// # Errors
+///
+/// Returns [`AgentError::Cancelled`] if the token fires before the
+/// future completes, otherwise the future's own result.
+pub async fn cancellable<T>(
+    cancel: Option<&CancellationToken>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(cancel) = cancel else {
+        return future.await;
+    };
+    tokio::select! {
+        biased;
+        () = cancel.cancelled() => Err(AgentError::Cancelled),
+        result = future => result,
+    }
+}
+
+/// End a stream with [`AgentError::Cancelled`] when the token fires.
+///
+/// The inner stream is dropped on cancellation, which closes its
+/// connection.
+#[must_use]
+pub fn cancellable_stream(
+    mut stream: CompletionStream,
+    cancel: Option<CancellationToken>,
+) -> CompletionStream {
+    let Some(cancel) = cancel else {
+        return stream;
+    };
+    Box::pin(async_stream::stream! {
+        loop {
+            tokio::select! {
+                biased;
+                () = cancel.cancelled() => {
+                    yield Err(AgentError::Cancelled);
+                    return;
+                }
+                event = stream.next() => match event {
+                    Some(event) => yield event,
+                    None => return,
+                },
+            }
+        }
+    })
+}
+
+/// Drain a completion stream into a finished response.
+///
+/// # Errors
+///
+/// Returns error if the stream fails, reports an error event, or yields
+/// invalid tool input JSON.
+pub async fn collect(mut stream: CompletionStream) -> Result<CompletionResponse> {
+    let mut collector = StreamCollector::new();
+    while let Some(event) = stream.next().await {
+        collector.push(event?)?;
+    }
+    collector.finish()
+}
+
+/// Check whether a tool input is a placeholder awaiting streamed JSON.
+fn is_empty_input(input: &serde_json::Value) -> bool {
+    match input {
+        serde_json::Value::Null => true,
+        serde_json::Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+/// Parse accumulated tool input, treating no input as an empty object.
+pub(crate) fn parse_tool_input(json: &str) -> Result<serde_json::Value> {
+    if json.trim().is_empty() {
+        return Ok(serde_json::Value::Object(serde_json::Map::new()));
+    }
+    serde_json::from_str(json).map_err(|e| AgentError::Parse(format!("invalid tool input: {e}")))
+}
+
 /// Trait for LLM providers.
 ///
 /// Implement this trait to add support for a new LLM provider.
@@ -62,4 +414,459 @@ pub trait LlmProvider: Send + Sync {
     ///
     /// Returns a stream of completion events.
     async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream>;
+
+    /// Run a completion request and return the assembled response.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the request or stream fails.
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        collect(self.stream(request).await?).await
+    }
+}
+
+/// Run a completion that returns JSON matching `schema`, parsed as `T`.
+///
+/// Uses the provider's native JSON schema response format, or forces a
+/// call to a tool whose input is the schema when the provider reports
+/// that as unsupported. Output that fails to parse as `T` is sent back
+/// with the error for one repair attempt.
+///
+/// # Errors
+///
+/// Returns error if a completion fails, or [`AgentError::Parse`] if the
+/// output still does not match after the repair attempt.
+pub async fn complete_structured<T: DeserializeOwned>(
+    provider: &dyn LlmProvider,
+    request: CompletionRequest,
+    schema: JsonSchema,
+) -> Result<T> {
+    let native = CompletionRequest {
+        response_format: Some(schema.clone()),
+        ..request.clone()
+    };
+
+    let (mut request, mut response) = match provider.complete(native.clone()).await {
+        Ok(response) => (native, response),
+        Err(AgentError::Unsupported { .. }) => {
+            let forced = CompletionRequest {
+                tools: Some(vec![Tool {
+                    name: schema.name.clone(),
+                    description: "Respond by calling this tool with the requested data."
+                        .to_string(),
+                    input_schema: schema.schema.clone(),
+                }]),
+                tool_choice: Some(ToolChoice::Tool(schema.name.clone())),
+                ..request
+            };
+            let response = provider.complete(forced.clone()).await?;
+            (forced, response)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let forced_tool = request.response_format.is_none();
+    let mut repaired = false;
+    loop {
+        let error = match structured_output(&response, forced_tool, &schema.name) {
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(output) => return Ok(output),
+                Err(e) => e.to_string(),
+            },
+            Err(e) => e,
+        };
+        if repaired {
+            return Err(AgentError::Parse(format!(
+                "structured output does not match schema '{}': {error}",
+                schema.name
+            )));
+        }
+        repaired = true;
+
+        tracing::debug!(schema = %schema.name, error = %error, "repairing structured output");
+        let feedback = format!(
+            "The output does not match the schema: {error}. Respond again with only data \
+             that matches the schema."
+        );
+        // A tool call must be answered with a tool result
+        let tool_use_id = match &response.message.content {
+            Content::Blocks(blocks) => blocks.iter().find_map(|b| match b {
+                ContentBlock::ToolUse { id, .. } => Some(id.clone()),
+                _ => None,
+            }),
+            Content::Text(_) => None,
+        };
+        let reply = match tool_use_id {
+            Some(tool_use_id) => Content::Blocks(vec![ContentBlock::ToolResult {
+                tool_use_id,
+                content: Content::Text(feedback),
+                is_error: Some(true),
+            }]),
+            None => Content::Text(feedback),
+        };
+        request.messages.push(response.message);
+        request.messages.push(Message {
+            role: Role::User,
+            content: reply,
+        });
+        response = provider.complete(request.clone()).await?;
+    }
+}
+
+/// Extract the structured JSON value from a response.
+fn structured_output(
+    response: &CompletionResponse,
+    forced_tool: bool,
+    name: &str,
+) -> std::result::Result<serde_json::Value, String> {
+    if forced_tool {
+        let Content::Blocks(blocks) = &response.message.content else {
+            return Err(format!("expected a call to tool '{name}'"));
+        };
+        return blocks
+            .iter()
+            .find_map(|b| match b {
+                ContentBlock::ToolUse {
+                    name: tool, input, ..
+                } if tool == name => Some(input.clone()),
+                _ => None,
+            })
+            .ok_or_else(|| format!("expected a call to tool '{name}'"));
+    }
+
+    // Models sometimes wrap JSON in a code fence despite the format
+    let text = response.text();
+    let text = text.trim();
+    let json = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(text);
+    serde_json::from_str(json).map_err(|e| format!("invalid JSON: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_of(events: Vec<CompletionEvent>) -> CompletionStream {
+        Box::pin(futures::stream::iter(events.into_iter().map(Ok)))
+    }
+
+    fn blocks(response: &CompletionResponse) -> &[ContentBlock] {
+        match &response.message.content {
+            Content::Blocks(blocks) => blocks,
+            Content::Text(_) => panic!("expected blocks"),
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelled_stream_ends_with_cancelled_error() {
+        let cancel = CancellationToken::new();
+        let pending = Box::pin(futures::stream::pending());
+        let mut stream = cancellable_stream(pending, Some(cancel.clone()));
+
+        cancel.cancel();
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(AgentError::Cancelled))
+        ));
+        assert!(stream.next().await.is_none());
+
+        let result = cancellable(Some(&cancel), futures::future::pending::<Result<()>>()).await;
+        assert!(matches!(result, Err(AgentError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn collect_folds_partial_json_into_empty_tool_block() {
+        // Anthropic-style: tool block completes with an empty input
+        let response = collect(stream_of(vec![
+            CompletionEvent::TextDelta("Let me check".to_string()),
+            CompletionEvent::ContentBlockDone {
+                index: 0,
+                block: ContentBlock::Text {
+                    text: "Let me check".to_string(),
+                },
+            },
+            CompletionEvent::ToolUseStart {
+                index: 1,
+                id: "toolu_1".to_string(),
+                name: "read_file".to_string(),
+            },
+            CompletionEvent::ToolInputDelta {
+                index: 1,
+                partial_json: r#"{"path":"#.to_string(),
+            },
+            CompletionEvent::ToolInputDelta {
+                index: 1,
+                partial_json: r#""/tmp/a"}"#.to_string(),
+            },
+            CompletionEvent::ContentBlockDone {
+                index: 1,
+                block: ContentBlock::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "read_file".to_string(),
+                    input: serde_json::json!({}),
+                },
+            },
+            CompletionEvent::Done {
+                stop_reason: Some(StopReason::ToolUse),
+                usage: Some(Usage {
+                    input_tokens: 10,
+                    output_tokens: 5,
+                    ..Default::default()
+                }),
+            },
+        ]))
+        .await
+        .unwrap();
+
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(response.usage.as_ref().unwrap().output_tokens, 5);
+        assert_eq!(response.text(), "Let me check");
+
+        let blocks = blocks(&response);
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(
+            &blocks[1],
+            ContentBlock::ToolUse { input, .. } if input["path"] == "/tmp/a"
+        ));
+    }
+
+    #[tokio::test]
+    async fn collect_keeps_completed_tool_input() {
+        // OpenAI-style: tool block completes with parsed input
+        let response = collect(stream_of(vec![
+            CompletionEvent::ToolUseStart {
+                index: 1,
+                id: "call_1".to_string(),
+                name: "shell".to_string(),
+            },
+            CompletionEvent::ToolInputDelta {
+                index: 1,
+                partial_json: r#"{"cmd":"ls"}"#.to_string(),
+            },
+            CompletionEvent::ContentBlockDone {
+                index: 1,
+                block: ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "shell".to_string(),
+                    input: serde_json::json!({"cmd": "ls"}),
+                },
+            },
+            CompletionEvent::Done {
+                stop_reason: Some(StopReason::ToolUse),
+                usage: None,
+            },
+        ]))
+        .await
+        .unwrap();
+
+        let blocks = blocks(&response);
+        assert_eq!(blocks.len(), 1);
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::ToolUse { input, .. } if input["cmd"] == "ls"
+        ));
+    }
+
+    #[tokio::test]
+    async fn collect_builds_unfinished_blocks() {
+        let response = collect(stream_of(vec![
+            CompletionEvent::TextDelta("Hello".to_string()),
+            CompletionEvent::TextDelta(" world".to_string()),
+            CompletionEvent::ToolUseStart {
+                index: 1,
+                id: "call_1".to_string(),
+                name: "noop".to_string(),
+            },
+        ]))
+        .await
+        .unwrap();
+
+        let blocks = blocks(&response);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(response.text(), "Hello world");
+        assert!(matches!(
+            &blocks[1],
+            ContentBlock::ToolUse { input, .. } if input == &serde_json::json!({})
+        ));
+    }
+
+    #[tokio::test]
+    async fn collect_keeps_signed_thinking_before_text() {
+        let response = collect(stream_of(vec![
+            CompletionEvent::ThinkingDelta("Hmm".to_string()),
+            CompletionEvent::ContentBlockDone {
+                index: 0,
+                block: ContentBlock::Thinking {
+                    thinking: "Hmm".to_string(),
+                    signature: Some("sig".to_string()),
+                },
+            },
+            CompletionEvent::TextDelta("Done".to_string()),
+            CompletionEvent::ContentBlockDone {
+                index: 1,
+                block: ContentBlock::Text {
+                    text: "Done".to_string(),
+                },
+            },
+        ]))
+        .await
+        .unwrap();
+
+        let content = blocks(&response);
+        assert_eq!(content.len(), 2);
+        assert!(matches!(
+            &content[0],
+            ContentBlock::Thinking { signature: Some(s), .. } if s == "sig"
+        ));
+        assert_eq!(response.text(), "Done");
+
+        let unsigned = collect(stream_of(vec![
+            CompletionEvent::ThinkingDelta("Hmm".to_string()),
+            CompletionEvent::TextDelta("Done".to_string()),
+        ]))
+        .await
+        .unwrap();
+        assert!(matches!(
+            &blocks(&unsigned)[0],
+            ContentBlock::Thinking { thinking, signature: None } if thinking == "Hmm"
+        ));
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Weather {
+        city: String,
+        celsius: i32,
+    }
+
+    fn weather_schema() -> JsonSchema {
+        JsonSchema::new(
+            "weather",
+            serde_json::json!({
+                "type": "object",
+                "properties": {"city": {"type": "string"}, "celsius": {"type": "integer"}},
+                "required": ["city", "celsius"]
+            }),
+        )
+    }
+
+    /// Provider without native JSON schema support.
+    struct NoSchemaProvider(crate::providers::MockProvider);
+
+    #[async_trait]
+    impl LlmProvider for NoSchemaProvider {
+        fn name(&self) -> &'static str {
+            "no-schema"
+        }
+
+        async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+            if request.response_format.is_some() {
+                return Err(AgentError::Unsupported {
+                    provider: "no-schema",
+                    feature: "JSON schema response format",
+                });
+            }
+            self.0.stream(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn structured_output_uses_native_format() {
+        let provider = crate::providers::MockProvider::new()
+            .with_text("```json\n{\"city\": \"Oslo\", \"celsius\": 4}\n```");
+
+        let weather: Weather =
+            complete_structured(&provider, CompletionRequest::default(), weather_schema())
+                .await
+                .unwrap();
+
+        assert_eq!(weather.city, "Oslo");
+        assert_eq!(
+            provider.requests()[0].response_format,
+            Some(weather_schema())
+        );
+    }
+
+    #[tokio::test]
+    async fn structured_output_falls_back_to_forced_tool_and_repairs() {
+        let provider = NoSchemaProvider(
+            crate::providers::MockProvider::new()
+                .with_tool_use("call_1", "weather", serde_json::json!({"city": "Oslo"}))
+                .with_tool_use(
+                    "call_2",
+                    "weather",
+                    serde_json::json!({"city": "Oslo", "celsius": 4}),
+                ),
+        );
+
+        let weather: Weather =
+            complete_structured(&provider, CompletionRequest::default(), weather_schema())
+                .await
+                .unwrap();
+        assert_eq!(weather.celsius, 4);
+
+        let requests = provider.0.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].tool_choice,
+            Some(ToolChoice::Tool("weather".to_string()))
+        );
+        // The repair turn answers the invalid call with an error result
+        let Content::Blocks(blocks) = &requests[1].messages[1].content else {
+            panic!("expected tool result");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::ToolResult { tool_use_id, is_error: Some(true), content }
+                if tool_use_id == "call_1" && content.text().contains("celsius")
+        ));
+    }
+
+    #[tokio::test]
+    async fn structured_output_fails_after_repair() {
+        let provider = crate::providers::MockProvider::new()
+            .with_text("not json")
+            .with_text("still not json");
+
+        let result: Result<Weather> =
+            complete_structured(&provider, CompletionRequest::default(), weather_schema()).await;
+
+        assert!(matches!(result, Err(AgentError::Parse(_))));
+        assert_eq!(provider.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn collect_surfaces_error_events() {
+        let result = collect(stream_of(vec![CompletionEvent::Error(
+            "overloaded".to_string(),
+        )]))
+        .await;
+        assert!(matches!(result, Err(AgentError::Transport(m)) if m == "overloaded"));
+    }
+
+    #[tokio::test]
+    async fn collect_rejects_invalid_tool_json() {
+        let result = collect(stream_of(vec![
+            CompletionEvent::ToolUseStart {
+                index: 0,
+                id: "call_1".to_string(),
+                name: "noop".to_string(),
+            },
+            CompletionEvent::ToolInputDelta {
+                index: 0,
+                partial_json: r#"{"a":"#.to_string(),
+            },
+        ]))
+        .await;
+        assert!(matches!(result, Err(AgentError::Parse(_))));
+    }
+
+    #[test]
+    fn thinking_budget_maps_to_effort() {
+        assert_eq!(reasoning_effort(1024), "low");
+        assert_eq!(reasoning_effort(8000), "medium");
+        assert_eq!(reasoning_effort(32000), "high");
+    }
 }
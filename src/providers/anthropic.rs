@@ -1,21 +1,39 @@
 //! Anthropic (Claude) provider implementation.
 
+use std::collections::HashMap;
+
 use async_trait::async_trait;
 use futures::StreamExt;
 use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue};
 
 use crate::error::{AgentError, Result};
-use crate::provider::{CompletionEvent, CompletionRequest, CompletionStream, LlmProvider};
-use crate::types::{ContentBlock, Delta, MessagesRequest, StreamEvent};
-
-const API_URL: &str = "https://api.anthropic.com/v1/messages";
+use crate::provider::{
+    CompletionEvent, CompletionRequest, CompletionStream, LlmProvider, PromptCache, ToolChoice,
+    cancellable, cancellable_stream,
+};
+use crate::providers::RetryPolicy;
+use crate::sse::sse_events;
+use crate::types::{
+    CacheControl, Cached, Content, ContentBlock, Delta, Message, MessagesMessage, MessagesRequest,
+    MessagesToolChoice, StreamEvent, ThinkingConfig, Usage,
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
 const API_VERSION: &str = "2023-06-01";
 
+/// Maximum number of cache breakpoints per request.
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// Smallest thinking budget the Messages API accepts.
+const MIN_THINKING_BUDGET: u32 = 1024;
+
 /// Anthropic (Claude) LLM provider.
 #[derive(Debug, Clone)]
 pub struct AnthropicProvider {
     http: reqwest::Client,
     api_key: String,
+    base_url: String,
+    retry: RetryPolicy,
 }
 
 impl AnthropicProvider {
@@ -33,8 +51,31 @@ impl AnthropicProvider {
         Ok(Self {
             http: reqwest::Client::new(),
             api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            retry: RetryPolicy::default(),
         })
     }
+
+    /// Use a different API endpoint, such as a proxy or mock server.
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Use a preconfigured HTTP client, e.g. with proxy or timeout settings.
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Set the retry policy for failed requests.
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 }
 
 #[async_trait]
@@ -43,6 +84,7 @@ impl LlmProvider for AnthropicProvider {
         "anthropic"
     }
 
+    #[allow(clippy::too_many_lines)]
     async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
@@ -52,145 +94,311 @@ impl LlmProvider for AnthropicProvider {
         );
         headers.insert("anthropic-version", HeaderValue::from_static(API_VERSION));
 
-        // Convert to Anthropic-specific request format
-        let anthropic_request = MessagesRequest {
-            model: request.model,
-            max_tokens: request.max_tokens,
-            messages: request.messages,
-            system: request.system,
-            tools: request.tools,
-            stream: true,
-        };
+        let cancel = request.cancel.clone();
+        let anthropic_request = convert_request(request)?;
 
-        let response = self
-            .http
-            .post(API_URL)
-            .headers(headers)
-            .json(&anthropic_request)
-            .send()
-            .await?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let message = response.text().await.unwrap_or_default();
-            return Err(AgentError::Api {
-                status: status.as_u16(),
-                message,
-            });
-        }
+        let url = format!("{}/v1/messages", self.base_url.trim_end_matches('/'));
+        let response = cancellable(
+            cancel.as_ref(),
+            self.retry.send(self.name(), || {
+                self.http
+                    .post(&url)
+                    .headers(headers.clone())
+                    .json(&anthropic_request)
+            }),
+        )
+        .await?;
 
-        let byte_stream = response.bytes_stream();
+        let events = sse_events(response.bytes_stream());
 
         let stream = async_stream::stream! {
-            let mut buffer = String::new();
             let mut current_blocks: Vec<ContentBlock> = Vec::new();
+            let mut partial_inputs: HashMap<usize, String> = HashMap::new();
+            let mut start_usage = Usage::default();
 
-            futures::pin_mut!(byte_stream);
-
-            while let Some(chunk) = byte_stream.next().await {
-                let chunk = chunk?;
-                buffer.push_str(&String::from_utf8_lossy(&chunk));
+            futures::pin_mut!(events);
 
-                // Process complete SSE events
-                while let Some((event_opt, remainder)) = parse_sse_event(&buffer) {
-                    buffer = remainder;
+            while let Some(sse) = events.next().await {
+                let event = match sse.and_then(|sse| parse_event(&sse.data)) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
 
-                    let Some(event) = event_opt else {
-                        continue;
-                    };
+                // Convert Anthropic events to generic completion events
+                match event {
+                    StreamEvent::MessageStart { message } => {
+                        start_usage = message.usage;
+                    }
 
-                    // Convert Anthropic events to generic completion events
-                    match event {
-                        StreamEvent::ContentBlockStart { index, content_block } => {
-                            // Ensure we have space
-                            while current_blocks.len() <= index {
-                                current_blocks.push(ContentBlock::Text { text: String::new() });
-                            }
-                            current_blocks[index] = content_block.clone();
+                    StreamEvent::ContentBlockStart { index, content_block } => {
+                        // Ensure we have space
+                        while current_blocks.len() <= index {
+                            current_blocks.push(ContentBlock::Text { text: String::new() });
+                        }
+                        current_blocks[index] = content_block.clone();
 
-                            if let ContentBlock::ToolUse { id, name, .. } = content_block {
-                                yield Ok(CompletionEvent::ToolUseStart { index, id, name });
-                            }
+                        if let ContentBlock::ToolUse { id, name, .. } = content_block {
+                            yield Ok(CompletionEvent::ToolUseStart { index, id, name });
                         }
+                    }
 
-                        StreamEvent::ContentBlockDelta { index, delta } => {
-                            match delta {
-                                Delta::TextDelta { text } => {
-                                    // Update accumulated block
-                                    if let Some(ContentBlock::Text { text: t }) = current_blocks.get_mut(index) {
-                                        t.push_str(&text);
-                                    }
-                                    yield Ok(CompletionEvent::TextDelta(text));
+                    StreamEvent::ContentBlockDelta { index, delta } => {
+                        match delta {
+                            Delta::TextDelta { text } => {
+                                // Update accumulated block
+                                if let Some(ContentBlock::Text { text: t }) = current_blocks.get_mut(index) {
+                                    t.push_str(&text);
                                 }
-                                Delta::InputJsonDelta { partial_json } => {
-                                    yield Ok(CompletionEvent::ToolInputDelta { index, partial_json });
+                                yield Ok(CompletionEvent::TextDelta(text));
+                            }
+                            Delta::InputJsonDelta { partial_json } => {
+                                partial_inputs.entry(index).or_default().push_str(&partial_json);
+                                yield Ok(CompletionEvent::ToolInputDelta { index, partial_json });
+                            }
+                            Delta::ThinkingDelta { thinking } => {
+                                if let Some(ContentBlock::Thinking { thinking: t, .. }) = current_blocks.get_mut(index) {
+                                    t.push_str(&thinking);
+                                }
+                                yield Ok(CompletionEvent::ThinkingDelta(thinking));
+                            }
+                            Delta::SignatureDelta { signature } => {
+                                if let Some(ContentBlock::Thinking { signature: s, .. }) = current_blocks.get_mut(index) {
+                                    *s = Some(signature);
                                 }
                             }
+                            Delta::Other => {}
                         }
+                    }
 
-                        StreamEvent::ContentBlockStop { index } => {
-                            if let Some(block) = current_blocks.get(index).cloned() {
-                                yield Ok(CompletionEvent::ContentBlockDone { index, block });
+                    StreamEvent::ContentBlockStop { index } => {
+                        // Fold streamed input JSON back into tool blocks
+                        if let (Some(ContentBlock::ToolUse { input, .. }), Some(json)) =
+                            (current_blocks.get_mut(index), partial_inputs.remove(&index))
+                            && !json.trim().is_empty()
+                        {
+                            match serde_json::from_str(&json) {
+                                Ok(value) => *input = value,
+                                Err(e) => {
+                                    tracing::debug!(index, error = %e, "invalid tool input JSON");
+                                }
                             }
                         }
 
-                        StreamEvent::MessageDelta { delta, usage } => {
-                            yield Ok(CompletionEvent::Done {
-                                stop_reason: delta.stop_reason,
-                                usage: Some(usage),
-                            });
+                        if let Some(block) = current_blocks.get(index).cloned() {
+                            yield Ok(CompletionEvent::ContentBlockDone { index, block });
                         }
+                    }
 
-                        StreamEvent::Error { error } => {
-                            yield Ok(CompletionEvent::Error(error.message));
+                    StreamEvent::MessageDelta { delta, mut usage } => {
+                        // `message_delta` may only carry output tokens
+                        if usage.input_tokens == 0 {
+                            usage.input_tokens = start_usage.input_tokens;
                         }
+                        if usage.cache_creation_input_tokens == 0 {
+                            usage.cache_creation_input_tokens = start_usage.cache_creation_input_tokens;
+                        }
+                        if usage.cache_read_input_tokens == 0 {
+                            usage.cache_read_input_tokens = start_usage.cache_read_input_tokens;
+                        }
+                        yield Ok(CompletionEvent::Done {
+                            stop_reason: delta.stop_reason,
+                            usage: Some(usage),
+                        });
+                    }
 
-                        _ => {}
+                    StreamEvent::Error { error } => {
+                        yield Ok(CompletionEvent::Error(error.message));
                     }
+
+                    _ => {}
                 }
             }
         };
 
-        Ok(Box::pin(stream))
+        Ok(cancellable_stream(Box::pin(stream), cancel))
     }
 }
 
-/// Parse a single SSE event from the buffer.
+/// Convert a completion request to the Messages API format.
 ///
-/// Returns the parsed event (if any) and the remaining buffer content.
-fn parse_sse_event(buffer: &str) -> Option<(Option<StreamEvent>, String)> {
-    // Find double newline (end of event)
-    let end = buffer.find("\n\n")?;
-    let event_str = &buffer[..end];
-    let remainder = buffer[end + 2..].to_string();
-
-    // Parse event
-    let mut data = None;
-
-    for line in event_str.lines() {
-        if let Some(rest) = line.strip_prefix("data: ") {
-            data = Some(rest.to_string());
+/// # Errors
+///
+/// Returns error if the cache breakpoints are invalid or a JSON schema
+/// response format is requested.
+fn convert_request(request: CompletionRequest) -> Result<MessagesRequest> {
+    if request.response_format.is_some() {
+        return Err(AgentError::Unsupported {
+            provider: "anthropic",
+            feature: "JSON schema response format",
+        });
+    }
+    if let Some(budget) = request.thinking_budget {
+        check_thinking(budget, &request)?;
+    }
+
+    let tool_choice =
+        convert_tool_choice(request.tool_choice.as_ref(), request.parallel_tool_calls);
+
+    let mut messages: Vec<MessagesMessage> = strip_unsigned_thinking(request.messages)
+        .into_iter()
+        .map(MessagesMessage::from)
+        .collect();
+    let mut system = request
+        .system
+        .map(|text| vec![Cached::from(ContentBlock::Text { text })]);
+    let mut tools = request
+        .tools
+        .map(|tools| tools.into_iter().map(Cached::from).collect::<Vec<_>>());
+
+    if let Some(cache) = request.cache {
+        let (cache_tools, cache_system, cache_messages) = match cache {
+            PromptCache::Auto => (
+                true,
+                true,
+                messages.len().checked_sub(1).into_iter().collect(),
+            ),
+            PromptCache::Breakpoints {
+                tools,
+                system,
+                messages,
+            } => (tools, system, messages),
+        };
+
+        let count = usize::from(cache_tools) + usize::from(cache_system) + cache_messages.len();
+        if count > MAX_CACHE_BREAKPOINTS {
+            return Err(AgentError::Config(format!(
+                "at most {MAX_CACHE_BREAKPOINTS} cache breakpoints are allowed, got {count}"
+            )));
+        }
+
+        if cache_tools {
+            mark_last(tools.as_deref_mut().unwrap_or_default(), |_| true);
+        }
+        if cache_system {
+            mark_last(system.as_deref_mut().unwrap_or_default(), |_| true);
+        }
+        for index in cache_messages {
+            let message = messages.get_mut(index).ok_or_else(|| {
+                AgentError::Config(format!("cache breakpoint on missing message {index}"))
+            })?;
+            // Thinking blocks cannot carry breakpoints
+            mark_last(&mut message.content, |block| {
+                !matches!(
+                    block,
+                    ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }
+                )
+            });
         }
     }
 
-    // Skip non-data events
-    let Some(data) = data else {
-        return Some((None, remainder));
+    Ok(MessagesRequest {
+        model: request.model,
+        max_tokens: request.max_tokens,
+        messages,
+        system,
+        tools,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        stop_sequences: request.stop_sequences,
+        tool_choice,
+        thinking: request
+            .thinking_budget
+            .map(|budget_tokens| ThinkingConfig::Enabled { budget_tokens }),
+        stream: true,
+    })
+}
+
+/// Check a thinking budget against the limits the Messages API enforces.
+///
+/// The budget must be at least 1024 tokens and below `max_tokens`, and
+/// sampling parameters cannot be changed while thinking is enabled.
+const fn check_thinking(budget: u32, request: &CompletionRequest) -> Result<()> {
+    let feature = if request.temperature.is_some() || request.top_p.is_some() {
+        "temperature or top_p with extended thinking"
+    } else if budget < MIN_THINKING_BUDGET {
+        "thinking budgets below 1024 tokens"
+    } else if budget >= request.max_tokens {
+        "thinking budgets of max_tokens or more"
+    } else {
+        return Ok(());
     };
+    Err(AgentError::Unsupported {
+        provider: "anthropic",
+        feature,
+    })
+}
+
+/// Put a cache breakpoint on the last item that accepts one.
+fn mark_last<T>(items: &mut [Cached<T>], cacheable: impl Fn(&T) -> bool) {
+    if let Some(cached) = items
+        .iter_mut()
+        .rev()
+        .find(|cached| cacheable(&cached.item))
+    {
+        cached.cache_control = Some(CacheControl::Ephemeral);
+    }
+}
+
+/// Convert tool choice and parallel tool use to the Messages API format.
+///
+/// Parallel tool use is controlled through `tool_choice`, so disabling it
+/// without an explicit choice implies `auto`.
+fn convert_tool_choice(
+    choice: Option<&ToolChoice>,
+    parallel_tool_calls: Option<bool>,
+) -> Option<MessagesToolChoice> {
+    let disable_parallel_tool_use = parallel_tool_calls.map(|parallel| !parallel);
+
+    match choice {
+        None if disable_parallel_tool_use.is_none() => None,
+        None | Some(ToolChoice::Auto) => Some(MessagesToolChoice::Auto {
+            disable_parallel_tool_use,
+        }),
+        Some(ToolChoice::Any) => Some(MessagesToolChoice::Any {
+            disable_parallel_tool_use,
+        }),
+        Some(ToolChoice::Tool(name)) => Some(MessagesToolChoice::Tool {
+            name: name.clone(),
+            disable_parallel_tool_use,
+        }),
+        Some(ToolChoice::None) => Some(MessagesToolChoice::None),
+    }
+}
 
-    // Parse JSON
-    match serde_json::from_str::<StreamEvent>(&data) {
-        Ok(event) => Some((Some(event), remainder)),
-        Err(e) => {
-            tracing::debug!(data = %data, error = %e, "failed to parse event");
-            Some((None, remainder))
+/// Drop thinking blocks without a signature.
+///
+/// The API rejects unsigned thinking, such as reasoning recorded from
+/// another provider, while signed blocks must be sent back unchanged.
+fn strip_unsigned_thinking(mut messages: Vec<Message>) -> Vec<Message> {
+    for message in &mut messages {
+        if let Content::Blocks(blocks) = &mut message.content {
+            blocks.retain(|b| {
+                !matches!(
+                    b,
+                    ContentBlock::Thinking {
+                        signature: None,
+                        ..
+                    }
+                )
+            });
         }
     }
+    messages
+}
+
+/// Parse the data of one SSE event.
+fn parse_event(data: &str) -> Result<StreamEvent> {
+    serde_json::from_str(data)
+        .map_err(|e| AgentError::Parse(format!("invalid Anthropic stream event: {e}")))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sse::SseDecoder;
 
     #[test]
     fn provider_requires_api_key() {
@@ -203,4 +411,239 @@ mod tests {
         let result = AnthropicProvider::new("test-key");
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn thinking_events_parse() {
+        let buffer = "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"Hmm\"}}\n\n";
+        let events = SseDecoder::new().feed(buffer.as_bytes()).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            parse_event(&events[0].data),
+            Ok(StreamEvent::ContentBlockDelta {
+                delta: Delta::ThinkingDelta { thinking },
+                ..
+            }) if thinking == "Hmm"
+        ));
+
+        // Event types added to the API later are skipped, bad JSON is not
+        assert!(matches!(
+            parse_event(
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"citations_delta"}}"#
+            ),
+            Ok(StreamEvent::ContentBlockDelta {
+                delta: Delta::Other,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_event(r#"{"type":"new_event"}"#),
+            Ok(StreamEvent::Other)
+        ));
+        assert!(matches!(parse_event("{"), Err(AgentError::Parse(_))));
+    }
+
+    #[test]
+    fn unsigned_thinking_is_not_sent() {
+        let messages = strip_unsigned_thinking(vec![Message {
+            role: crate::types::Role::Assistant,
+            content: Content::Blocks(vec![
+                ContentBlock::Thinking {
+                    thinking: "from another provider".to_string(),
+                    signature: None,
+                },
+                ContentBlock::Thinking {
+                    thinking: "signed".to_string(),
+                    signature: Some("sig".to_string()),
+                },
+                ContentBlock::Text {
+                    text: "Hi".to_string(),
+                },
+            ]),
+        }]);
+        let Content::Blocks(blocks) = &messages[0].content else {
+            panic!("expected blocks");
+        };
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::Thinking {
+                signature: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn thinking_budget_is_checked_before_sending() {
+        let request = |thinking_budget, temperature| CompletionRequest {
+            model: "claude-sonnet-4-5".to_string(),
+            max_tokens: 4096,
+            thinking_budget: Some(thinking_budget),
+            temperature,
+            ..Default::default()
+        };
+
+        let ok = convert_request(request(2048, None)).unwrap();
+        assert_eq!(
+            ok.thinking,
+            Some(ThinkingConfig::Enabled {
+                budget_tokens: 2048
+            })
+        );
+        for rejected in [
+            request(512, None),
+            request(4096, None),
+            request(2048, Some(0.5)),
+        ] {
+            assert!(matches!(
+                convert_request(rejected),
+                Err(AgentError::Unsupported { .. })
+            ));
+        }
+    }
+
+    fn cache_request(cache: PromptCache) -> CompletionRequest {
+        CompletionRequest {
+            model: "claude-sonnet-4-5".to_string(),
+            max_tokens: 100,
+            system: Some("You are helpful.".to_string()),
+            tools: Some(vec![crate::types::Tool {
+                name: "Read".to_string(),
+                description: "Read a file".to_string(),
+                input_schema: serde_json::json!({"type": "object"}),
+            }]),
+            messages: vec![
+                Message {
+                    role: crate::types::Role::User,
+                    content: Content::Text("Hi".to_string()),
+                },
+                Message {
+                    role: crate::types::Role::Assistant,
+                    content: Content::Blocks(vec![
+                        ContentBlock::Text {
+                            text: "Hello".to_string(),
+                        },
+                        ContentBlock::Thinking {
+                            thinking: "done".to_string(),
+                            signature: Some("sig".to_string()),
+                        },
+                    ]),
+                },
+            ],
+            cache: Some(cache),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn auto_cache_marks_stable_prefix() {
+        let request = convert_request(cache_request(PromptCache::Auto)).unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+
+        let ephemeral = serde_json::json!({"type": "ephemeral"});
+        assert_eq!(json["tools"][0]["cache_control"], ephemeral);
+        assert_eq!(json["tools"][0]["name"], "Read");
+        assert_eq!(json["system"][0]["cache_control"], ephemeral);
+        assert_eq!(json["system"][0]["text"], "You are helpful.");
+        assert!(
+            json["messages"][0]["content"][0]
+                .get("cache_control")
+                .is_none()
+        );
+        // The trailing thinking block is skipped
+        assert_eq!(
+            json["messages"][1]["content"][0]["cache_control"],
+            ephemeral
+        );
+        assert!(
+            json["messages"][1]["content"][1]
+                .get("cache_control")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn explicit_cache_breakpoints_are_validated() {
+        let request = convert_request(cache_request(PromptCache::Breakpoints {
+            tools: false,
+            system: true,
+            messages: vec![0],
+        }))
+        .unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json["tools"][0].get("cache_control").is_none());
+        assert!(json["system"][0].get("cache_control").is_some());
+        assert!(
+            json["messages"][0]["content"][0]
+                .get("cache_control")
+                .is_some()
+        );
+
+        let too_many = convert_request(cache_request(PromptCache::Breakpoints {
+            tools: true,
+            system: true,
+            messages: vec![0, 1, 1],
+        }));
+        assert!(matches!(too_many, Err(AgentError::Config(_))));
+
+        let missing = convert_request(cache_request(PromptCache::Breakpoints {
+            tools: false,
+            system: false,
+            messages: vec![5],
+        }));
+        assert!(matches!(missing, Err(AgentError::Config(_))));
+    }
+
+    #[test]
+    fn usage_reports_cache_tokens() {
+        let usage: Usage = serde_json::from_value(serde_json::json!({
+            "input_tokens": 10,
+            "output_tokens": 2,
+            "cache_creation_input_tokens": 100,
+            "cache_read_input_tokens": 2000
+        }))
+        .unwrap();
+        assert_eq!(usage.cache_creation_input_tokens, 100);
+        assert_eq!(usage.cache_read_input_tokens, 2000);
+    }
+
+    #[test]
+    fn tool_choice_omitted_by_default() {
+        assert_eq!(convert_tool_choice(None, None), None);
+    }
+
+    #[test]
+    fn disabling_parallel_tools_implies_auto() {
+        let choice = convert_tool_choice(None, Some(false)).unwrap();
+        assert_eq!(
+            serde_json::to_value(choice).unwrap(),
+            serde_json::json!({"type": "auto", "disable_parallel_tool_use": true})
+        );
+
+        let request = convert_request(CompletionRequest {
+            model: "claude-sonnet-4-5".to_string(),
+            max_tokens: 1024,
+            parallel_tool_calls: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(request).unwrap()["tool_choice"],
+            serde_json::json!({"type": "auto", "disable_parallel_tool_use": true})
+        );
+    }
+
+    #[test]
+    fn specific_tool_choice_serializes_name() {
+        let choice = convert_tool_choice(Some(&ToolChoice::Tool("Bash".to_string())), None);
+        assert_eq!(
+            serde_json::to_value(choice.unwrap()).unwrap(),
+            serde_json::json!({"type": "tool", "name": "Bash"})
+        );
+        let none = convert_tool_choice(Some(&ToolChoice::None), Some(true));
+        assert_eq!(
+            serde_json::to_value(none.unwrap()).unwrap(),
+            serde_json::json!({"type": "none"})
+        );
+    }
 }
@@ -0,0 +1,307 @@
+//! Fallback provider that fails over between backends.
+//!
+//! Backends are tried in order until one starts streaming. Failover only
+//! happens before any event reaches the caller, so a completion is never
+//! stitched together from two models.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use crate::error::{AgentError, Result};
+use crate::provider::{Backend, CompletionEvent, CompletionRequest, CompletionStream, LlmProvider};
+
+/// A provider and the model to request from it.
+struct FallbackBackend {
+    name: String,
+    provider: Arc<dyn LlmProvider>,
+    model: String,
+}
+
+/// Composite provider that tries an ordered list of backends.
+///
+/// Each backend overrides the request model with its own. The stream
+/// starts with a [`CompletionEvent::Backend`] naming the backend that
+/// served the request.
+#[derive(Default)]
+pub struct FallbackProvider {
+    backends: Vec<FallbackBackend>,
+}
+
+impl std::fmt::Debug for FallbackProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FallbackProvider")
+            .field(
+                "backends",
+                &self
+                    .backends
+                    .iter()
+                    .map(|b| format!("{}/{}", b.name, b.model))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl FallbackProvider {
+    /// Create an empty chain.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a backend to the chain.
+    ///
+    /// `name` identifies the backend in reports; it is usually the
+    /// registry name of the provider.
+    #[must_use]
+    pub fn with_backend(
+        mut self,
+        name: impl Into<String>,
+        provider: Arc<dyn LlmProvider>,
+        model: impl Into<String>,
+    ) -> Self {
+        self.backends.push(FallbackBackend {
+            name: name.into(),
+            provider,
+            model: model.into(),
+        });
+        self
+    }
+
+    /// Number of backends in the chain.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.backends.len()
+    }
+
+    /// Check whether the chain has no backends.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.backends.is_empty()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FallbackProvider {
+    fn name(&self) -> &'static str {
+        "fallback"
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let mut last_error = None;
+
+        for backend in &self.backends {
+            let request = CompletionRequest {
+                model: backend.model.clone(),
+                ..request.clone()
+            };
+
+            let error = match backend.provider.stream(request).await {
+                Ok(mut stream) => {
+                    // Errors before the first event can still fail over
+                    let first = stream.next().await;
+                    match first {
+                        Some(Err(e)) if should_fail_over(&e) => e,
+                        Some(Ok(CompletionEvent::Error(message))) => AgentError::Transport(message),
+                        first => {
+                            let served = Backend {
+                                provider: backend.name.clone(),
+                                model: backend.model.clone(),
+                            };
+                            tracing::debug!(provider = %served.provider, model = %served.model, "backend serving request");
+                            let head =
+                                std::iter::once(Ok(CompletionEvent::Backend(served))).chain(first);
+                            return Ok(Box::pin(futures::stream::iter(head).chain(stream)));
+                        }
+                    }
+                }
+                Err(e) if should_fail_over(&e) => e,
+                Err(e) => return Err(e),
+            };
+
+            tracing::warn!(
+                provider = %backend.name,
+                model = %backend.model,
+                error = %error,
+                "backend failed, trying next"
+            );
+            last_error = Some(error);
+        }
+
+        Err(last_error.unwrap_or_else(|| AgentError::Config("fallback chain is empty".into())))
+    }
+}
+
+/// Check whether an error means another backend may succeed.
+///
+/// Connection failures, timeouts, rate limits, overload and streams that
+/// break before starting qualify; request-building, body and
+/// authentication errors would fail the same way elsewhere.
+fn should_fail_over(error: &AgentError) -> bool {
+    match error {
+        AgentError::Http(e) => {
+            e.is_connect()
+                || e.is_timeout()
+                || e.status()
+                    .is_some_and(|status| fails_over_on(status.as_u16()))
+        }
+        AgentError::Transport(_) | AgentError::StreamEnded => true,
+        AgentError::Api { status, .. } => fails_over_on(*status),
+        _ => false,
+    }
+}
+
+/// Check whether an HTTP status is worth retrying on another backend.
+const fn fails_over_on(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500..=599)
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::provider::collect;
+    use crate::test_support::StubServer;
+
+    /// Provider that fails with a fixed error or echoes the model name.
+    struct StubProvider {
+        error: Option<fn() -> AgentError>,
+        models: Mutex<Vec<String>>,
+    }
+
+    impl StubProvider {
+        fn ok() -> Arc<Self> {
+            Arc::new(Self {
+                error: None,
+                models: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn failing(error: fn() -> AgentError) -> Arc<Self> {
+            Arc::new(Self {
+                error: Some(error),
+                models: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl LlmProvider for StubProvider {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+            self.models.lock().push(request.model.clone());
+            if let Some(error) = self.error {
+                return Err(error());
+            }
+            Ok(Box::pin(futures::stream::iter(vec![
+                Ok(CompletionEvent::TextDelta(request.model)),
+                Ok(CompletionEvent::Done {
+                    stop_reason: None,
+                    usage: None,
+                }),
+            ])))
+        }
+    }
+
+    fn overloaded() -> AgentError {
+        AgentError::Api {
+            status: 529,
+            message: "overloaded".into(),
+        }
+    }
+
+    fn request() -> CompletionRequest {
+        CompletionRequest {
+            model: "ignored".into(),
+            max_tokens: 10,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_over_on_overload_and_reports_backend() {
+        let primary = StubProvider::failing(overloaded);
+        let secondary = StubProvider::ok();
+        let chain = FallbackProvider::new()
+            .with_backend("anthropic", primary.clone(), "claude-sonnet-4-5")
+            .with_backend("openai", secondary.clone(), "gpt-4o");
+
+        let response = collect(chain.stream(request()).await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.text(), "gpt-4o");
+        assert_eq!(
+            response.backend,
+            Some(Backend {
+                provider: "openai".into(),
+                model: "gpt-4o".into(),
+            })
+        );
+        assert_eq!(*primary.models.lock(), vec!["claude-sonnet-4-5"]);
+    }
+
+    #[tokio::test]
+    async fn request_errors_do_not_fail_over() {
+        let secondary = StubProvider::ok();
+        let chain = FallbackProvider::new()
+            .with_backend(
+                "anthropic",
+                StubProvider::failing(|| AgentError::Api {
+                    status: 400,
+                    message: "bad request".into(),
+                }),
+                "claude-sonnet-4-5",
+            )
+            .with_backend("openai", secondary.clone(), "gpt-4o");
+
+        let result = chain.stream(request()).await;
+
+        assert!(matches!(result, Err(AgentError::Api { status: 400, .. })));
+        assert!(secondary.models.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn request_body_errors_do_not_fail_over() {
+        // The server never answers, so only the body can fail
+        let server = StubServer::start(|_, _| None).await;
+        let body = reqwest::Body::wrap_stream(futures::stream::once(async {
+            Err::<Vec<u8>, _>(std::io::Error::other("body failed"))
+        }));
+
+        let error = reqwest::Client::new()
+            .post(server.url())
+            .body(body)
+            .send()
+            .await
+            .unwrap_err();
+
+        assert!(error.is_request());
+        assert!(!should_fail_over(&AgentError::Http(error)));
+    }
+
+    #[tokio::test]
+    async fn returns_last_error_when_all_fail() {
+        let chain = FallbackProvider::new()
+            .with_backend("a", StubProvider::failing(overloaded), "m1")
+            .with_backend(
+                "b",
+                StubProvider::failing(|| AgentError::Transport("reset".into())),
+                "m2",
+            );
+
+        let result = chain.stream(request()).await;
+
+        assert!(matches!(result, Err(AgentError::Transport(_))));
+        assert!(matches!(
+            FallbackProvider::new().stream(request()).await,
+            Err(AgentError::Config(_))
+        ));
+    }
+}
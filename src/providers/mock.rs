@@ -0,0 +1,144 @@
+//! Scripted provider for offline tests.
+
+use std::collections::VecDeque;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use crate::error::{AgentError, Result};
+use crate::provider::{
+    CompletionEvent, CompletionRequest, CompletionStream, LlmProvider, cancellable_stream,
+};
+use crate::types::{ContentBlock, StopReason};
+
+/// Provider that streams one scripted event sequence per call.
+///
+/// Responses are served in the order they were added, and every request
+/// is kept for later inspection. Calling past the end of the script is
+/// an error, so tests notice unexpected extra turns.
+#[derive(Debug, Default)]
+pub struct MockProvider {
+    responses: Mutex<VecDeque<Vec<CompletionEvent>>>,
+    requests: Mutex<Vec<CompletionRequest>>,
+}
+
+impl MockProvider {
+    /// Create a provider with an empty script.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a response made of raw events.
+    #[must_use]
+    pub fn with_response(self, events: Vec<CompletionEvent>) -> Self {
+        self.responses.lock().push_back(events);
+        self
+    }
+
+    /// Append a response that ends the turn with text.
+    #[must_use]
+    pub fn with_text(self, text: impl Into<String>) -> Self {
+        let text = text.into();
+        self.with_response(vec![
+            CompletionEvent::TextDelta(text.clone()),
+            CompletionEvent::ContentBlockDone {
+                index: 0,
+                block: ContentBlock::Text { text },
+            },
+            CompletionEvent::Done {
+                stop_reason: Some(StopReason::EndTurn),
+                usage: None,
+            },
+        ])
+    }
+
+    /// Append a response that calls a tool.
+    #[must_use]
+    pub fn with_tool_use(
+        self,
+        id: impl Into<String>,
+        name: impl Into<String>,
+        input: serde_json::Value,
+    ) -> Self {
+        let (id, name) = (id.into(), name.into());
+        self.with_response(vec![
+            CompletionEvent::ToolUseStart {
+                index: 0,
+                id: id.clone(),
+                name: name.clone(),
+            },
+            CompletionEvent::ContentBlockDone {
+                index: 0,
+                block: ContentBlock::ToolUse { id, name, input },
+            },
+            CompletionEvent::Done {
+                stop_reason: Some(StopReason::ToolUse),
+                usage: None,
+            },
+        ])
+    }
+
+    /// Get the requests received so far.
+    #[must_use]
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().clone()
+    }
+
+    /// Number of scripted responses not yet served.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.responses.lock().len()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let cancel = request.cancel.clone();
+        self.requests.lock().push(request);
+        let events = self.responses.lock().pop_front().ok_or_else(|| {
+            AgentError::Config("mock provider has no scripted response left".into())
+        })?;
+        let stream = futures::stream::iter(events.into_iter().map(Ok));
+        Ok(cancellable_stream(Box::pin(stream), cancel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::collect;
+
+    #[tokio::test]
+    async fn serves_script_in_order_and_records_requests() {
+        let provider = MockProvider::new()
+            .with_tool_use("call_1", "Read", serde_json::json!({"path": "a"}))
+            .with_text("done");
+
+        let first = collect(provider.stream(CompletionRequest::default()).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(first.stop_reason, Some(StopReason::ToolUse));
+
+        let request = CompletionRequest {
+            model: "m".into(),
+            ..Default::default()
+        };
+        let second = collect(provider.stream(request).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(second.text(), "done");
+
+        assert_eq!(provider.remaining(), 0);
+        assert_eq!(provider.requests()[1].model, "m");
+        assert!(matches!(
+            provider.stream(CompletionRequest::default()).await,
+            Err(AgentError::Config(_))
+        ));
+    }
+}
@@ -1,9 +1,21 @@
 //! LLM provider implementations.
 
 mod anthropic;
+mod fallback;
+mod mock;
+mod ollama;
 mod openai;
+mod openai_responses;
+mod replay;
+mod retry;
 mod unified;
 
 pub use anthropic::AnthropicProvider;
+pub use fallback::FallbackProvider;
+pub use mock::MockProvider;
+pub use ollama::{KeepAlive, OllamaModel, OllamaModelDetails, OllamaOptions, OllamaProvider};
 pub use openai::OpenAiProvider;
+pub use openai_responses::OpenAiResponsesProvider;
+pub use replay::{RecordingProvider, ReplayProvider};
+pub use retry::RetryPolicy;
 pub use unified::UnifiedProvider;
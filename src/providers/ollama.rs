@@ -0,0 +1,863 @@
+//! Native Ollama provider.
+//!
+//! Talks to the `/api/chat` endpoint instead of the OpenAI-compatible
+//! `/v1` layer, which keeps Ollama-specific controls such as model
+//! options, `keep_alive` and thinking. Also lists installed models and
+//! pulls missing ones.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AgentError, Result};
+use crate::provider::{
+    CompletionEvent, CompletionRequest, CompletionStream, LlmProvider, ToolChoice, cancellable,
+    cancellable_stream,
+};
+use crate::providers::RetryPolicy;
+use crate::types::{Content, ContentBlock, MediaSource, Message, Role, StopReason, Tool, Usage};
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+/// Block index of streamed reasoning.
+const THINKING_INDEX: usize = 0;
+
+/// Block index of streamed text.
+const TEXT_INDEX: usize = 1;
+
+/// How long Ollama keeps a model loaded after a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    /// Unload after the model has been idle this long.
+    Duration(Duration),
+    /// Keep the model loaded until the server stops.
+    Forever,
+    /// Unload as soon as the request finishes.
+    Unload,
+}
+
+impl Serialize for KeepAlive {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Self::Duration(duration) => {
+                serializer.serialize_str(&format!("{}s", duration.as_secs()))
+            }
+            Self::Forever => serializer.serialize_i64(-1),
+            Self::Unload => serializer.serialize_i64(0),
+        }
+    }
+}
+
+/// Model runtime options.
+///
+/// Request fields such as `max_tokens`, `temperature`, `top_p` and
+/// `stop_sequences` override the matching option.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OllamaOptions {
+    /// Context window size in tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// Maximum tokens to generate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<u32>,
+    /// Sampling temperature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability mass.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Sample only from the most likely tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    /// Minimum probability relative to the most likely token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    /// Penalty for repeated tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    /// Random seed for reproducible output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Sequences that stop generation when produced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Number of layers to offload to the GPU.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_gpu: Option<u32>,
+    /// Number of CPU threads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_thread: Option<u32>,
+}
+
+/// A model installed on the Ollama server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OllamaModel {
+    /// Model name including tag (e.g. "llama3.2:latest").
+    pub name: String,
+    /// Size on disk in bytes.
+    #[serde(default)]
+    pub size: u64,
+    /// Content digest.
+    #[serde(default)]
+    pub digest: String,
+    /// Last modification time as reported by the server.
+    #[serde(default)]
+    pub modified_at: Option<String>,
+    /// Model family and quantization details.
+    #[serde(default)]
+    pub details: Option<OllamaModelDetails>,
+}
+
+/// Model family and quantization details.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OllamaModelDetails {
+    /// Model family (e.g. "llama").
+    #[serde(default)]
+    pub family: Option<String>,
+    /// Parameter count (e.g. "3.2B").
+    #[serde(default)]
+    pub parameter_size: Option<String>,
+    /// Quantization level (e.g. "`Q4_K_M`").
+    #[serde(default)]
+    pub quantization_level: Option<String>,
+}
+
+/// LLM provider for the native Ollama API.
+#[derive(Debug, Clone)]
+pub struct OllamaProvider {
+    http: reqwest::Client,
+    base_url: String,
+    options: OllamaOptions,
+    keep_alive: Option<KeepAlive>,
+    retry: RetryPolicy,
+}
+
+impl Default for OllamaProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OllamaProvider {
+    /// Create a provider for a local Ollama server.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            options: OllamaOptions::default(),
+            keep_alive: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Override the server URL.
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Use a preconfigured HTTP client, e.g. with proxy or timeout settings.
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Set the default model options.
+    #[must_use]
+    pub fn with_options(mut self, options: OllamaOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the context window size.
+    #[must_use]
+    pub const fn with_num_ctx(mut self, num_ctx: u32) -> Self {
+        self.options.num_ctx = Some(num_ctx);
+        self
+    }
+
+    /// Set how long the model stays loaded after each request.
+    #[must_use]
+    pub const fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Set the retry policy for failed requests.
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// List the models installed on the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the server is unreachable or the response is
+    /// malformed.
+    pub async fn list_models(&self) -> Result<Vec<OllamaModel>> {
+        #[derive(Deserialize)]
+        struct Tags {
+            #[serde(default)]
+            models: Vec<OllamaModel>,
+        }
+
+        let url = format!("{}/api/tags", self.base_url);
+        let response = self.retry.send(self.name(), || self.http.get(&url)).await?;
+        let tags: Tags = response
+            .json()
+            .await
+            .map_err(|e| AgentError::Parse(format!("invalid model list: {e}")))?;
+        Ok(tags.models)
+    }
+
+    /// Download a model, waiting until the pull completes.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the server is unreachable or reports a failure,
+    /// such as an unknown model name.
+    pub async fn pull_model(&self, model: &str) -> Result<()> {
+        let url = format!("{}/api/pull", self.base_url);
+        let body = serde_json::json!({ "model": model, "stream": true });
+        let response = self
+            .retry
+            .send(self.name(), || self.http.post(&url).json(&body))
+            .await?;
+
+        let mut lines = Box::pin(ndjson_lines(response.bytes_stream()));
+        while let Some(line) = lines.next().await {
+            let progress: PullProgress = parse_line(&line?)?;
+            if let Some(error) = progress.error {
+                return Err(AgentError::Api {
+                    status: 500,
+                    message: error,
+                });
+            }
+            tracing::debug!(model, status = %progress.status, "ollama: pull progress");
+            if progress.status == "success" {
+                return Ok(());
+            }
+        }
+        Err(AgentError::StreamEnded)
+    }
+
+    /// Pull a model unless it is already installed.
+    ///
+    /// Returns whether a pull was needed. Names without a tag match the
+    /// `latest` tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if listing or pulling fails.
+    pub async fn ensure_model(&self, model: &str) -> Result<bool> {
+        let installed = self.list_models().await?;
+        if installed.iter().any(|m| same_model(&m.name, model)) {
+            return Ok(false);
+        }
+        self.pull_model(model).await?;
+        Ok(true)
+    }
+
+    /// Build the wire request.
+    fn convert_request(&self, request: CompletionRequest) -> Result<OllamaRequest> {
+        let tools = match request.tool_choice {
+            None | Some(ToolChoice::Auto) => request.tools.as_deref().map(convert_tools),
+            Some(ToolChoice::None) => None,
+            Some(ToolChoice::Any | ToolChoice::Tool(_)) => {
+                return Err(AgentError::Unsupported {
+                    provider: "ollama",
+                    feature: "forced tool choice",
+                });
+            }
+        };
+        if request.parallel_tool_calls == Some(false) {
+            return Err(AgentError::Unsupported {
+                provider: "ollama",
+                feature: "disabling parallel tool calls",
+            });
+        }
+
+        let mut options = self.options.clone();
+        if request.max_tokens > 0 {
+            options.num_predict = Some(request.max_tokens);
+        }
+        options.temperature = request.temperature.or(options.temperature);
+        options.top_p = request.top_p.or(options.top_p);
+        options.stop = request.stop_sequences.or(options.stop);
+
+        Ok(OllamaRequest {
+            model: request.model,
+            messages: convert_messages(&request.messages, request.system.as_deref())?,
+            tools,
+            format: request.response_format.map(|format| format.schema),
+            think: request.thinking_budget.map(|_| true),
+            options,
+            keep_alive: self.keep_alive,
+            stream: true,
+        })
+    }
+}
+
+// Ollama request types
+
+#[derive(Debug, Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OllamaTool>>,
+    /// JSON schema the response must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    think: Option<bool>,
+    options: OllamaOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<KeepAlive>,
+    stream: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thinking: Option<String>,
+    /// Base64-encoded images.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+    /// Name of the tool whose result this message carries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaToolCall {
+    /// Call ID, sent by newer servers only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    function: OllamaFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaTool {
+    #[serde(rename = "type")]
+    tool_type: &'static str,
+    function: OllamaFunction,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+// Ollama response types
+
+#[derive(Debug, Deserialize)]
+struct OllamaChunk {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: u32,
+    #[serde(default)]
+    eval_count: u32,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullProgress {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Convert tools to Ollama function definitions.
+fn convert_tools(tools: &[Tool]) -> Vec<OllamaTool> {
+    tools
+        .iter()
+        .map(|t| OllamaTool {
+            tool_type: "function",
+            function: OllamaFunction {
+                name: t.name.clone(),
+                description: t.description.clone(),
+                parameters: t.input_schema.clone(),
+            },
+        })
+        .collect()
+}
+
+/// Convert messages to Ollama format.
+///
+/// Tool results become `tool` messages named after the call they answer,
+/// since Ollama matches results to calls by tool name and order.
+fn convert_messages(messages: &[Message], system: Option<&str>) -> Result<Vec<OllamaMessage>> {
+    let mut result = Vec::new();
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+
+    if let Some(system) = system {
+        result.push(OllamaMessage {
+            role: "system".to_string(),
+            content: system.to_string(),
+            ..Default::default()
+        });
+    }
+
+    for msg in messages {
+        let blocks = match &msg.content {
+            Content::Text(text) => {
+                result.push(OllamaMessage {
+                    role: role_name(msg.role).to_string(),
+                    content: text.clone(),
+                    ..Default::default()
+                });
+                continue;
+            }
+            Content::Blocks(blocks) => blocks,
+        };
+
+        let mut message = OllamaMessage {
+            role: role_name(msg.role).to_string(),
+            ..Default::default()
+        };
+        let mut tool_results = Vec::new();
+
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text } => message.content.push_str(text),
+                ContentBlock::Thinking { thinking, .. } => {
+                    message
+                        .thinking
+                        .get_or_insert_with(String::new)
+                        .push_str(thinking);
+                }
+                ContentBlock::Image { source } => message.images.push(image_data(source)?),
+                ContentBlock::ToolUse { id, name, input } => {
+                    tool_names.insert(id, name);
+                    message.tool_calls.push(OllamaToolCall {
+                        id: Some(id.clone()),
+                        function: OllamaFunctionCall {
+                            name: name.clone(),
+                            arguments: input.clone(),
+                        },
+                    });
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    ..
+                } => {
+                    let mut tool = OllamaMessage {
+                        role: "tool".to_string(),
+                        content: content.text(),
+                        tool_name: tool_names
+                            .get(tool_use_id.as_str())
+                            .map(|n| (*n).to_string()),
+                        ..Default::default()
+                    };
+                    if let Content::Blocks(blocks) = content {
+                        for block in blocks {
+                            if let ContentBlock::Image { source } = block {
+                                tool.images.push(image_data(source)?);
+                            }
+                        }
+                    }
+                    tool_results.push(tool);
+                }
+                ContentBlock::Document { .. } => {
+                    return Err(AgentError::Unsupported {
+                        provider: "ollama",
+                        feature: "documents",
+                    });
+                }
+                ContentBlock::RedactedThinking { .. } => {}
+            }
+        }
+
+        let has_content = !message.content.is_empty()
+            || !message.images.is_empty()
+            || !message.tool_calls.is_empty()
+            || message.thinking.is_some();
+        if has_content {
+            result.push(message);
+        }
+        result.extend(tool_results);
+    }
+
+    Ok(result)
+}
+
+const fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    }
+}
+
+/// Get the base64 payload of an inline image.
+fn image_data(source: &MediaSource) -> Result<String> {
+    match source {
+        MediaSource::Base64 { data, .. } => Ok(data.clone()),
+        MediaSource::Url { .. } => Err(AgentError::Unsupported {
+            provider: "ollama",
+            feature: "image URLs",
+        }),
+    }
+}
+
+/// Check whether an installed model name matches a requested one.
+fn same_model(installed: &str, requested: &str) -> bool {
+    installed == requested
+        || (!requested.contains(':') && installed.strip_suffix(":latest") == Some(requested))
+}
+
+fn parse_line<T: serde::de::DeserializeOwned>(line: &str) -> Result<T> {
+    serde_json::from_str(line)
+        .map_err(|e| AgentError::Parse(format!("invalid Ollama response line: {e}")))
+}
+
+/// Split a byte stream into newline-delimited lines.
+///
+/// Buffers bytes rather than text so multi-byte characters split across
+/// chunks decode correctly.
+fn ndjson_lines<B: AsRef<[u8]> + Send>(
+    bytes: impl futures::Stream<Item = reqwest::Result<B>> + Send + 'static,
+) -> impl futures::Stream<Item = Result<String>> + Send {
+    async_stream::stream! {
+        let mut buffer = Vec::new();
+        futures::pin_mut!(bytes);
+        while let Some(chunk) = bytes.next().await {
+            match chunk {
+                Ok(chunk) => buffer.extend_from_slice(chunk.as_ref()),
+                Err(e) => {
+                    yield Err(AgentError::Http(e));
+                    return;
+                }
+            }
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                if !line.is_empty() {
+                    yield Ok(line);
+                }
+            }
+        }
+        let rest = String::from_utf8_lossy(&buffer).trim().to_string();
+        if !rest.is_empty() {
+            yield Ok(rest);
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let cancel = request.cancel.clone();
+        let ollama_request = self.convert_request(request)?;
+
+        let url = format!("{}/api/chat", self.base_url);
+        let response = cancellable(
+            cancel.as_ref(),
+            self.retry
+                .send(self.name(), || self.http.post(&url).json(&ollama_request)),
+        )
+        .await?;
+
+        let mut lines = Box::pin(ndjson_lines(response.bytes_stream()));
+
+        let stream = async_stream::stream! {
+            let mut thinking = String::new();
+            let mut text = String::new();
+            let mut tool_calls = Vec::new();
+
+            while let Some(line) = lines.next().await {
+                let chunk: OllamaChunk = match line.and_then(|line| parse_line(&line)) {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+
+                if let Some(error) = chunk.error {
+                    yield Ok(CompletionEvent::Error(error));
+                    return;
+                }
+
+                if let Some(message) = chunk.message {
+                    if let Some(delta) = message.thinking.filter(|t| !t.is_empty()) {
+                        thinking.push_str(&delta);
+                        yield Ok(CompletionEvent::ThinkingDelta(delta));
+                    }
+                    if !message.content.is_empty() {
+                        text.push_str(&message.content);
+                        yield Ok(CompletionEvent::TextDelta(message.content));
+                    }
+                    for call in message.tool_calls {
+                        let index = TEXT_INDEX + 1 + tool_calls.len();
+                        let id = call
+                            .id
+                            .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple()));
+                        yield Ok(CompletionEvent::ToolUseStart {
+                            index,
+                            id: id.clone(),
+                            name: call.function.name.clone(),
+                        });
+                        tool_calls.push(ContentBlock::ToolUse {
+                            id,
+                            name: call.function.name,
+                            input: call.function.arguments,
+                        });
+                    }
+                }
+
+                if chunk.done {
+                    if !thinking.is_empty() {
+                        yield Ok(CompletionEvent::ContentBlockDone {
+                            index: THINKING_INDEX,
+                            block: ContentBlock::Thinking {
+                                thinking: std::mem::take(&mut thinking),
+                                signature: None,
+                            },
+                        });
+                    }
+                    if !text.is_empty() {
+                        yield Ok(CompletionEvent::ContentBlockDone {
+                            index: TEXT_INDEX,
+                            block: ContentBlock::Text { text: std::mem::take(&mut text) },
+                        });
+                    }
+                    let called_tools = !tool_calls.is_empty();
+                    for (i, block) in std::mem::take(&mut tool_calls).into_iter().enumerate() {
+                        yield Ok(CompletionEvent::ContentBlockDone {
+                            index: TEXT_INDEX + 1 + i,
+                            block,
+                        });
+                    }
+
+                    let stop_reason = match chunk.done_reason.as_deref() {
+                        _ if called_tools => Some(StopReason::ToolUse),
+                        Some("length") => Some(StopReason::MaxTokens),
+                        Some("stop") | None => Some(StopReason::EndTurn),
+                        Some(_) => None,
+                    };
+                    yield Ok(CompletionEvent::Done {
+                        stop_reason,
+                        usage: Some(Usage {
+                            input_tokens: chunk.prompt_eval_count,
+                            output_tokens: chunk.eval_count,
+                            ..Default::default()
+                        }),
+                    });
+                    return;
+                }
+            }
+
+            yield Err(AgentError::StreamEnded);
+        };
+
+        Ok(cancellable_stream(Box::pin(stream), cancel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::collect;
+    use crate::test_support::StubServer;
+
+    const NDJSON: &str = "application/x-ndjson";
+
+    fn request(text: &str) -> CompletionRequest {
+        CompletionRequest {
+            model: "llama3.2".into(),
+            max_tokens: 256,
+            messages: vec![Message {
+                role: Role::User,
+                content: Content::Text(text.into()),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_text_tool_calls_and_usage() {
+        let body = [
+            r#"{"message":{"role":"assistant","content":"","thinking":"Check the weather."},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":"Let me look."},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"weather","arguments":{"city":"Paris"}}}]},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":42,"eval_count":7}"#,
+        ]
+        .join("\n");
+        let server = StubServer::routes(NDJSON, vec![("/api/chat", body)]).await;
+        let provider = OllamaProvider::new()
+            .with_base_url(server.url())
+            .with_num_ctx(8192)
+            .with_keep_alive(KeepAlive::Duration(Duration::from_secs(600)));
+
+        let response = collect(provider.stream(request("weather?")).await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.text(), "Let me look.");
+        assert_eq!(response.stop_reason, Some(StopReason::ToolUse));
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens), (42, 7));
+        let Content::Blocks(blocks) = &response.message.content else {
+            panic!("expected blocks");
+        };
+        assert!(
+            matches!(&blocks[0], ContentBlock::Thinking { thinking, .. } if thinking == "Check the weather.")
+        );
+        assert!(
+            matches!(&blocks[2], ContentBlock::ToolUse { name, input, .. } if name == "weather" && input["city"] == "Paris")
+        );
+
+        let sent: serde_json::Value = serde_json::from_str(&server.requests()[0].body).unwrap();
+        assert_eq!(sent["options"]["num_ctx"], 8192);
+        assert_eq!(sent["options"]["num_predict"], 256);
+        assert_eq!(sent["keep_alive"], "600s");
+        assert_eq!(sent["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn lists_and_pulls_models() {
+        let tags = r#"{"models":[{"name":"llama3.2:latest","size":2019393189,"digest":"a80c4f17acd5","details":{"family":"llama","parameter_size":"3.2B","quantization_level":"Q4_K_M"}}]}"#;
+        let pull = [
+            r#"{"status":"pulling manifest"}"#,
+            r#"{"status":"downloading","total":100,"completed":100}"#,
+            r#"{"status":"success"}"#,
+        ]
+        .join("\n");
+        let server = StubServer::routes(
+            NDJSON,
+            vec![("/api/tags", tags.to_string()), ("/api/pull", pull)],
+        )
+        .await;
+        let provider = OllamaProvider::new().with_base_url(server.url());
+
+        let models = provider.list_models().await.unwrap();
+        assert_eq!(models[0].name, "llama3.2:latest");
+        assert_eq!(
+            models[0]
+                .details
+                .as_ref()
+                .unwrap()
+                .parameter_size
+                .as_deref(),
+            Some("3.2B")
+        );
+
+        assert!(!provider.ensure_model("llama3.2").await.unwrap());
+        assert!(provider.ensure_model("qwen3:8b").await.unwrap());
+        let pulled: serde_json::Value =
+            serde_json::from_str(&server.requests().last().unwrap().body).unwrap();
+        assert_eq!(pulled["model"], "qwen3:8b");
+    }
+
+    #[tokio::test]
+    async fn pull_reports_server_error() {
+        let server = StubServer::routes(
+            NDJSON,
+            vec![(
+                "/api/pull",
+                r#"{"error":"pull model manifest: file does not exist"}"#.to_string(),
+            )],
+        )
+        .await;
+        let provider = OllamaProvider::new().with_base_url(server.url());
+
+        let result = provider.pull_model("missing").await;
+        assert!(
+            matches!(result, Err(AgentError::Api { message, .. }) if message.contains("does not exist"))
+        );
+    }
+
+    #[test]
+    fn tool_results_become_named_tool_messages() {
+        let messages = vec![
+            Message {
+                role: Role::Assistant,
+                content: Content::Blocks(vec![ContentBlock::ToolUse {
+                    id: "call_1".into(),
+                    name: "weather".into(),
+                    input: serde_json::json!({"city": "Paris"}),
+                }]),
+            },
+            Message {
+                role: Role::User,
+                content: Content::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".into(),
+                    content: Content::Text("sunny".into()),
+                    is_error: None,
+                }]),
+            },
+        ];
+
+        let converted = convert_messages(&messages, Some("be brief")).unwrap();
+
+        assert_eq!(converted.len(), 3);
+        assert_eq!(converted[0].role, "system");
+        assert_eq!(
+            converted[1].tool_calls[0].function.arguments["city"],
+            "Paris"
+        );
+        assert_eq!(converted[2].role, "tool");
+        assert_eq!(converted[2].tool_name.as_deref(), Some("weather"));
+        assert_eq!(converted[2].content, "sunny");
+    }
+
+    #[test]
+    fn keep_alive_serializes_ollama_durations() {
+        assert_eq!(
+            serde_json::to_value(KeepAlive::Duration(Duration::from_secs(300))).unwrap(),
+            "300s"
+        );
+        assert_eq!(serde_json::to_value(KeepAlive::Forever).unwrap(), -1);
+        assert_eq!(serde_json::to_value(KeepAlive::Unload).unwrap(), 0);
+    }
+
+    #[test]
+    fn forced_tool_choice_is_unsupported() {
+        let request = CompletionRequest {
+            tool_choice: Some(ToolChoice::Any),
+            ..request("hi")
+        };
+        assert!(matches!(
+            OllamaProvider::new().convert_request(request),
+            Err(AgentError::Unsupported { .. })
+        ));
+    }
+}
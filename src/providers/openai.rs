@@ -8,8 +8,13 @@ use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 
 use crate::error::{AgentError, Result};
-use crate::provider::{CompletionEvent, CompletionRequest, CompletionStream, LlmProvider};
-use crate::types::{Content, ContentBlock, Message, Role, StopReason, Tool};
+use crate::provider::{
+    CompletionEvent, CompletionRequest, CompletionStream, LlmProvider, ToolChoice, cancellable,
+    cancellable_stream, reasoning_effort,
+};
+use crate::providers::RetryPolicy;
+use crate::sse::sse_events;
+use crate::types::{Content, ContentBlock, MediaSource, Message, Role, StopReason, Tool, Usage};
 
 const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
 
@@ -19,6 +24,7 @@ pub struct OpenAiProvider {
     http: reqwest::Client,
     api_key: Option<String>,
     base_url: String,
+    retry: RetryPolicy,
 }
 
 impl OpenAiProvider {
@@ -37,6 +43,7 @@ impl OpenAiProvider {
             http: reqwest::Client::new(),
             api_key: Some(api_key),
             base_url: DEFAULT_BASE_URL.to_string(),
+            retry: RetryPolicy::default(),
         })
     }
 
@@ -53,8 +60,23 @@ impl OpenAiProvider {
             http: reqwest::Client::new(),
             api_key,
             base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            retry: RetryPolicy::default(),
         })
     }
+
+    /// Use a preconfigured HTTP client, e.g. with proxy or timeout settings.
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Set the retry policy for failed requests.
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 }
 
 // OpenAI request types
@@ -66,6 +88,20 @@ struct OpenAiRequest {
     messages: Vec<OpenAiMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<OpenAiTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
     stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream_options: Option<StreamOptions>,
@@ -91,6 +127,27 @@ struct OpenAiMessage {
 #[serde(untagged)]
 enum OpenAiContent {
     Text(String),
+    Parts(Vec<OpenAiContentPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAiContentPart {
+    Text { text: String },
+    ImageUrl { image_url: OpenAiImageUrl },
+    File { file: OpenAiFile },
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    file_data: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -125,7 +182,40 @@ struct OpenAiFunction {
 
 #[derive(Debug, Deserialize)]
 struct OpenAiChunk {
+    #[serde(default)]
     choices: Vec<OpenAiChoice>,
+    /// Sent in a final chunk when `include_usage` is set.
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<OpenAiPromptTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiPromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
+impl From<OpenAiUsage> for Usage {
+    /// Split cached tokens out of the prompt count, which includes them.
+    fn from(usage: OpenAiUsage) -> Self {
+        let cached = usage.prompt_tokens_details.map_or(0, |d| d.cached_tokens);
+        Self {
+            input_tokens: usage.prompt_tokens.saturating_sub(cached),
+            output_tokens: usage.completion_tokens,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: cached,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -138,6 +228,12 @@ struct OpenAiChoice {
 struct OpenAiDelta {
     #[serde(default)]
     content: Option<String>,
+    /// Reasoning text from compatible servers such as `DeepSeek` and vLLM.
+    #[serde(default)]
+    reasoning_content: Option<String>,
+    /// Reasoning text from compatible servers such as `OpenRouter` and Ollama.
+    #[serde(default)]
+    reasoning: Option<String>,
     #[serde(default)]
     tool_calls: Option<Vec<OpenAiToolCallDelta>>,
 }
@@ -160,7 +256,11 @@ struct OpenAiFunctionDelta {
 }
 
 /// Convert our messages to the format expected by the chat completions API.
-fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<OpenAiMessage> {
+///
+/// # Errors
+///
+/// Returns error if a message contains media the API cannot accept.
+fn convert_messages(messages: &[Message], system: Option<&str>) -> Result<Vec<OpenAiMessage>> {
     let mut result = Vec::new();
 
     // Add system message if present
@@ -174,12 +274,13 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<OpenAiMes
     }
 
     for msg in messages {
+        let role = match msg.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        };
+
         match &msg.content {
             Content::Text(text) => {
-                let role = match msg.role {
-                    Role::User => "user",
-                    Role::Assistant => "assistant",
-                };
                 result.push(OpenAiMessage {
                     role,
                     content: Some(OpenAiContent::Text(text.clone())),
@@ -189,14 +290,18 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<OpenAiMes
             }
             Content::Blocks(blocks) => {
                 // Handle mixed content blocks
-                let mut text_parts = Vec::new();
+                let mut parts = Vec::new();
                 let mut tool_calls = Vec::new();
                 let mut tool_results = Vec::new();
+                let mut tool_media = Vec::new();
 
                 for block in blocks {
                     match block {
                         ContentBlock::Text { text } => {
-                            text_parts.push(text.clone());
+                            parts.push(OpenAiContentPart::Text { text: text.clone() });
+                        }
+                        ContentBlock::Image { .. } | ContentBlock::Document { .. } => {
+                            parts.push(convert_media(block)?);
                         }
                         ContentBlock::ToolUse { id, name, input } => {
                             tool_calls.push(OpenAiToolCallRequest {
@@ -208,22 +313,29 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<OpenAiMes
                                 },
                             });
                         }
+                        // Chat completions has no field for prior reasoning
+                        ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
                         ContentBlock::ToolResult {
                             tool_use_id,
                             content,
                             ..
                         } => {
-                            tool_results.push((tool_use_id.clone(), content.clone()));
+                            // Tool messages are text-only; media follows in a user message
+                            for b in content.media_blocks() {
+                                tool_media.push(convert_media(b)?);
+                            }
+                            tool_results.push((tool_use_id.clone(), content.text()));
                         }
                     }
                 }
 
                 // Emit assistant message with tool calls if any
                 if !tool_calls.is_empty() {
-                    let content = if text_parts.is_empty() {
+                    let text = join_text(&parts);
+                    let content = if text.is_empty() {
                         None
                     } else {
-                        Some(OpenAiContent::Text(text_parts.join("")))
+                        Some(OpenAiContent::Text(text))
                     };
                     result.push(OpenAiMessage {
                         role: "assistant",
@@ -231,14 +343,10 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<OpenAiMes
                         tool_calls: Some(tool_calls),
                         tool_call_id: None,
                     });
-                } else if !text_parts.is_empty() {
-                    let role = match msg.role {
-                        Role::User => "user",
-                        Role::Assistant => "assistant",
-                    };
+                } else if !parts.is_empty() {
                     result.push(OpenAiMessage {
                         role,
-                        content: Some(OpenAiContent::Text(text_parts.join(""))),
+                        content: Some(parts_content(parts)),
                         tool_calls: None,
                         tool_call_id: None,
                     });
@@ -253,11 +361,70 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<OpenAiMes
                         tool_call_id: Some(tool_use_id),
                     });
                 }
+
+                if !tool_media.is_empty() {
+                    result.push(OpenAiMessage {
+                        role: "user",
+                        content: Some(OpenAiContent::Parts(tool_media)),
+                        tool_calls: None,
+                        tool_call_id: None,
+                    });
+                }
             }
         }
     }
 
-    result
+    Ok(result)
+}
+
+/// Convert an image or document block to a content part.
+fn convert_media(block: &ContentBlock) -> Result<OpenAiContentPart> {
+    match block {
+        ContentBlock::Image { source } => Ok(OpenAiContentPart::ImageUrl {
+            image_url: OpenAiImageUrl {
+                url: source.to_url(),
+            },
+        }),
+        ContentBlock::Document {
+            source: source @ MediaSource::Base64 { .. },
+            title,
+        } => Ok(OpenAiContentPart::File {
+            file: OpenAiFile {
+                filename: title.clone(),
+                file_data: source.to_url(),
+            },
+        }),
+        ContentBlock::Document { .. } => Err(AgentError::Unsupported {
+            provider: "openai",
+            feature: "document URLs",
+        }),
+        _ => Err(AgentError::Parse(
+            "expected an image or document block".to_string(),
+        )),
+    }
+}
+
+/// Join the text parts of a message.
+fn join_text(parts: &[OpenAiContentPart]) -> String {
+    parts
+        .iter()
+        .filter_map(|p| match p {
+            OpenAiContentPart::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Use plain text content unless the message carries media.
+fn parts_content(parts: Vec<OpenAiContentPart>) -> OpenAiContent {
+    if parts
+        .iter()
+        .all(|p| matches!(p, OpenAiContentPart::Text { .. }))
+    {
+        OpenAiContent::Text(join_text(&parts))
+    } else {
+        OpenAiContent::Parts(parts)
+    }
 }
 
 /// Convert our tools to the function calling format.
@@ -275,42 +442,29 @@ fn convert_tools(tools: &[Tool]) -> Vec<OpenAiTool> {
         .collect()
 }
 
-/// Parse a single SSE event from the buffer.
-///
-/// Returns the parsed chunk (if any) and the remaining buffer content.
-fn parse_sse_event(buffer: &str) -> Option<(Option<OpenAiChunk>, String)> {
-    // Find double newline (end of event)
-    let end = buffer.find("\n\n")?;
-    let event_str = &buffer[..end];
-    let remainder = buffer[end + 2..].to_string();
-
-    // Parse event
-    let mut data = None;
-
-    for line in event_str.lines() {
-        if let Some(rest) = line.strip_prefix("data: ") {
-            data = Some(rest.to_string());
-        }
+/// Convert tool choice to the chat completions format.
+fn convert_tool_choice(choice: &ToolChoice) -> serde_json::Value {
+    match choice {
+        ToolChoice::Auto => serde_json::json!("auto"),
+        ToolChoice::Any => serde_json::json!("required"),
+        ToolChoice::None => serde_json::json!("none"),
+        ToolChoice::Tool(name) => serde_json::json!({
+            "type": "function",
+            "function": { "name": name }
+        }),
     }
+}
 
-    // Skip non-data events
-    let Some(data) = data else {
-        return Some((None, remainder));
-    };
-
-    // Handle [DONE] marker
+/// Parse the data of one SSE event.
+///
+/// Returns `None` for the `[DONE]` marker.
+fn parse_chunk(data: &str) -> Result<Option<OpenAiChunk>> {
     if data.trim() == "[DONE]" {
-        return Some((None, remainder));
-    }
-
-    // Parse JSON
-    match serde_json::from_str::<OpenAiChunk>(&data) {
-        Ok(chunk) => Some((Some(chunk), remainder)),
-        Err(e) => {
-            tracing::debug!(data = %data, error = %e, "failed to parse OpenAI event");
-            Some((None, remainder))
-        }
+        return Ok(None);
     }
+    serde_json::from_str(data)
+        .map(Some)
+        .map_err(|e| AgentError::Parse(format!("invalid OpenAI stream chunk: {e}")))
 }
 
 /// Convert finish reason to our stop reason.
@@ -343,13 +497,26 @@ impl LlmProvider for OpenAiProvider {
             );
         }
 
+        let cancel = request.cancel.clone();
         let openai_tools = request.tools.as_ref().map(|t| convert_tools(t));
 
         let openai_request = OpenAiRequest {
             model: request.model,
             max_tokens: request.max_tokens,
-            messages: convert_messages(&request.messages, request.system.as_deref()),
+            messages: convert_messages(&request.messages, request.system.as_deref())?,
             tools: openai_tools,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            stop: request.stop_sequences,
+            tool_choice: request.tool_choice.as_ref().map(convert_tool_choice),
+            parallel_tool_calls: request.parallel_tool_calls,
+            reasoning_effort: request.thinking_budget.map(reasoning_effort),
+            response_format: request.response_format.map(|format| {
+                serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": {"name": format.name, "schema": format.schema},
+                })
+            }),
             stream: true,
             stream_options: Some(StreamOptions {
                 include_usage: true,
@@ -357,125 +524,132 @@ impl LlmProvider for OpenAiProvider {
         };
 
         let url = format!("{}/chat/completions", self.base_url);
-        let response = self
-            .http
-            .post(&url)
-            .headers(headers)
-            .json(&openai_request)
-            .send()
-            .await?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let message = response.text().await.unwrap_or_default();
-            return Err(AgentError::Api {
-                status: status.as_u16(),
-                message,
-            });
-        }
+        let response = cancellable(
+            cancel.as_ref(),
+            self.retry.send(self.name(), || {
+                self.http
+                    .post(&url)
+                    .headers(headers.clone())
+                    .json(&openai_request)
+            }),
+        )
+        .await?;
 
-        let byte_stream = response.bytes_stream();
+        let events = sse_events(response.bytes_stream());
 
         let stream = async_stream::stream! {
-            let mut buffer = String::new();
             // Track tool calls being built: index -> (id, name, arguments)
             let mut pending_tool_calls: std::collections::HashMap<usize, (String, String, String)> =
                 std::collections::HashMap::new();
             let text_block_index = 0_usize;
             let mut current_text = String::new();
+            // Usage arrives in its own chunk after the finish reason
+            let mut finished: Option<Option<StopReason>> = None;
+            let mut usage: Option<Usage> = None;
+
+            futures::pin_mut!(events);
+
+            while let Some(sse) = events.next().await {
+                let chunk = match sse.and_then(|sse| parse_chunk(&sse.data)) {
+                    Ok(Some(chunk)) => chunk,
+                    // `[DONE]` marker
+                    Ok(None) => continue,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
 
-            futures::pin_mut!(byte_stream);
+                if let Some(reported) = chunk.usage {
+                    usage = Some(reported.into());
+                }
 
-            while let Some(chunk) = byte_stream.next().await {
-                let chunk = chunk?;
-                buffer.push_str(&String::from_utf8_lossy(&chunk));
+                for choice in chunk.choices {
+                    if let Some(thinking) = choice.delta.reasoning_content.or(choice.delta.reasoning)
+                        && !thinking.is_empty()
+                    {
+                        yield Ok(CompletionEvent::ThinkingDelta(thinking));
+                    }
 
-                // Process complete SSE events
-                while let Some((chunk_opt, remainder)) = parse_sse_event(&buffer) {
-                    buffer = remainder;
+                    // Handle text content
+                    if let Some(text) = choice.delta.content {
+                        if !text.is_empty() {
+                            current_text.push_str(&text);
+                            yield Ok(CompletionEvent::TextDelta(text));
+                        }
+                    }
 
-                    let Some(chunk) = chunk_opt else {
-                        continue;
-                    };
+                    // Handle tool calls
+                    if let Some(tool_calls) = choice.delta.tool_calls {
+                        for tc in tool_calls {
+                            let entry = pending_tool_calls.entry(tc.index).or_insert_with(|| {
+                                (String::new(), String::new(), String::new())
+                            });
 
-                    for choice in chunk.choices {
-                        // Handle text content
-                        if let Some(text) = choice.delta.content {
-                            if !text.is_empty() {
-                                current_text.push_str(&text);
-                                yield Ok(CompletionEvent::TextDelta(text));
+                            // Update id if present
+                            if let Some(id) = tc.id {
+                                entry.0 = id;
                             }
-                        }
-
-                        // Handle tool calls
-                        if let Some(tool_calls) = choice.delta.tool_calls {
-                            for tc in tool_calls {
-                                let entry = pending_tool_calls.entry(tc.index).or_insert_with(|| {
-                                    (String::new(), String::new(), String::new())
-                                });
 
-                                // Update id if present
-                                if let Some(id) = tc.id {
-                                    entry.0 = id;
+                            // Update function info if present
+                            if let Some(func) = tc.function {
+                                if let Some(name) = func.name {
+                                    entry.1.clone_from(&name);
+                                    // Emit tool use start when we get the name
+                                    let tool_index = text_block_index + 1 + tc.index;
+                                    yield Ok(CompletionEvent::ToolUseStart {
+                                        index: tool_index,
+                                        id: entry.0.clone(),
+                                        name,
+                                    });
                                 }
-
-                                // Update function info if present
-                                if let Some(func) = tc.function {
-                                    if let Some(name) = func.name {
-                                        entry.1.clone_from(&name);
-                                        // Emit tool use start when we get the name
-                                        let tool_index = text_block_index + 1 + tc.index;
-                                        yield Ok(CompletionEvent::ToolUseStart {
-                                            index: tool_index,
-                                            id: entry.0.clone(),
-                                            name,
-                                        });
-                                    }
-                                    if let Some(args) = func.arguments {
-                                        entry.2.push_str(&args);
-                                        let tool_index = text_block_index + 1 + tc.index;
-                                        yield Ok(CompletionEvent::ToolInputDelta {
-                                            index: tool_index,
-                                            partial_json: args,
-                                        });
-                                    }
+                                if let Some(args) = func.arguments {
+                                    entry.2.push_str(&args);
+                                    let tool_index = text_block_index + 1 + tc.index;
+                                    yield Ok(CompletionEvent::ToolInputDelta {
+                                        index: tool_index,
+                                        partial_json: args,
+                                    });
                                 }
                             }
                         }
+                    }
 
-                        // Handle finish reason
-                        if let Some(reason) = choice.finish_reason {
-                            // Emit text block done if we have text
-                            if !current_text.is_empty() {
-                                yield Ok(CompletionEvent::ContentBlockDone {
-                                    index: text_block_index,
-                                    block: ContentBlock::Text { text: current_text.clone() },
-                                });
-                            }
-
-                            // Emit tool blocks done
-                            for (idx, (id, name, args)) in &pending_tool_calls {
-                                let tool_index = text_block_index + 1 + idx;
-                                let input = serde_json::from_str(args).unwrap_or(serde_json::Value::Null);
-                                yield Ok(CompletionEvent::ContentBlockDone {
-                                    index: tool_index,
-                                    block: ContentBlock::ToolUse {
-                                        id: id.clone(),
-                                        name: name.clone(),
-                                        input,
-                                    },
-                                });
-                            }
+                    // Handle finish reason
+                    if let Some(reason) = choice.finish_reason {
+                        // Emit text block done if we have text
+                        if !current_text.is_empty() {
+                            yield Ok(CompletionEvent::ContentBlockDone {
+                                index: text_block_index,
+                                block: ContentBlock::Text { text: current_text.clone() },
+                            });
+                        }
 
-                            let stop_reason = convert_stop_reason(&reason);
-                            yield Ok(CompletionEvent::Done { stop_reason, usage: None });
+                        // Emit tool blocks done
+                        for (idx, (id, name, args)) in &pending_tool_calls {
+                            let tool_index = text_block_index + 1 + idx;
+                            let input = serde_json::from_str(args).unwrap_or(serde_json::Value::Null);
+                            yield Ok(CompletionEvent::ContentBlockDone {
+                                index: tool_index,
+                                block: ContentBlock::ToolUse {
+                                    id: id.clone(),
+                                    name: name.clone(),
+                                    input,
+                                },
+                            });
                         }
+
+                        finished = Some(convert_stop_reason(&reason));
                     }
                 }
             }
+
+            if let Some(stop_reason) = finished {
+                yield Ok(CompletionEvent::Done { stop_reason, usage });
+            }
         };
 
-        Ok(Box::pin(stream))
+        Ok(cancellable_stream(Box::pin(stream), cancel))
     }
 }
 
@@ -547,12 +721,85 @@ mod tests {
             content: Content::Text("Hello".to_string()),
         }];
 
-        let openai_messages = convert_messages(&messages, Some("You are helpful"));
+        let openai_messages = convert_messages(&messages, Some("You are helpful")).unwrap();
         assert_eq!(openai_messages.len(), 2);
         assert_eq!(openai_messages[0].role, "system");
         assert_eq!(openai_messages[1].role, "user");
     }
 
+    #[test]
+    fn convert_messages_uses_parts_for_images() {
+        let messages = vec![Message {
+            role: Role::User,
+            content: Content::Blocks(vec![
+                ContentBlock::Text {
+                    text: "What is this?".to_string(),
+                },
+                ContentBlock::image("image/png", b"png"),
+            ]),
+        }];
+
+        let openai_messages = convert_messages(&messages, None).unwrap();
+        assert_eq!(
+            serde_json::to_value(&openai_messages[0]).unwrap(),
+            serde_json::json!({
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,cG5n"}}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn convert_messages_moves_tool_result_images_to_user_message() {
+        let messages = vec![Message {
+            role: Role::User,
+            content: Content::Blocks(vec![ContentBlock::ToolResult {
+                tool_use_id: "call_1".to_string(),
+                content: Content::Blocks(vec![
+                    ContentBlock::Text {
+                        text: "screenshot taken".to_string(),
+                    },
+                    ContentBlock::image("image/png", b"png"),
+                ]),
+                is_error: None,
+            }]),
+        }];
+
+        let openai_messages = convert_messages(&messages, None).unwrap();
+        assert_eq!(openai_messages.len(), 2);
+        assert_eq!(openai_messages[0].role, "tool");
+        assert!(matches!(
+            &openai_messages[0].content,
+            Some(OpenAiContent::Text(t)) if t == "screenshot taken"
+        ));
+        assert_eq!(openai_messages[1].role, "user");
+        assert!(matches!(
+            &openai_messages[1].content,
+            Some(OpenAiContent::Parts(parts)) if parts.len() == 1
+        ));
+    }
+
+    #[test]
+    fn convert_messages_rejects_document_urls() {
+        let messages = vec![Message {
+            role: Role::User,
+            content: Content::Blocks(vec![ContentBlock::Document {
+                source: MediaSource::Url {
+                    url: "https://example.com/a.pdf".to_string(),
+                },
+                title: None,
+            }]),
+        }];
+
+        assert!(matches!(
+            convert_messages(&messages, None),
+            Err(AgentError::Unsupported { .. })
+        ));
+    }
+
     #[test]
     fn convert_stop_reason_maps_correctly() {
         assert_eq!(convert_stop_reason("stop"), Some(StopReason::EndTurn));
@@ -562,12 +809,38 @@ mod tests {
     }
 
     #[test]
-    fn parse_sse_event_handles_done() {
-        let buffer = "data: [DONE]\n\n";
-        let result = parse_sse_event(buffer);
-        assert!(result.is_some());
-        let (chunk, remainder) = result.unwrap();
-        assert!(chunk.is_none());
-        assert!(remainder.is_empty());
+    fn convert_tool_choice_maps_variants() {
+        assert_eq!(convert_tool_choice(&ToolChoice::Auto), "auto");
+        assert_eq!(convert_tool_choice(&ToolChoice::Any), "required");
+        assert_eq!(convert_tool_choice(&ToolChoice::None), "none");
+        assert_eq!(
+            convert_tool_choice(&ToolChoice::Tool("Bash".to_string())),
+            serde_json::json!({"type": "function", "function": {"name": "Bash"}})
+        );
+    }
+
+    #[test]
+    fn parse_chunk_handles_done_and_errors() {
+        assert!(parse_chunk("[DONE]").unwrap().is_none());
+        assert!(matches!(parse_chunk("{"), Err(AgentError::Parse(_))));
+    }
+
+    #[test]
+    fn parse_chunk_reads_reasoning() {
+        let data =
+            "{\"choices\":[{\"delta\":{\"reasoning_content\":\"Hmm\"},\"finish_reason\":null}]}";
+        let chunk = parse_chunk(data).unwrap();
+        let delta = &chunk.unwrap().choices[0].delta;
+        assert_eq!(delta.reasoning_content.as_deref(), Some("Hmm"));
+    }
+
+    #[test]
+    fn usage_chunk_separates_cached_tokens() {
+        let data = "{\"choices\":[],\"usage\":{\"prompt_tokens\":120,\"completion_tokens\":30,\"prompt_tokens_details\":{\"cached_tokens\":100}}}";
+        let chunk = parse_chunk(data).unwrap();
+        let usage = Usage::from(chunk.unwrap().usage.unwrap());
+        assert_eq!(usage.input_tokens, 20);
+        assert_eq!(usage.cache_read_input_tokens, 100);
+        assert_eq!(usage.output_tokens, 30);
     }
 }
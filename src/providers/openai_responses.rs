@@ -0,0 +1,1019 @@
+//! `OpenAI` Responses API provider.
+//!
+//! Speaks `/v1/responses`, which carries reasoning items, built-in tools
+//! and server-side conversation state that Chat Completions lacks.
+//! Reasoning items round-trip through [`ContentBlock::Thinking`]: the
+//! item ID and encrypted content travel in the block's signature.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use parking_lot::Mutex;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AgentError, Result};
+use crate::provider::{
+    CompletionEvent, CompletionRequest, CompletionStream, LlmProvider, StreamCollector, ToolChoice,
+    cancellable, cancellable_stream, parse_tool_input, reasoning_effort,
+};
+use crate::providers::RetryPolicy;
+use crate::sse::sse_events;
+use crate::types::{Content, ContentBlock, MediaSource, Message, Role, StopReason, Tool, Usage};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// The last stored response, the input it answered and its reply.
+#[derive(Debug, Clone)]
+struct ResponseChain {
+    response_id: String,
+    input_len: usize,
+    /// Hash of the input followed by the assistant message produced.
+    transcript_hash: u64,
+}
+
+/// LLM provider for the `OpenAI` Responses API.
+#[derive(Debug, Clone)]
+pub struct OpenAiResponsesProvider {
+    http: reqwest::Client,
+    api_key: Option<String>,
+    base_url: String,
+    retry: RetryPolicy,
+    store: bool,
+    builtin_tools: Vec<serde_json::Value>,
+    chain: Arc<Mutex<Option<ResponseChain>>>,
+}
+
+impl OpenAiResponsesProvider {
+    /// Create a new provider instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if API key is empty.
+    pub fn new(api_key: impl Into<String>) -> Result<Self> {
+        let api_key = api_key.into();
+        if api_key.is_empty() {
+            return Err(AgentError::ApiKeyMissing);
+        }
+        Self::with_config(Some(api_key), None)
+    }
+
+    /// Create a provider with optional API key and base URL.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if configuration is invalid.
+    pub fn with_config(api_key: Option<String>, base_url: Option<String>) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::new(),
+            api_key,
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            retry: RetryPolicy::default(),
+            store: false,
+            builtin_tools: Vec::new(),
+            chain: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Use a preconfigured HTTP client, e.g. with proxy or timeout settings.
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Set the retry policy for failed requests.
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Store responses server-side and chain requests with
+    /// `previous_response_id`.
+    ///
+    /// When a request extends the conversation of the last response,
+    /// only the new messages are sent. Any other request sends the full
+    /// conversation and starts a new chain.
+    #[must_use]
+    pub const fn with_store(mut self, store: bool) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Offer a built-in tool such as `{"type": "web_search"}`.
+    ///
+    /// Built-in tools run on `OpenAI`'s side; their results reach the
+    /// conversation as assistant text.
+    #[must_use]
+    pub fn with_builtin_tool(mut self, tool: serde_json::Value) -> Self {
+        self.builtin_tools.push(tool);
+        self
+    }
+
+    /// Pick the stored response to continue from, if any.
+    ///
+    /// Continuation requires the request to repeat the stored input
+    /// followed by the stored response's assistant message unchanged, so
+    /// an edited reply is sent rather than replaced by the stored one.
+    fn continuation(&self, messages: &[Message]) -> Option<(String, usize)> {
+        if !self.store {
+            return None;
+        }
+        let chain = self.chain.lock().clone()?;
+        let extends = messages.len() > chain.input_len + 1
+            && messages_hash(&messages[..=chain.input_len]) == chain.transcript_hash;
+        extends.then_some((chain.response_id, chain.input_len + 1))
+    }
+
+    /// Build the wire request.
+    fn convert_request(&self, request: CompletionRequest) -> Result<ResponsesRequest> {
+        if request.stop_sequences.is_some() {
+            return Err(AgentError::Unsupported {
+                provider: "openai",
+                feature: "stop sequences with the Responses API",
+            });
+        }
+
+        let (previous_response_id, skip) = self
+            .continuation(&request.messages)
+            .map_or((None, 0), |(id, skip)| (Some(id), skip));
+
+        let mut tools: Vec<serde_json::Value> = request
+            .tools
+            .as_deref()
+            .map(convert_tools)
+            .unwrap_or_default();
+        tools.extend(self.builtin_tools.iter().cloned());
+
+        let responses_request = ResponsesRequest {
+            model: request.model,
+            input: convert_messages(&request.messages[skip..])?,
+            instructions: request.system,
+            tools,
+            tool_choice: request.tool_choice.as_ref().map(convert_tool_choice),
+            parallel_tool_calls: request.parallel_tool_calls,
+            max_output_tokens: (request.max_tokens > 0).then_some(request.max_tokens),
+            temperature: request.temperature,
+            top_p: request.top_p,
+            reasoning: request.thinking_budget.map(|budget| ReasoningConfig {
+                effort: reasoning_effort(budget),
+                summary: "auto",
+            }),
+            text: request.response_format.map(|format| {
+                serde_json::json!({
+                    "format": {
+                        "type": "json_schema",
+                        "name": format.name,
+                        "schema": format.schema,
+                    }
+                })
+            }),
+            include: if self.store {
+                Vec::new()
+            } else {
+                vec!["reasoning.encrypted_content"]
+            },
+            previous_response_id,
+            store: self.store,
+            stream: true,
+        };
+        Ok(responses_request)
+    }
+}
+
+// Responses request types
+
+#[derive(Debug, Serialize)]
+struct ResponsesRequest {
+    model: String,
+    input: Vec<InputItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<ReasoningConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    include: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_response_id: Option<String>,
+    store: bool,
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ReasoningConfig {
+    effort: &'static str,
+    summary: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputItem {
+    Message {
+        role: &'static str,
+        content: Vec<InputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+    Reasoning {
+        id: String,
+        summary: Vec<SummaryText>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        encrypted_content: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputContent {
+    InputText {
+        text: String,
+    },
+    OutputText {
+        text: String,
+    },
+    InputImage {
+        image_url: String,
+    },
+    InputFile {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        filename: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file_data: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file_url: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct SummaryText {
+    #[serde(rename = "type")]
+    part_type: &'static str,
+    text: String,
+}
+
+/// Reasoning item identity stored in a thinking block signature.
+#[derive(Debug, Serialize, Deserialize)]
+struct ReasoningSignature {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted_content: Option<String>,
+}
+
+// Responses stream event types
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ResponsesEvent {
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+    #[serde(
+        rename = "response.reasoning_summary_text.delta",
+        alias = "response.reasoning_text.delta"
+    )]
+    ReasoningDelta { delta: String },
+    #[serde(rename = "response.output_item.added")]
+    OutputItemAdded {
+        output_index: usize,
+        item: OutputItem,
+    },
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta { output_index: usize, delta: String },
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone {
+        output_index: usize,
+        item: OutputItem,
+    },
+    #[serde(rename = "response.completed", alias = "response.incomplete")]
+    Completed { response: ResponseObject },
+    #[serde(rename = "response.failed")]
+    Failed { response: ResponseObject },
+    #[serde(rename = "error")]
+    Error { message: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputItem {
+    Message {
+        #[serde(default)]
+        content: Vec<OutputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        #[serde(default)]
+        arguments: String,
+    },
+    Reasoning {
+        id: String,
+        #[serde(default)]
+        summary: Vec<SummaryPart>,
+        #[serde(default)]
+        encrypted_content: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputContent {
+    OutputText {
+        text: String,
+    },
+    Refusal {
+        refusal: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct SummaryPart {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseObject {
+    id: String,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    incomplete_details: Option<IncompleteDetails>,
+    #[serde(default)]
+    error: Option<ResponseError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+    #[serde(default)]
+    input_tokens_details: Option<InputTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct IncompleteDetails {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseError {
+    message: String,
+}
+
+impl From<ResponsesUsage> for Usage {
+    /// Split cached tokens out of the input count, which includes them.
+    fn from(usage: ResponsesUsage) -> Self {
+        let cached = usage.input_tokens_details.map_or(0, |d| d.cached_tokens);
+        Self {
+            input_tokens: usage.input_tokens.saturating_sub(cached),
+            output_tokens: usage.output_tokens,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: cached,
+        }
+    }
+}
+
+/// Translates Responses stream events into completion events.
+#[derive(Debug, Default)]
+struct EventMapper {
+    called_tools: bool,
+    /// ID of the response once it completed.
+    completed: Option<String>,
+    finished: bool,
+}
+
+impl EventMapper {
+    fn map(&mut self, event: ResponsesEvent) -> Result<Vec<CompletionEvent>> {
+        Ok(match event {
+            ResponsesEvent::OutputTextDelta { delta } => vec![CompletionEvent::TextDelta(delta)],
+            ResponsesEvent::ReasoningDelta { delta } => {
+                vec![CompletionEvent::ThinkingDelta(delta)]
+            }
+            ResponsesEvent::OutputItemAdded {
+                output_index,
+                item: OutputItem::FunctionCall { call_id, name, .. },
+            } => vec![CompletionEvent::ToolUseStart {
+                index: output_index,
+                id: call_id,
+                name,
+            }],
+            ResponsesEvent::FunctionCallArgumentsDelta {
+                output_index,
+                delta,
+            } => vec![CompletionEvent::ToolInputDelta {
+                index: output_index,
+                partial_json: delta,
+            }],
+            ResponsesEvent::OutputItemDone { output_index, item } => self
+                .finish_item(item)?
+                .map(|block| CompletionEvent::ContentBlockDone {
+                    index: output_index,
+                    block,
+                })
+                .into_iter()
+                .collect(),
+            ResponsesEvent::Completed { response } => {
+                self.finished = true;
+                let stop_reason = if self.called_tools {
+                    Some(StopReason::ToolUse)
+                } else if response.status.as_deref() == Some("incomplete") {
+                    match response
+                        .incomplete_details
+                        .and_then(|d| d.reason)
+                        .as_deref()
+                    {
+                        Some("max_output_tokens") => Some(StopReason::MaxTokens),
+                        _ => None,
+                    }
+                } else {
+                    Some(StopReason::EndTurn)
+                };
+                self.completed = Some(response.id);
+                vec![CompletionEvent::Done {
+                    stop_reason,
+                    usage: response.usage.map(Usage::from),
+                }]
+            }
+            ResponsesEvent::Failed { response } => {
+                self.finished = true;
+                let message = response
+                    .error
+                    .map_or_else(|| "response failed".to_string(), |e| e.message);
+                vec![CompletionEvent::Error(message)]
+            }
+            ResponsesEvent::Error { message } => {
+                self.finished = true;
+                vec![CompletionEvent::Error(message)]
+            }
+            ResponsesEvent::OutputItemAdded { .. } | ResponsesEvent::Other => Vec::new(),
+        })
+    }
+
+    /// Convert a finished output item into a content block.
+    ///
+    /// Function call arguments that are not valid JSON are a parse error.
+    fn finish_item(&mut self, item: OutputItem) -> Result<Option<ContentBlock>> {
+        Ok(match item {
+            OutputItem::Message { content } => {
+                let text: String = content
+                    .into_iter()
+                    .filter_map(|part| match part {
+                        OutputContent::OutputText { text } => Some(text),
+                        OutputContent::Refusal { refusal } => Some(refusal),
+                        OutputContent::Other => None,
+                    })
+                    .collect();
+                Some(ContentBlock::Text { text })
+            }
+            OutputItem::FunctionCall {
+                call_id,
+                name,
+                arguments,
+            } => {
+                self.called_tools = true;
+                Some(ContentBlock::ToolUse {
+                    id: call_id,
+                    name,
+                    input: parse_tool_input(&arguments)?,
+                })
+            }
+            OutputItem::Reasoning {
+                id,
+                summary,
+                encrypted_content,
+            } => {
+                let signature = serde_json::to_string(&ReasoningSignature {
+                    id,
+                    encrypted_content,
+                })
+                .ok();
+                Some(ContentBlock::Thinking {
+                    thinking: summary
+                        .into_iter()
+                        .map(|part| part.text)
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                    signature,
+                })
+            }
+            OutputItem::Other => None,
+        })
+    }
+}
+
+/// Convert our tools to Responses function tools.
+fn convert_tools(tools: &[Tool]) -> Vec<serde_json::Value> {
+    tools
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "type": "function",
+                "name": t.name,
+                "description": t.description,
+                "parameters": t.input_schema,
+            })
+        })
+        .collect()
+}
+
+/// Convert tool choice to the Responses format.
+fn convert_tool_choice(choice: &ToolChoice) -> serde_json::Value {
+    match choice {
+        ToolChoice::Auto => serde_json::json!("auto"),
+        ToolChoice::Any => serde_json::json!("required"),
+        ToolChoice::None => serde_json::json!("none"),
+        ToolChoice::Tool(name) => serde_json::json!({ "type": "function", "name": name }),
+    }
+}
+
+/// Convert messages to Responses input items.
+///
+/// Tool calls and results become their own items. Images returned by
+/// tools follow as a user message, since function output is text only.
+fn convert_messages(messages: &[Message]) -> Result<Vec<InputItem>> {
+    let mut items = Vec::new();
+
+    for msg in messages {
+        let (role, text_part): (&'static str, fn(String) -> InputContent) = match msg.role {
+            Role::User => ("user", |text| InputContent::InputText { text }),
+            Role::Assistant => ("assistant", |text| InputContent::OutputText { text }),
+        };
+        let blocks = match &msg.content {
+            Content::Text(text) => {
+                items.push(InputItem::Message {
+                    role,
+                    content: vec![text_part(text.clone())],
+                });
+                continue;
+            }
+            Content::Blocks(blocks) => blocks,
+        };
+
+        let mut content = Vec::new();
+        let mut tool_images = Vec::new();
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text } => content.push(text_part(text.clone())),
+                ContentBlock::Image { .. } | ContentBlock::Document { .. } => {
+                    content.push(convert_media(block)?);
+                }
+                ContentBlock::ToolUse { id, name, input } => {
+                    flush_message(&mut items, role, &mut content);
+                    items.push(InputItem::FunctionCall {
+                        call_id: id.clone(),
+                        name: name.clone(),
+                        arguments: input.to_string(),
+                    });
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content: result,
+                    ..
+                } => {
+                    flush_message(&mut items, role, &mut content);
+                    items.push(InputItem::FunctionCallOutput {
+                        call_id: tool_use_id.clone(),
+                        output: result.text(),
+                    });
+                    if let Content::Blocks(blocks) = result {
+                        for block in blocks
+                            .iter()
+                            .filter(|b| matches!(b, ContentBlock::Image { .. }))
+                        {
+                            tool_images.push(convert_media(block)?);
+                        }
+                    }
+                }
+                ContentBlock::Thinking {
+                    thinking,
+                    signature: Some(signature),
+                } => {
+                    // Only reasoning items from this API can be replayed
+                    if let Ok(reasoning) = serde_json::from_str::<ReasoningSignature>(signature) {
+                        flush_message(&mut items, role, &mut content);
+                        items.push(InputItem::Reasoning {
+                            id: reasoning.id,
+                            summary: (!thinking.is_empty())
+                                .then(|| SummaryText {
+                                    part_type: "summary_text",
+                                    text: thinking.clone(),
+                                })
+                                .into_iter()
+                                .collect(),
+                            encrypted_content: reasoning.encrypted_content,
+                        });
+                    }
+                }
+                ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
+            }
+        }
+        flush_message(&mut items, role, &mut content);
+        if !tool_images.is_empty() {
+            items.push(InputItem::Message {
+                role: "user",
+                content: tool_images,
+            });
+        }
+    }
+
+    Ok(items)
+}
+
+/// Emit accumulated message content as one message item.
+fn flush_message(items: &mut Vec<InputItem>, role: &'static str, content: &mut Vec<InputContent>) {
+    if !content.is_empty() {
+        items.push(InputItem::Message {
+            role,
+            content: std::mem::take(content),
+        });
+    }
+}
+
+/// Convert an image or document block to an input part.
+fn convert_media(block: &ContentBlock) -> Result<InputContent> {
+    match block {
+        ContentBlock::Image { source } => Ok(InputContent::InputImage {
+            image_url: source.to_url(),
+        }),
+        ContentBlock::Document { source, title } => Ok(match source {
+            MediaSource::Base64 { .. } => InputContent::InputFile {
+                filename: Some(title.clone().unwrap_or_else(|| "document.pdf".to_string())),
+                file_data: Some(source.to_url()),
+                file_url: None,
+            },
+            MediaSource::Url { url } => InputContent::InputFile {
+                filename: None,
+                file_data: None,
+                file_url: Some(url.clone()),
+            },
+        }),
+        _ => Err(AgentError::Parse(
+            "expected an image or document block".to_string(),
+        )),
+    }
+}
+
+/// Hash messages to recognise a stored conversation prefix.
+fn messages_hash(messages: &[Message]) -> u64 {
+    serde_json::to_string(messages)
+        .unwrap_or_default()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Parse the data of one SSE event.
+fn parse_event(data: &str) -> Result<ResponsesEvent> {
+    serde_json::from_str(data)
+        .map_err(|e| AgentError::Parse(format!("invalid Responses stream event: {e}")))
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiResponsesProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Some(api_key) = &self.api_key {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {api_key}"))
+                    .map_err(|_| AgentError::ApiKeyMissing)?,
+            );
+        }
+
+        let cancel = request.cancel.clone();
+        let chain = self.store.then(|| Arc::clone(&self.chain));
+        // Stored responses chain on the input plus the reply, see `continuation`
+        let mut transcript = chain.as_ref().map(|_| request.messages.clone());
+        let responses_request = self.convert_request(request)?;
+
+        let url = format!("{}/responses", self.base_url.trim_end_matches('/'));
+        let response = cancellable(
+            cancel.as_ref(),
+            self.retry.send(self.name(), || {
+                self.http
+                    .post(&url)
+                    .headers(headers.clone())
+                    .json(&responses_request)
+            }),
+        )
+        .await?;
+
+        let events = sse_events(response.bytes_stream());
+
+        let stream = async_stream::stream! {
+            let mut mapper = EventMapper::default();
+            let mut reply = StreamCollector::new();
+
+            futures::pin_mut!(events);
+
+            while let Some(sse) = events.next().await {
+                let event = match sse.and_then(|sse| parse_event(&sse.data)) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+
+                match mapper.map(event) {
+                    Ok(events) => {
+                        for event in events {
+                            if transcript.is_some() && reply.push(event.clone()).is_err() {
+                                transcript = None;
+                            }
+                            yield Ok(event);
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+
+                if mapper.finished {
+                    if let (Some(chain), Some(response_id), Some(mut transcript)) =
+                        (&chain, mapper.completed.take(), transcript)
+                        && let Ok(reply) = reply.finish()
+                    {
+                        let input_len = transcript.len();
+                        transcript.push(reply.message);
+                        *chain.lock() = Some(ResponseChain {
+                            response_id,
+                            input_len,
+                            transcript_hash: messages_hash(&transcript),
+                        });
+                    }
+                    return;
+                }
+            }
+
+            yield Err(AgentError::StreamEnded);
+        };
+
+        Ok(cancellable_stream(Box::pin(stream), cancel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::collect;
+    use crate::sse::SseDecoder;
+    use crate::test_support::{StubServer, response};
+
+    fn user(text: &str) -> Message {
+        Message {
+            role: Role::User,
+            content: Content::Text(text.into()),
+        }
+    }
+
+    /// Feed raw SSE text through the mapper and collect the response.
+    fn collect_sse(sse: &str) -> (crate::provider::CompletionResponse, EventMapper) {
+        let mut mapper = EventMapper::default();
+        let mut collector = StreamCollector::new();
+        for sse in SseDecoder::new().feed(sse.as_bytes()).unwrap() {
+            for event in mapper.map(parse_event(&sse.data).unwrap()).unwrap() {
+                collector.push(event).unwrap();
+            }
+        }
+        (collector.finish().unwrap(), mapper)
+    }
+
+    #[test]
+    fn maps_stream_events_to_completion() {
+        let sse = concat!(
+            "event: response.created\n",
+            "data: {\"type\":\"response.created\",\"response\":{\"id\":\"resp_1\"}}\n\n",
+            "event: response.reasoning_summary_text.delta\n",
+            "data: {\"type\":\"response.reasoning_summary_text.delta\",\"delta\":\"Think\"}\n\n",
+            "data: {\"type\":\"response.output_item.done\",\"output_index\":0,\"item\":{\"type\":\"reasoning\",\"id\":\"rs_1\",\"summary\":[{\"type\":\"summary_text\",\"text\":\"Think\"}],\"encrypted_content\":\"enc\"}}\n\n",
+            "data: {\"type\":\"response.output_text.delta\",\"delta\":\"Hi\"}\n\n",
+            "data: {\"type\":\"response.output_item.done\",\"output_index\":1,\"item\":{\"type\":\"message\",\"content\":[{\"type\":\"output_text\",\"text\":\"Hi\"}]}}\n\n",
+            "data: {\"type\":\"response.output_item.added\",\"output_index\":2,\"item\":{\"type\":\"function_call\",\"call_id\":\"call_1\",\"name\":\"Read\",\"arguments\":\"\"}}\n\n",
+            "data: {\"type\":\"response.function_call_arguments.delta\",\"output_index\":2,\"delta\":\"{\\\"path\\\":\"}\n\n",
+            "data: {\"type\":\"response.function_call_arguments.delta\",\"output_index\":2,\"delta\":\"\\\"a\\\"}\"}\n\n",
+            "data: {\"type\":\"response.completed\",\"response\":{\"id\":\"resp_1\",\"status\":\"completed\",\"usage\":{\"input_tokens\":50,\"output_tokens\":9,\"input_tokens_details\":{\"cached_tokens\":40}}}}\n\n",
+        );
+
+        let (response, mapper) = collect_sse(sse);
+
+        assert_eq!(mapper.completed.as_deref(), Some("resp_1"));
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+        let usage = response.usage.unwrap();
+        assert_eq!(
+            (usage.input_tokens, usage.cache_read_input_tokens),
+            (10, 40)
+        );
+        let Content::Blocks(blocks) = &response.message.content else {
+            panic!("expected blocks");
+        };
+        assert!(matches!(
+            &blocks[0],
+            ContentBlock::Thinking { thinking, signature: Some(_) } if thinking == "Think"
+        ));
+        assert!(matches!(&blocks[1], ContentBlock::Text { text } if text == "Hi"));
+        assert!(matches!(
+            &blocks[2],
+            ContentBlock::ToolUse { id, input, .. } if id == "call_1" && input["path"] == "a"
+        ));
+    }
+
+    #[test]
+    fn failed_response_reports_error() {
+        let mut mapper = EventMapper::default();
+        let event: ResponsesEvent = serde_json::from_str(
+            r#"{"type":"response.failed","response":{"id":"resp_1","error":{"message":"quota"}}}"#,
+        )
+        .unwrap();
+
+        let events = mapper.map(event).unwrap();
+
+        assert!(mapper.finished);
+        assert!(matches!(&events[0], CompletionEvent::Error(m) if m == "quota"));
+    }
+
+    #[test]
+    fn invalid_function_arguments_are_a_parse_error() {
+        let mut mapper = EventMapper::default();
+        let event: ResponsesEvent = serde_json::from_str(
+            r#"{"type":"response.output_item.done","output_index":0,"item":{"type":"function_call","call_id":"call_1","name":"Read","arguments":"{\"path\":"}}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(mapper.map(event), Err(AgentError::Parse(_))));
+    }
+
+    #[test]
+    fn converts_tool_round_trip_and_reasoning_items() {
+        let signature = serde_json::to_string(&ReasoningSignature {
+            id: "rs_1".into(),
+            encrypted_content: Some("enc".into()),
+        })
+        .unwrap();
+        let messages = vec![
+            user("read a"),
+            Message {
+                role: Role::Assistant,
+                content: Content::Blocks(vec![
+                    ContentBlock::Thinking {
+                        thinking: "plan".into(),
+                        signature: Some(signature),
+                    },
+                    ContentBlock::Thinking {
+                        thinking: "foreign".into(),
+                        signature: Some("anthropic-signature".into()),
+                    },
+                    ContentBlock::ToolUse {
+                        id: "call_1".into(),
+                        name: "Read".into(),
+                        input: serde_json::json!({"path": "a"}),
+                    },
+                ]),
+            },
+            Message {
+                role: Role::User,
+                content: Content::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: "call_1".into(),
+                    content: Content::Text("contents".into()),
+                    is_error: None,
+                }]),
+            },
+        ];
+
+        let items = serde_json::to_value(convert_messages(&messages).unwrap()).unwrap();
+
+        assert_eq!(items.as_array().unwrap().len(), 4);
+        assert_eq!(items[0]["content"][0]["type"], "input_text");
+        assert_eq!(items[1]["type"], "reasoning");
+        assert_eq!(items[1]["encrypted_content"], "enc");
+        assert_eq!(items[2]["type"], "function_call");
+        assert_eq!(items[2]["arguments"], r#"{"path":"a"}"#);
+        assert_eq!(items[3]["type"], "function_call_output");
+        assert_eq!(items[3]["output"], "contents");
+    }
+
+    #[test]
+    fn stored_responses_send_only_new_messages() {
+        let provider = OpenAiResponsesProvider::new("key")
+            .unwrap()
+            .with_store(true);
+        let reply = Message {
+            role: Role::Assistant,
+            content: Content::Text("hello".into()),
+        };
+        *provider.chain.lock() = Some(ResponseChain {
+            response_id: "resp_1".into(),
+            input_len: 1,
+            transcript_hash: messages_hash(&[user("hi"), reply.clone()]),
+        });
+
+        let request = CompletionRequest {
+            model: "gpt-5".into(),
+            messages: vec![user("hi"), reply, user("again")],
+            ..Default::default()
+        };
+        let wire = provider.convert_request(request.clone()).unwrap();
+        assert_eq!(wire.previous_response_id.as_deref(), Some("resp_1"));
+        assert_eq!(wire.input.len(), 1);
+
+        // A different history starts over
+        let edited = CompletionRequest {
+            messages: vec![user("bye"), request.messages[1].clone(), user("again")],
+            ..request.clone()
+        };
+        let wire = provider.convert_request(edited).unwrap();
+        assert!(wire.previous_response_id.is_none());
+        assert_eq!(wire.input.len(), 3);
+
+        // So does an edited reply, which the stored one must not replace
+        let forked = CompletionRequest {
+            messages: vec![
+                user("hi"),
+                Message {
+                    role: Role::Assistant,
+                    content: Content::Text("hello there".into()),
+                },
+                user("again"),
+            ],
+            ..request
+        };
+        let wire = provider.convert_request(forked).unwrap();
+        assert!(wire.previous_response_id.is_none());
+        assert_eq!(wire.input.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn streamed_replies_chain_the_next_request() {
+        let sse = concat!(
+            "data: {\"type\":\"response.output_text.delta\",\"delta\":\"Hi\"}\n\n",
+            "data: {\"type\":\"response.output_item.done\",\"output_index\":0,\"item\":{\"type\":\"message\",\"content\":[{\"type\":\"output_text\",\"text\":\"Hi\"}]}}\n\n",
+            "data: {\"type\":\"response.completed\",\"response\":{\"id\":\"resp_1\",\"status\":\"completed\"}}\n\n",
+        );
+        let server =
+            StubServer::start(|_, _| Some(response("200 OK", "text/event-stream", sse))).await;
+        let provider =
+            OpenAiResponsesProvider::with_config(Some("key".into()), Some(server.url().into()))
+                .unwrap()
+                .with_store(true);
+        let mut request = CompletionRequest {
+            model: "gpt-5".into(),
+            messages: vec![user("hi")],
+            ..Default::default()
+        };
+
+        let reply = collect(provider.stream(request.clone()).await.unwrap())
+            .await
+            .unwrap();
+        request.messages.extend([reply.message, user("again")]);
+
+        let wire = provider.convert_request(request).unwrap();
+        assert_eq!(wire.previous_response_id.as_deref(), Some("resp_1"));
+        assert_eq!(wire.input.len(), 1);
+    }
+}
@@ -0,0 +1,282 @@
+//! Record and replay provider streams as JSONL fixtures.
+//!
+//! Each fixture line holds one exchange: the hash of the request and the
+//! events the provider streamed back. Requests are hashed from every
+//! field that affects the output, so prompt caching hints do not change
+//! the key.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::error::{AgentError, Result};
+use crate::provider::{CompletionEvent, CompletionRequest, CompletionStream, LlmProvider};
+
+/// One recorded request and response.
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    key: String,
+    events: Vec<CompletionEvent>,
+}
+
+/// Provider wrapper that appends every completed stream to a fixture file.
+///
+/// Streams that fail part way are not recorded.
+#[derive(Clone)]
+pub struct RecordingProvider {
+    inner: Arc<dyn LlmProvider>,
+    path: PathBuf,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl std::fmt::Debug for RecordingProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordingProvider")
+            .field("inner", &self.inner.name())
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RecordingProvider {
+    /// Record streams from `inner` into the JSONL file at `path`.
+    #[must_use]
+    pub fn new(inner: Arc<dyn LlmProvider>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+            lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Append an exchange as one line.
+    async fn append(&self, exchange: &Exchange) -> Result<()> {
+        let mut line = serde_json::to_string(exchange)
+            .map_err(|e| AgentError::Parse(format!("failed to encode fixture: {e}")))?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| fixture_error(&self.path, &e))?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(|e| fixture_error(&self.path, &e))?;
+        // Tokio files write in the background until flushed
+        file.flush()
+            .await
+            .map_err(|e| fixture_error(&self.path, &e))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RecordingProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let key = request_key(&request);
+        let mut inner = self.inner.stream(request).await?;
+        let recorder = self.clone();
+
+        let stream = async_stream::stream! {
+            let mut events = Vec::new();
+            while let Some(event) = inner.next().await {
+                match event {
+                    Ok(event) => {
+                        events.push(event.clone());
+                        yield Ok(event);
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+            if let Err(e) = recorder.append(&Exchange { key, events }).await {
+                yield Err(e);
+            }
+        };
+
+        Ok(Box::pin(stream))
+    }
+}
+
+/// Provider that serves recorded streams without network access.
+///
+/// Identical requests recorded more than once are served in recording
+/// order; the last recording repeats once the others are used up.
+#[derive(Debug, Default)]
+pub struct ReplayProvider {
+    exchanges: Mutex<HashMap<String, VecDeque<Vec<CompletionEvent>>>>,
+}
+
+impl ReplayProvider {
+    /// Load exchanges from a JSONL fixture file.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the file cannot be read or a line is not a
+    /// recorded exchange.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| fixture_error(path, &e))?;
+
+        let mut exchanges: HashMap<String, VecDeque<Vec<CompletionEvent>>> = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: Exchange = serde_json::from_str(line).map_err(|e| {
+                AgentError::Parse(format!("{}:{}: {e}", path.display(), number + 1))
+            })?;
+            exchanges
+                .entry(exchange.key)
+                .or_default()
+                .push_back(exchange.events);
+        }
+
+        Ok(Self {
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ReplayProvider {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let key = request_key(&request);
+        let events = {
+            let mut exchanges = self.exchanges.lock();
+            let recorded = exchanges.get_mut(&key).ok_or_else(|| {
+                AgentError::Config(format!(
+                    "no recorded response for request {key} (model '{}')",
+                    request.model
+                ))
+            })?;
+            if recorded.len() > 1 {
+                recorded.pop_front().unwrap_or_default()
+            } else {
+                recorded.front().cloned().unwrap_or_default()
+            }
+        };
+        Ok(Box::pin(futures::stream::iter(events.into_iter().map(Ok))))
+    }
+}
+
+/// Hash the output-relevant fields of a request.
+///
+/// Uses FNV-1a over canonical JSON so keys stay stable across Rust
+/// versions and platforms.
+fn request_key(request: &CompletionRequest) -> String {
+    let canonical = serde_json::json!({
+        "model": request.model,
+        "max_tokens": request.max_tokens,
+        "messages": request.messages,
+        "system": request.system,
+        "tools": request.tools,
+        "temperature": request.temperature,
+        "top_p": request.top_p,
+        "stop_sequences": request.stop_sequences,
+        "tool_choice": request.tool_choice,
+        "parallel_tool_calls": request.parallel_tool_calls,
+        "thinking_budget": request.thinking_budget,
+        "response_format": request.response_format.as_ref().map(|f| (&f.name, &f.schema)),
+    });
+
+    let hash = canonical
+        .to_string()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{hash:016x}")
+}
+
+fn fixture_error(path: &Path, error: &std::io::Error) -> AgentError {
+    AgentError::Config(format!("fixture {}: {error}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ToolChoice;
+    use crate::provider::{PromptCache, collect};
+    use crate::providers::MockProvider;
+    use crate::types::{Content, Message, Role};
+
+    fn request(text: &str) -> CompletionRequest {
+        CompletionRequest {
+            model: "claude-sonnet-4-5".into(),
+            max_tokens: 100,
+            messages: vec![Message {
+                role: Role::User,
+                content: Content::Text(text.into()),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_streams_by_request() {
+        let dir = std::env::temp_dir().join(format!("replay-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fixture.jsonl");
+
+        let mock = MockProvider::new().with_text("first").with_text("second");
+        let recorder = RecordingProvider::new(Arc::new(mock), &path);
+        for text in ["a", "b"] {
+            collect(recorder.stream(request(text)).await.unwrap())
+                .await
+                .unwrap();
+        }
+
+        let replay = ReplayProvider::load(&path).unwrap();
+        let b = collect(replay.stream(request("b")).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(b.text(), "second");
+
+        // Cache hints do not change the key
+        let cached = CompletionRequest {
+            cache: Some(PromptCache::Auto),
+            ..request("a")
+        };
+        let a = collect(replay.stream(cached).await.unwrap()).await.unwrap();
+        assert_eq!(a.text(), "first");
+
+        assert!(matches!(
+            replay.stream(request("c")).await,
+            Err(AgentError::Config(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn request_key_depends_on_content() {
+        assert_eq!(request_key(&request("a")), request_key(&request("a")));
+        assert_ne!(request_key(&request("a")), request_key(&request("b")));
+        assert_eq!(request_key(&request("a")).len(), 16);
+
+        let forced = CompletionRequest {
+            tool_choice: Some(ToolChoice::Tool("Read".into())),
+            ..request("a")
+        };
+        assert_ne!(request_key(&forced), request_key(&request("a")));
+    }
+}
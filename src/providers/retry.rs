@@ -0,0 +1,288 @@
+//! Retry policy for provider HTTP calls.
+//!
+//! Retries happen only while sending the request and reading the status
+//! line. Once a response starts streaming, errors surface to the caller,
+//! since a partially consumed stream cannot be replayed.
+
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::error::{AgentError, Result};
+
+/// Retry and backoff settings for provider requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the first attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound for computed backoff delays.
+    pub max_backoff: Duration,
+    /// Total time after which no further retries start.
+    pub max_elapsed: Duration,
+    /// Whether to randomize delays to spread out concurrent retries.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            max_elapsed: Duration::from_secs(60),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy that never retries.
+    #[must_use]
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Send a request, retrying transient failures.
+    ///
+    /// `build` is called once per attempt. Responses with status 408, 409,
+    /// 429 or 5xx (including Anthropic's 529) are retried, as are connect
+    /// and timeout errors. A `retry-after` or `retry-after-ms` header
+    /// replaces the computed backoff. An `x-should-retry` header overrides
+    /// the status-based decision.
+    ///
+    /// # Errors
+    ///
+    /// Returns the last failure as [`AgentError::Api`] or
+    /// [`AgentError::Http`] once retries or elapsed time are exhausted, or
+    /// immediately for errors that are not transient.
+    pub async fn send(
+        &self,
+        provider: &str,
+        build: impl Fn() -> RequestBuilder + Send,
+    ) -> Result<Response> {
+        let start = Instant::now();
+        let mut attempt = 0;
+
+        loop {
+            let (error, retry_after) = match build().send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let retryable = should_retry(status, response.headers());
+                    let retry_after = retry_after(response.headers());
+                    let message = response.text().await.unwrap_or_default();
+                    let error = AgentError::Api {
+                        status: status.as_u16(),
+                        message,
+                    };
+                    if !retryable {
+                        return Err(error);
+                    }
+                    (error, retry_after)
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => (AgentError::Http(e), None),
+                Err(e) => return Err(AgentError::Http(e)),
+            };
+
+            if attempt >= self.max_retries {
+                return Err(error);
+            }
+            let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+            if start.elapsed() + delay > self.max_elapsed {
+                tracing::warn!(provider, attempt, error = %error, "retry time exhausted");
+                return Err(error);
+            }
+
+            attempt += 1;
+            tracing::warn!(
+                provider,
+                attempt,
+                max_retries = self.max_retries,
+                delay_ms = u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+                error = %error,
+                "retrying request"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Compute the backoff before retry number `attempt` (zero-based).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+
+        if self.jitter {
+            // Keep at least half the delay so retries still back off
+            delay.mul_f64(fastrand::f64().mul_add(0.5, 0.5))
+        } else {
+            delay
+        }
+    }
+}
+
+/// Decide whether a failed response is worth retrying.
+fn should_retry(status: StatusCode, headers: &HeaderMap) -> bool {
+    match headers.get("x-should-retry").and_then(|v| v.to_str().ok()) {
+        Some("true") => return true,
+        Some("false") => return false,
+        _ => {}
+    }
+
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT | StatusCode::CONFLICT | StatusCode::TOO_MANY_REQUESTS
+    ) || status.is_server_error()
+}
+
+/// Read the server-requested delay from response headers.
+///
+/// Supports `retry-after-ms`, and `retry-after` as seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Duration::try_from_secs_f64(ms / 1000.0).ok();
+    }
+
+    let value = header("retry-after")?.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{StubServer, response};
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            max_elapsed: Duration::from_secs(5),
+            jitter: false,
+        }
+    }
+
+    fn overloaded() -> String {
+        response("529 Overloaded", "text/plain", "overloaded")
+    }
+
+    #[tokio::test]
+    async fn retries_transient_status_until_success() {
+        let server = StubServer::sequence(vec![
+            overloaded(),
+            overloaded(),
+            response("200 OK", "text/plain", "ok"),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+
+        let response = fast_policy()
+            .send("test", || client.get(server.url()))
+            .await
+            .unwrap();
+
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert_eq!(server.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let server = StubServer::sequence(vec![overloaded()]).await;
+        let client = reqwest::Client::new();
+
+        let result = fast_policy()
+            .send("test", || client.get(server.url()))
+            .await;
+
+        assert!(matches!(result, Err(AgentError::Api { status: 529, .. })));
+        assert_eq!(server.hits(), 4);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server =
+            StubServer::sequence(vec![response("401 Unauthorized", "text/plain", "bad")]).await;
+        let client = reqwest::Client::new();
+
+        let result = fast_policy()
+            .send("test", || client.get(server.url()))
+            .await;
+
+        assert!(
+            matches!(result, Err(AgentError::Api { status: 401, message }) if message == "bad")
+        );
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_after_beyond_max_elapsed_stops() {
+        let server = StubServer::sequence(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nretry-after: 120\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string(),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+
+        let result = fast_policy()
+            .send("test", || client.get(server.url()))
+            .await;
+
+        assert!(matches!(result, Err(AgentError::Api { status: 429, .. })));
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[test]
+    fn retry_after_header_formats() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+
+        headers.insert("retry-after-ms", "150".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(150)));
+
+        let mut past = HeaderMap::new();
+        past.insert(
+            "retry-after",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&past), None);
+    }
+
+    #[test]
+    fn should_retry_honors_override_header() {
+        let mut headers = HeaderMap::new();
+        assert!(should_retry(StatusCode::SERVICE_UNAVAILABLE, &headers));
+        assert!(!should_retry(StatusCode::BAD_REQUEST, &headers));
+
+        headers.insert("x-should-retry", "false".parse().unwrap());
+        assert!(!should_retry(StatusCode::SERVICE_UNAVAILABLE, &headers));
+    }
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(8));
+
+        let jittered = RetryPolicy::default().backoff(1);
+        assert!(jittered >= Duration::from_millis(500) && jittered <= Duration::from_secs(1));
+    }
+}
@@ -5,18 +5,31 @@
 
 use async_trait::async_trait;
 use futures::StreamExt;
-use llm::builder::{LLMBackend, LLMBuilder};
-use llm::chat::{ChatMessage, FunctionTool, StreamChunk, Tool as LlmTool};
+use llm::builder::{FunctionBuilder, LLMBackend, LLMBuilder};
+use llm::chat::{
+    ChatMessage, FunctionTool, ImageMime, ReasoningEffort, StreamChunk, Tool as LlmTool,
+    ToolChoice as LlmToolChoice, Usage as LlmUsage,
+};
 use llm::{FunctionCall, LLMProvider, ToolCall as LlmToolCall};
 
 use crate::error::{AgentError, Result};
-use crate::provider::{CompletionEvent, CompletionRequest, CompletionStream, LlmProvider};
-use crate::types::{Content, ContentBlock, Message, Role, StopReason, Tool};
+use crate::provider::{
+    CompletionEvent, CompletionRequest, CompletionStream, LlmProvider, ToolChoice, cancellable,
+    cancellable_stream, reasoning_effort,
+};
+use crate::providers::RetryPolicy;
+use crate::types::{Content, ContentBlock, MediaSource, Message, Role, StopReason, Tool, Usage};
 
 /// Unified LLM provider supporting multiple backends.
+///
+/// The underlying `llm` client is built per request so the request's
+/// model, token limit and sampling parameters apply.
 pub struct UnifiedProvider {
-    inner: Box<dyn LLMProvider>,
+    backend: LLMBackend,
+    api_key: Option<String>,
+    base_url: Option<String>,
     name: &'static str,
+    retry: RetryPolicy,
 }
 
 impl std::fmt::Debug for UnifiedProvider {
@@ -30,105 +43,191 @@ impl std::fmt::Debug for UnifiedProvider {
 impl UnifiedProvider {
     /// Create a new Anthropic provider.
     pub fn anthropic(api_key: impl Into<String>) -> Result<Self> {
-        let api_key = api_key.into();
+        Self::keyed(LLMBackend::Anthropic, api_key.into(), "anthropic")
+    }
+
+    /// Create a new `OpenAI` provider.
+    pub fn openai(api_key: Option<String>, base_url: Option<String>) -> Result<Self> {
+        Self::build(LLMBackend::OpenAI, api_key, base_url, "openai")
+    }
+
+    /// Create a new Google Gemini provider.
+    pub fn google(api_key: impl Into<String>) -> Result<Self> {
+        Self::keyed(LLMBackend::Google, api_key.into(), "google")
+    }
+
+    /// Create a new Groq provider.
+    pub fn groq(api_key: impl Into<String>) -> Result<Self> {
+        Self::keyed(LLMBackend::Groq, api_key.into(), "groq")
+    }
+
+    /// Create a new Mistral provider.
+    pub fn mistral(api_key: impl Into<String>) -> Result<Self> {
+        Self::keyed(LLMBackend::Mistral, api_key.into(), "mistral")
+    }
+
+    /// Set the retry policy for failed requests.
+    ///
+    /// The `llm` crate retries on its own, so only the attempt count and
+    /// backoff bounds apply; `retry-after` and the elapsed limit do not.
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Create a provider for a backend that requires an API key.
+    fn keyed(backend: LLMBackend, api_key: String, name: &'static str) -> Result<Self> {
         if api_key.is_empty() {
             return Err(AgentError::ApiKeyMissing);
         }
+        Self::build(backend, Some(api_key), None, name)
+    }
+
+    /// Create a provider, validating the configuration with a trial build.
+    fn build(
+        backend: LLMBackend,
+        api_key: Option<String>,
+        base_url: Option<String>,
+        name: &'static str,
+    ) -> Result<Self> {
+        let provider = Self {
+            backend,
+            api_key,
+            base_url,
+            name,
+            retry: RetryPolicy::default(),
+        };
 
-        let provider = LLMBuilder::new()
-            .backend(LLMBackend::Anthropic)
-            .api_key(api_key)
+        provider
+            .builder()
             .build()
             .map_err(|e| AgentError::ToolExecution(e.to_string()))?;
 
-        Ok(Self {
-            inner: provider,
-            name: "anthropic",
-        })
+        Ok(provider)
     }
 
-    /// Create a new `OpenAI` provider.
-    pub fn openai(api_key: Option<String>, base_url: Option<String>) -> Result<Self> {
-        let mut builder = LLMBuilder::new().backend(LLMBackend::OpenAI);
+    /// Start a builder with the backend and credentials.
+    fn builder(&self) -> LLMBuilder {
+        let mut builder = LLMBuilder::new().backend(self.backend.clone());
 
-        if let Some(key) = api_key {
+        if let Some(key) = &self.api_key {
             builder = builder.api_key(key);
         }
 
-        if let Some(url) = base_url {
+        if let Some(url) = &self.base_url {
             builder = builder.base_url(url);
         }
 
-        let provider = builder
-            .build()
-            .map_err(|e| AgentError::ToolExecution(e.to_string()))?;
+        if self.retry.max_retries > 0 {
+            let millis = |d: std::time::Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+            builder = builder
+                .resilient(true)
+                .resilient_attempts(self.retry.max_retries as usize + 1)
+                .resilient_backoff(
+                    millis(self.retry.initial_backoff),
+                    millis(self.retry.max_backoff),
+                )
+                .resilient_jitter(self.retry.jitter);
+        }
 
-        Ok(Self {
-            inner: provider,
-            name: "openai",
-        })
+        builder
     }
 
-    /// Create a new Google Gemini provider.
-    pub fn google(api_key: impl Into<String>) -> Result<Self> {
-        let api_key = api_key.into();
-        if api_key.is_empty() {
-            return Err(AgentError::ApiKeyMissing);
-        }
+    /// Whether the backend has a structured stream that reports usage.
+    const fn streams_usage(&self) -> bool {
+        !matches!(self.backend, LLMBackend::Anthropic)
+    }
 
-        let provider = LLMBuilder::new()
-            .backend(LLMBackend::Google)
-            .api_key(api_key)
-            .build()
-            .map_err(|e| AgentError::ToolExecution(e.to_string()))?;
+    /// Reject request fields this backend cannot honor.
+    fn check_supported(&self, request: &CompletionRequest) -> Result<()> {
+        let unsupported = |feature| {
+            Err(AgentError::Unsupported {
+                provider: self.name,
+                feature,
+            })
+        };
 
-        Ok(Self {
-            inner: provider,
-            name: "google",
-        })
-    }
+        if request.stop_sequences.is_some() {
+            return unsupported("stop sequences");
+        }
+        if request.response_format.is_some() {
+            return unsupported("JSON schema response format");
+        }
 
-    /// Create a new Groq provider.
-    pub fn groq(api_key: impl Into<String>) -> Result<Self> {
-        let api_key = api_key.into();
-        if api_key.is_empty() {
-            return Err(AgentError::ApiKeyMissing);
+        // Gemini ignores tool choice; only Groq and Mistral forward the parallel toggle
+        let tool_choice = !matches!(self.backend, LLMBackend::Google);
+        let parallel_tool_calls = matches!(self.backend, LLMBackend::Groq | LLMBackend::Mistral);
+
+        if request.tool_choice.is_some() && !tool_choice {
+            return unsupported("tool choice");
+        }
+        if request.parallel_tool_calls.is_some() && !parallel_tool_calls {
+            return unsupported("parallel tool call control");
         }
 
-        let provider = LLMBuilder::new()
-            .backend(LLMBackend::Groq)
-            .api_key(api_key)
-            .build()
-            .map_err(|e| AgentError::ToolExecution(e.to_string()))?;
+        // The crate drops thinking blocks, so only effort-based reasoning works
+        if request.thinking_budget.is_some() && !matches!(self.backend, LLMBackend::OpenAI) {
+            return unsupported("extended thinking");
+        }
 
-        Ok(Self {
-            inner: provider,
-            name: "groq",
-        })
+        Ok(())
     }
 
-    /// Create a new Mistral provider.
-    pub fn mistral(api_key: impl Into<String>) -> Result<Self> {
-        let api_key = api_key.into();
-        if api_key.is_empty() {
-            return Err(AgentError::ApiKeyMissing);
+    /// Build an `llm` client configured for a request.
+    fn client_for(&self, request: &CompletionRequest) -> Result<Box<dyn LLMProvider>> {
+        self.check_supported(request)?;
+
+        let mut builder = self
+            .builder()
+            .model(&request.model)
+            .max_tokens(request.max_tokens);
+
+        if let Some(temperature) = request.temperature {
+            builder = builder.temperature(temperature);
+        }
+        if let Some(top_p) = request.top_p {
+            builder = builder.top_p(top_p);
+        }
+        if let Some(parallel) = request.parallel_tool_calls {
+            builder = builder.enable_parallel_tool_use(parallel);
+        }
+        if let Some(budget) = request.thinking_budget {
+            builder = builder.reasoning_effort(match reasoning_effort(budget) {
+                "low" => ReasoningEffort::Low,
+                "medium" => ReasoningEffort::Medium,
+                _ => ReasoningEffort::High,
+            });
+        }
+        if let Some(choice) = &request.tool_choice {
+            // The llm crate validates a tool choice against builder tools
+            for tool in request.tools.iter().flatten() {
+                builder = builder.function(
+                    FunctionBuilder::new(&tool.name)
+                        .description(&tool.description)
+                        .json_schema(tool.input_schema.clone()),
+                );
+            }
+            builder = builder.tool_choice(match choice {
+                ToolChoice::Auto => LlmToolChoice::Auto,
+                ToolChoice::Any => LlmToolChoice::Any,
+                ToolChoice::None => LlmToolChoice::None,
+                ToolChoice::Tool(name) => LlmToolChoice::Tool(name.clone()),
+            });
         }
 
-        let provider = LLMBuilder::new()
-            .backend(LLMBackend::Mistral)
-            .api_key(api_key)
+        builder
             .build()
-            .map_err(|e| AgentError::ToolExecution(e.to_string()))?;
-
-        Ok(Self {
-            inner: provider,
-            name: "mistral",
-        })
+            .map_err(|e| AgentError::ToolExecution(e.to_string()))
     }
 }
 
 /// Convert our messages to the llm crate format.
-fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<ChatMessage> {
+///
+/// # Errors
+///
+/// Returns error if a message contains media the crate cannot send.
+fn convert_messages(messages: &[Message], system: Option<&str>) -> Result<Vec<ChatMessage>> {
     let mut result = Vec::new();
 
     // Add system message if present (as a user message with system context)
@@ -154,12 +253,16 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<ChatMessa
                 let mut text_parts = Vec::new();
                 let mut tool_uses = Vec::new();
                 let mut tool_results = Vec::new();
+                let mut media = Vec::new();
 
                 for block in blocks {
                     match block {
                         ContentBlock::Text { text } => {
                             text_parts.push(text.clone());
                         }
+                        ContentBlock::Image { .. } | ContentBlock::Document { .. } => {
+                            media.push(convert_media(msg.role, block)?);
+                        }
                         ContentBlock::ToolUse { id, name, input } => {
                             tool_uses.push(LlmToolCall {
                                 id: id.clone(),
@@ -170,11 +273,17 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<ChatMessa
                                 },
                             });
                         }
+                        // The crate has no message type for prior reasoning
+                        ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
                         ContentBlock::ToolResult {
                             tool_use_id,
                             content,
                             is_error,
                         } => {
+                            // Tool results are text-only; media follows as user messages
+                            for b in content.media_blocks() {
+                                media.push(convert_media(Role::User, b)?);
+                            }
                             tool_results.push(LlmToolCall {
                                 id: tool_use_id.clone(),
                                 call_type: "function".to_string(),
@@ -184,7 +293,7 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<ChatMessa
                                     } else {
                                         "result".to_string()
                                     },
-                                    arguments: content.clone(),
+                                    arguments: content.text(),
                                 },
                             });
                         }
@@ -217,11 +326,149 @@ fn convert_messages(messages: &[Message], system: Option<&str>) -> Vec<ChatMessa
                 if !tool_results.is_empty() {
                     result.push(ChatMessage::user().tool_result(tool_results).build());
                 }
+
+                // Each image or document is its own message in the llm crate
+                result.extend(media);
             }
         }
     }
 
-    result
+    Ok(result)
+}
+
+/// Convert an image or document block to a chat message.
+fn convert_media(role: Role, block: &ContentBlock) -> Result<ChatMessage> {
+    let builder = match role {
+        Role::User => ChatMessage::user(),
+        Role::Assistant => ChatMessage::assistant(),
+    };
+    let unsupported = |feature| AgentError::Unsupported {
+        provider: "unified",
+        feature,
+    };
+
+    let message = match block {
+        ContentBlock::Image {
+            source: MediaSource::Url { url },
+        } => builder.image_url(url).build(),
+        ContentBlock::Image {
+            source: MediaSource::Base64 { media_type, data },
+        } => {
+            let mime = match media_type.as_str() {
+                "image/jpeg" => ImageMime::JPEG,
+                "image/png" => ImageMime::PNG,
+                "image/gif" => ImageMime::GIF,
+                "image/webp" => ImageMime::WEBP,
+                _ => return Err(unsupported("this image type")),
+            };
+            builder.image(mime, decode_base64(data)?).build()
+        }
+        ContentBlock::Document {
+            source: MediaSource::Base64 { media_type, data },
+            ..
+        } if media_type == "application/pdf" => builder.pdf(decode_base64(data)?).build(),
+        ContentBlock::Document { .. } => return Err(unsupported("non-PDF or URL documents")),
+        _ => {
+            return Err(AgentError::Parse(
+                "expected an image or document block".to_string(),
+            ));
+        }
+    };
+
+    Ok(message)
+}
+
+/// Decode inline base64 media data.
+fn decode_base64(data: &str) -> Result<Vec<u8>> {
+    use base64::Engine;
+
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| AgentError::Parse(format!("invalid base64 media: {e}")))
+}
+
+/// Stream a reply without tools, reading the usage the backend reports.
+///
+/// The llm crate only surfaces usage on its structured streams, which
+/// cannot carry tool calls and do not report a stop reason. A reply that
+/// used the whole token budget is taken to have hit the limit.
+async fn stream_text(
+    client: Box<dyn LLMProvider>,
+    messages: Vec<ChatMessage>,
+    request: CompletionRequest,
+) -> Result<CompletionStream> {
+    let responses = cancellable(request.cancel.as_ref(), async {
+        client
+            .chat_stream_struct(&messages)
+            .await
+            .map_err(|e| AgentError::ToolExecution(e.to_string()))
+    })
+    .await?;
+    let max_tokens = request.max_tokens;
+
+    let stream = async_stream::stream! {
+        let mut text = String::new();
+        let mut usage = None;
+
+        futures::pin_mut!(responses);
+
+        while let Some(response) = responses.next().await {
+            match response {
+                Ok(response) => {
+                    // Usage is cumulative, so the last report wins
+                    if let Some(reported) = response.usage {
+                        usage = Some(convert_usage(&reported));
+                    }
+                    for choice in response.choices {
+                        if let Some(delta) = choice.delta.content
+                            && !delta.is_empty()
+                        {
+                            text.push_str(&delta);
+                            yield Ok(CompletionEvent::TextDelta(delta));
+                        }
+                    }
+                }
+                Err(e) => {
+                    yield Ok(CompletionEvent::Error(e.to_string()));
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            yield Ok(CompletionEvent::ContentBlockDone {
+                index: 0,
+                block: ContentBlock::Text { text },
+            });
+        }
+        let limited = usage
+            .as_ref()
+            .is_some_and(|u: &Usage| u.output_tokens >= max_tokens);
+        yield Ok(CompletionEvent::Done {
+            stop_reason: Some(if limited {
+                StopReason::MaxTokens
+            } else {
+                StopReason::EndTurn
+            }),
+            usage,
+        });
+    };
+
+    Ok(cancellable_stream(Box::pin(stream), request.cancel))
+}
+
+/// Convert llm crate usage, splitting cached tokens out of the input.
+fn convert_usage(usage: &LlmUsage) -> Usage {
+    let cached = usage
+        .prompt_tokens_details
+        .as_ref()
+        .and_then(|details| details.cached_tokens)
+        .unwrap_or(0);
+    Usage {
+        input_tokens: usage.prompt_tokens.saturating_sub(cached),
+        output_tokens: usage.completion_tokens,
+        cache_read_input_tokens: cached,
+        ..Usage::default()
+    }
 }
 
 /// Convert our tools to llm crate format.
@@ -246,14 +493,20 @@ impl LlmProvider for UnifiedProvider {
     }
 
     async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
-        let messages = convert_messages(&request.messages, request.system.as_deref());
+        let messages = convert_messages(&request.messages, request.system.as_deref())?;
         let tools = request.tools.as_ref().map(|t| convert_tools(t));
 
-        let stream_result = self
-            .inner
-            .chat_stream_with_tools(&messages, tools.as_deref())
-            .await
-            .map_err(|e| AgentError::ToolExecution(e.to_string()))?;
+        let client = self.client_for(&request)?;
+        if tools.as_ref().is_none_or(Vec::is_empty) && self.streams_usage() {
+            return stream_text(client, messages, request).await;
+        }
+        let stream_result = cancellable(request.cancel.as_ref(), async {
+            client
+                .chat_stream_with_tools(&messages, tools.as_deref())
+                .await
+                .map_err(|e| AgentError::ToolExecution(e.to_string()))
+        })
+        .await?;
 
         let stream = async_stream::stream! {
             let mut current_text = String::new();
@@ -308,6 +561,7 @@ impl LlmProvider for UnifiedProvider {
                             _ => None,
                         };
 
+                        // Tool streams carry no usage; see `stream_text`
                         yield Ok(CompletionEvent::Done {
                             stop_reason: reason,
                             usage: None,
@@ -320,13 +574,15 @@ impl LlmProvider for UnifiedProvider {
             }
         };
 
-        Ok(Box::pin(stream))
+        Ok(cancellable_stream(Box::pin(stream), request.cancel))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::provider::collect;
+    use crate::test_support::{StubServer, response};
 
     #[test]
     fn anthropic_requires_api_key() {
@@ -351,4 +607,167 @@ mod tests {
         let result = UnifiedProvider::mistral("");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn convert_messages_emits_media_messages() {
+        let messages = vec![Message {
+            role: Role::User,
+            content: Content::Blocks(vec![
+                ContentBlock::Text {
+                    text: "Summarize".to_string(),
+                },
+                ContentBlock::image("image/png", b"png"),
+                ContentBlock::document("application/pdf", b"pdf", None),
+            ]),
+        }];
+
+        let converted = convert_messages(&messages, None).unwrap();
+        assert_eq!(converted.len(), 3);
+        assert!(matches!(
+            &converted[1].message_type,
+            llm::chat::MessageType::Image((ImageMime::PNG, bytes)) if bytes == b"png"
+        ));
+        assert!(matches!(
+            &converted[2].message_type,
+            llm::chat::MessageType::Pdf(bytes) if bytes == b"pdf"
+        ));
+    }
+
+    #[test]
+    fn convert_messages_rejects_unknown_image_type() {
+        let messages = vec![Message {
+            role: Role::User,
+            content: Content::Blocks(vec![ContentBlock::image("image/tiff", b"tiff")]),
+        }];
+
+        assert!(matches!(
+            convert_messages(&messages, None),
+            Err(AgentError::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn stop_sequences_are_unsupported() {
+        let provider = UnifiedProvider::groq("key").unwrap();
+        let request = CompletionRequest {
+            model: "llama-3.3-70b-versatile".to_string(),
+            max_tokens: 100,
+            stop_sequences: Some(vec!["END".to_string()]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            provider.client_for(&request),
+            Err(AgentError::Unsupported {
+                feature: "stop sequences",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn google_rejects_tool_choice() {
+        let provider = UnifiedProvider::google("key").unwrap();
+        let request = CompletionRequest {
+            model: "gemini-2.0-flash".to_string(),
+            max_tokens: 100,
+            tool_choice: Some(ToolChoice::Any),
+            ..Default::default()
+        };
+        assert!(matches!(
+            provider.client_for(&request),
+            Err(AgentError::Unsupported {
+                provider: "google",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn thinking_budget_needs_reasoning_effort_backend() {
+        let request = CompletionRequest {
+            model: "o4-mini".to_string(),
+            max_tokens: 100,
+            thinking_budget: Some(8000),
+            ..Default::default()
+        };
+        assert!(
+            UnifiedProvider::openai(Some("key".to_string()), None)
+                .unwrap()
+                .client_for(&request)
+                .is_ok()
+        );
+        assert!(matches!(
+            UnifiedProvider::anthropic("key")
+                .unwrap()
+                .client_for(&request),
+            Err(AgentError::Unsupported {
+                feature: "extended thinking",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn groq_accepts_sampling_and_tool_choice() {
+        let provider = UnifiedProvider::groq("key").unwrap();
+        let request = CompletionRequest {
+            model: "llama-3.3-70b-versatile".to_string(),
+            max_tokens: 100,
+            tools: Some(vec![Tool {
+                name: "lookup".to_string(),
+                description: "Look something up".to_string(),
+                input_schema: serde_json::json!({"type": "object", "properties": {}}),
+            }]),
+            temperature: Some(0.2),
+            top_p: Some(0.9),
+            tool_choice: Some(ToolChoice::Tool("lookup".to_string())),
+            parallel_tool_calls: Some(false),
+            ..Default::default()
+        };
+        assert!(provider.client_for(&request).is_ok());
+    }
+
+    #[tokio::test]
+    async fn text_streams_report_usage() {
+        let body = [
+            r#"data: {"choices":[{"delta":{"content":"Hi"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":" there"}}],"usage":{"prompt_tokens":12,"completion_tokens":2,"total_tokens":14,"prompt_tokens_details":{"cached_tokens":4}}}"#,
+            "data: [DONE]",
+        ]
+        .map(|line| format!("{line}\n\n"))
+        .concat();
+        let server =
+            StubServer::start(move |_, _| Some(response("200 OK", "text/event-stream", &body)))
+                .await;
+        let provider = UnifiedProvider::openai(
+            Some("sk-test".into()),
+            Some(format!("{}/v1/", server.url())),
+        )
+        .unwrap();
+        let request = CompletionRequest {
+            model: "gpt-4o".to_string(),
+            max_tokens: 100,
+            messages: vec![Message {
+                role: Role::User,
+                content: Content::Text("hello".into()),
+            }],
+            ..Default::default()
+        };
+
+        let response = collect(provider.stream(request).await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.text(), "Hi there");
+        assert_eq!(response.stop_reason, Some(StopReason::EndTurn));
+        let usage = response.usage.unwrap();
+        assert_eq!(
+            (
+                usage.input_tokens,
+                usage.cache_read_input_tokens,
+                usage.output_tokens
+            ),
+            (8, 4, 2)
+        );
+    }
 }
@@ -0,0 +1,944 @@
+//! Layered provider and model configuration
+//!
+//! Configuration is built from four layers, each overriding the one
+//! before it:
+//!
+//! 1. Built-in defaults from [`default_providers`] and [`default_models`]
+//! 2. `config.toml` / `config.json` in the global config dir
+//!    (`~/.config/omni` on Linux)
+//! 3. `config.toml` / `config.json` in the project's `.omni/` dir
+//! 4. `OMNI_PROVIDER__<NAME>__<FIELD>` environment variables
+//!
+//! Files share one layout:
+//!
+//! ```toml
+//! [providers.groq]
+//! disabled = true
+//!
+//! [providers.proxy]
+//! type = "openai"
+//! base_url = "https://proxy.example.com/v1"
+//! api_key_env = "PROXY_API_KEY"
+//! headers = { "x-org-id" = "acme" }
+//! read_timeout_secs = 120
+//!
+//! [models."llama3.2"]
+//! provider = "ollama"
+//! context_window = 131072
+//! supports_tools = true
+//! aliases = ["local"]
+//! ```
+//!
+//! Loading never fails outright; problems are reported as diagnostics
+//! carrying the file and key they came from
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::defaults::{default_models, default_providers};
+use super::types::{ModelInfo, ModelPricing, ProviderApiType, ProviderConfig};
+
+/// Config file names looked up in each config dir, in load order
+const CONFIG_FILES: [&str; 2] = ["config.toml", "config.json"];
+
+/// Prefix of provider environment variables
+const ENV_PREFIX: &str = "OMNI_PROVIDER__";
+
+/// Where a configuration value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// Built-in defaults
+    Defaults,
+    /// A config file
+    File(PathBuf),
+    /// An environment variable
+    Env(String),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Defaults => write!(f, "<defaults>"),
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Env(var) => write!(f, "${var}"),
+        }
+    }
+}
+
+/// Severity of a configuration diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticSeverity {
+    /// The offending entry is invalid and was ignored or will fail
+    Error,
+    /// The entry was applied but is likely a mistake
+    Warning,
+}
+
+/// Problem found while loading configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    /// How serious the problem is
+    pub severity: DiagnosticSeverity,
+    /// Where the offending value came from
+    pub source: ConfigSource,
+    /// Line in the source file, for syntax errors
+    pub line: Option<usize>,
+    /// Dotted key path (e.g. `providers.proxy.type`), if any
+    pub key: Option<String>,
+    /// Human-readable description
+    pub message: String,
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            DiagnosticSeverity::Error => "error",
+            DiagnosticSeverity::Warning => "warning",
+        };
+        write!(f, "{severity}: {}", self.source)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, ": {key}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Result of loading configuration
+#[derive(Debug, Clone, Default)]
+pub struct LoadedConfig {
+    /// Enabled providers by name
+    pub providers: HashMap<String, ProviderConfig>,
+    /// Enabled models
+    pub models: Vec<ModelInfo>,
+    /// Model aliases, mapping to `provider/id`
+    pub aliases: HashMap<String, String>,
+    /// Config files that were read, in load order
+    pub files: Vec<PathBuf>,
+    /// Problems found while loading
+    pub diagnostics: Vec<ConfigDiagnostic>,
+}
+
+impl LoadedConfig {
+    /// Whether any diagnostic is an error
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == DiagnosticSeverity::Error)
+    }
+
+    /// Look up a model by alias, `provider/id` or bare ID
+    #[must_use]
+    pub fn model(&self, name: &str) -> Option<&ModelInfo> {
+        let name = self.aliases.get(name).map_or(name, String::as_str);
+        self.models.iter().find(|m| m.id == name).or_else(|| {
+            let (provider, id) = name.split_once('/')?;
+            self.models
+                .iter()
+                .find(|m| m.provider == provider && m.id == id)
+        })
+    }
+}
+
+/// Loads layered provider and model configuration
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    global_dir: Option<PathBuf>,
+    project_dir: Option<PathBuf>,
+    env: Option<Vec<(String, String)>>,
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigLoader {
+    /// Create a loader for the user's global config dir, the current
+    /// directory's `.omni/` dir and the process environment
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            global_dir: directories::BaseDirs::new().map(|base| base.config_dir().join("omni")),
+            project_dir: std::env::current_dir().ok().map(|dir| dir.join(".omni")),
+            env: None,
+        }
+    }
+
+    /// Read global config from `dir` instead of the user config dir
+    #[must_use]
+    pub fn with_global_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.global_dir = Some(dir.into());
+        self
+    }
+
+    /// Skip the global config layer
+    #[must_use]
+    pub fn without_global_dir(mut self) -> Self {
+        self.global_dir = None;
+        self
+    }
+
+    /// Read project config from `root/.omni/`
+    #[must_use]
+    pub fn with_project_root(mut self, root: impl AsRef<Path>) -> Self {
+        self.project_dir = Some(root.as_ref().join(".omni"));
+        self
+    }
+
+    /// Skip the project config layer
+    #[must_use]
+    pub fn without_project(mut self) -> Self {
+        self.project_dir = None;
+        self
+    }
+
+    /// Read environment overrides from `vars` instead of the process
+    #[must_use]
+    pub fn with_env(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.env = Some(vars.into_iter().collect());
+        self
+    }
+
+    /// Skip the environment layer
+    #[must_use]
+    pub fn without_env(self) -> Self {
+        self.with_env([])
+    }
+
+    /// Load all layers
+    #[must_use]
+    pub fn load(&self) -> LoadedConfig {
+        let mut layers = Layers::defaults();
+
+        for dir in [&self.global_dir, &self.project_dir].into_iter().flatten() {
+            for name in CONFIG_FILES {
+                layers.apply_file(&dir.join(name));
+            }
+        }
+
+        match &self.env {
+            Some(vars) => layers.apply_env(vars.iter().cloned()),
+            None => layers.apply_env(std::env::vars()),
+        }
+
+        layers.finish()
+    }
+}
+
+/// Provider entry as written in a config file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProviderEntry {
+    #[serde(rename = "type")]
+    api_type: Option<ProviderApiType>,
+    base_url: Option<String>,
+    api_key_env: Option<String>,
+    api_key: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    connect_timeout_secs: Option<u64>,
+    read_timeout_secs: Option<u64>,
+    proxy: Option<String>,
+    ca_cert: Option<PathBuf>,
+    disabled: Option<bool>,
+}
+
+/// Model entry as written in a config file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelEntry {
+    provider: Option<String>,
+    context_window: Option<u32>,
+    max_output_tokens: Option<u32>,
+    supports_tools: Option<bool>,
+    supports_vision: Option<bool>,
+    supports_reasoning: Option<bool>,
+    pricing: Option<ModelPricing>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    disabled: Option<bool>,
+}
+
+/// Merge state while layers are applied
+///
+/// Every entry remembers the source that last touched it so validation
+/// can point at it
+#[derive(Debug, Default)]
+struct Layers {
+    providers: HashMap<String, (ProviderConfig, ConfigSource)>,
+    disabled_providers: HashSet<String>,
+    models: Vec<(ModelInfo, ConfigSource)>,
+    disabled_models: HashSet<(String, String)>,
+    aliases: HashMap<String, (String, ConfigSource)>,
+    files: Vec<PathBuf>,
+    diagnostics: Vec<ConfigDiagnostic>,
+}
+
+impl Layers {
+    fn defaults() -> Self {
+        Self {
+            providers: default_providers()
+                .into_iter()
+                .map(|(name, config)| (name, (config, ConfigSource::Defaults)))
+                .collect(),
+            models: default_models()
+                .into_iter()
+                .map(|model| (model, ConfigSource::Defaults))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn report(
+        &mut self,
+        severity: DiagnosticSeverity,
+        source: &ConfigSource,
+        key: Option<String>,
+        message: impl Into<String>,
+    ) {
+        self.diagnostics.push(ConfigDiagnostic {
+            severity,
+            source: source.clone(),
+            line: None,
+            key,
+            message: message.into(),
+        });
+    }
+
+    /// Read and apply one config file, skipping it if absent
+    fn apply_file(&mut self, path: &Path) {
+        let source = ConfigSource::File(path.to_path_buf());
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                self.report(DiagnosticSeverity::Error, &source, None, e.to_string());
+                return;
+            }
+        };
+        self.files.push(path.to_path_buf());
+
+        let parsed = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str::<Value>(&content).map_err(|e| (Some(e.line()), e.to_string()))
+        } else {
+            toml::from_str::<toml::Table>(&content)
+                .map_err(|e| {
+                    let line = e.span().map(|span| line_of(&content, span.start));
+                    (line, e.message().to_string())
+                })
+                .and_then(|table| serde_json::to_value(table).map_err(|e| (None, e.to_string())))
+        };
+
+        match parsed {
+            Ok(document) => self.apply_document(&source, document),
+            Err((line, message)) => self.diagnostics.push(ConfigDiagnostic {
+                severity: DiagnosticSeverity::Error,
+                source,
+                line,
+                key: None,
+                message,
+            }),
+        }
+    }
+
+    /// Apply a parsed config document
+    fn apply_document(&mut self, source: &ConfigSource, document: Value) {
+        let Value::Object(document) = document else {
+            self.report(
+                DiagnosticSeverity::Error,
+                source,
+                None,
+                "expected a table at the top level",
+            );
+            return;
+        };
+
+        for (section, value) in document {
+            let entries = match (section.as_str(), value) {
+                ("providers" | "models", Value::Object(entries)) => entries,
+                ("providers" | "models", _) => {
+                    self.report(
+                        DiagnosticSeverity::Error,
+                        source,
+                        Some(section),
+                        "expected a table",
+                    );
+                    continue;
+                }
+                _ => {
+                    self.report(
+                        DiagnosticSeverity::Warning,
+                        source,
+                        Some(section),
+                        "unknown section, ignored",
+                    );
+                    continue;
+                }
+            };
+
+            for (name, value) in entries {
+                let key = format!("{section}.{name}");
+                if section == "providers" {
+                    match serde_json::from_value::<ProviderEntry>(value) {
+                        Ok(entry) => self.apply_provider(source, &key, &name, entry),
+                        Err(e) => {
+                            self.report(
+                                DiagnosticSeverity::Error,
+                                source,
+                                Some(key),
+                                e.to_string(),
+                            );
+                        }
+                    }
+                } else {
+                    match serde_json::from_value::<ModelEntry>(value) {
+                        Ok(entry) => self.apply_model(source, &key, name, entry),
+                        Err(e) => {
+                            self.report(
+                                DiagnosticSeverity::Error,
+                                source,
+                                Some(key),
+                                e.to_string(),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply_provider(
+        &mut self,
+        source: &ConfigSource,
+        key: &str,
+        name: &str,
+        entry: ProviderEntry,
+    ) {
+        match entry.disabled {
+            Some(true) => {
+                self.disabled_providers.insert(name.to_string());
+            }
+            Some(false) => {
+                self.disabled_providers.remove(name);
+            }
+            None => {}
+        }
+
+        if entry.api_key.is_some() && matches!(source, ConfigSource::File(_)) {
+            self.report(
+                DiagnosticSeverity::Warning,
+                source,
+                Some(format!("{key}.api_key")),
+                "API key stored in a config file, prefer api_key_env",
+            );
+        }
+
+        if !self.providers.contains_key(name) {
+            let Some(api_type) = entry.api_type.clone() else {
+                // Disabling an unknown provider is harmless
+                if entry.disabled != Some(true) {
+                    self.report(
+                        DiagnosticSeverity::Error,
+                        source,
+                        Some(format!("{key}.type")),
+                        "new provider needs a type",
+                    );
+                }
+                return;
+            };
+            let config = ProviderConfig {
+                api_type,
+                ..Default::default()
+            };
+            self.providers
+                .insert(name.to_string(), (config, source.clone()));
+        }
+        let Some((config, origin)) = self.providers.get_mut(name) else {
+            return;
+        };
+        *origin = source.clone();
+
+        if let Some(api_type) = entry.api_type {
+            config.api_type = api_type;
+        }
+        if let Some(base_url) = entry.base_url {
+            config.base_url = Some(base_url);
+        }
+        if let Some(api_key_env) = entry.api_key_env {
+            config.api_key_env = Some(api_key_env);
+        }
+        if let Some(api_key) = entry.api_key {
+            config.api_key = Some(api_key);
+        }
+        config.headers.extend(entry.headers);
+        if let Some(secs) = entry.connect_timeout_secs {
+            config.connect_timeout_secs = Some(secs);
+        }
+        if let Some(secs) = entry.read_timeout_secs {
+            config.read_timeout_secs = Some(secs);
+        }
+        if let Some(proxy) = entry.proxy {
+            config.proxy = Some(proxy);
+        }
+        if let Some(ca_cert) = entry.ca_cert {
+            config.ca_cert = Some(ca_cert);
+        }
+    }
+
+    fn apply_model(&mut self, source: &ConfigSource, key: &str, id: String, entry: ModelEntry) {
+        let index = self.models.iter().position(|(model, _)| {
+            model.id == id && entry.provider.as_ref().is_none_or(|p| *p == model.provider)
+        });
+        let index = if let Some(index) = index {
+            self.models[index].1 = source.clone();
+            index
+        } else {
+            let Some(provider) = entry.provider.clone() else {
+                self.report(
+                    DiagnosticSeverity::Error,
+                    source,
+                    Some(format!("{key}.provider")),
+                    "new model needs a provider",
+                );
+                return;
+            };
+            self.models
+                .push((ModelInfo::new(id.clone(), provider), source.clone()));
+            self.models.len() - 1
+        };
+
+        let model = &mut self.models[index].0;
+        if let Some(context_window) = entry.context_window {
+            model.context_window = Some(context_window);
+        }
+        if let Some(max_output_tokens) = entry.max_output_tokens {
+            model.max_output_tokens = Some(max_output_tokens);
+        }
+        if let Some(supports_tools) = entry.supports_tools {
+            model.supports_tools = supports_tools;
+        }
+        if let Some(supports_vision) = entry.supports_vision {
+            model.supports_vision = supports_vision;
+        }
+        if let Some(supports_reasoning) = entry.supports_reasoning {
+            model.supports_reasoning = supports_reasoning;
+        }
+        if let Some(pricing) = entry.pricing {
+            model.pricing = Some(pricing);
+        }
+
+        let identity = (model.provider.clone(), id);
+        let target = format!("{}/{}", identity.0, identity.1);
+        match entry.disabled {
+            Some(true) => {
+                self.disabled_models.insert(identity);
+            }
+            Some(false) => {
+                self.disabled_models.remove(&identity);
+            }
+            None => {}
+        }
+
+        for alias in entry.aliases {
+            if let Some((previous, _)) = self.aliases.get(&alias)
+                && *previous != target
+            {
+                self.report(
+                    DiagnosticSeverity::Warning,
+                    source,
+                    Some(format!("{key}.aliases")),
+                    format!("alias '{alias}' moved from {previous} to {target}"),
+                );
+            }
+            self.aliases.insert(alias, (target.clone(), source.clone()));
+        }
+    }
+
+    /// Apply `OMNI_PROVIDER__<NAME>__<FIELD>` variables
+    ///
+    /// Names are lowercased; fields are `TYPE`, `BASE_URL`, `API_KEY_ENV`,
+    /// `API_KEY`, `PROXY`, `CA_CERT`, `CONNECT_TIMEOUT_SECS`,
+    /// `READ_TIMEOUT_SECS` and `DISABLED`
+    fn apply_env(&mut self, vars: impl Iterator<Item = (String, String)>) {
+        let mut vars: Vec<_> = vars
+            .filter(|(var, _)| var.starts_with(ENV_PREFIX))
+            .collect();
+        vars.sort();
+
+        for (var, value) in vars {
+            let source = ConfigSource::Env(var.clone());
+            let Some((name, field)) = var[ENV_PREFIX.len()..].split_once("__") else {
+                self.report(
+                    DiagnosticSeverity::Warning,
+                    &source,
+                    None,
+                    "expected OMNI_PROVIDER__<NAME>__<FIELD>",
+                );
+                continue;
+            };
+            let name = name.to_lowercase();
+            let key = format!("providers.{name}");
+            let field = field.to_lowercase();
+
+            let value = match field.as_str() {
+                "type" | "base_url" | "api_key_env" | "api_key" | "proxy" | "ca_cert" => {
+                    Value::String(value)
+                }
+                "connect_timeout_secs" | "read_timeout_secs" => {
+                    if let Ok(secs) = value.parse::<u64>() {
+                        Value::from(secs)
+                    } else {
+                        self.report(
+                            DiagnosticSeverity::Error,
+                            &source,
+                            Some(format!("{key}.{field}")),
+                            format!("expected a number of seconds, got '{value}'"),
+                        );
+                        continue;
+                    }
+                }
+                "disabled" => match value.to_lowercase().as_str() {
+                    "1" | "true" | "yes" => Value::Bool(true),
+                    "0" | "false" | "no" => Value::Bool(false),
+                    _ => {
+                        self.report(
+                            DiagnosticSeverity::Error,
+                            &source,
+                            Some(format!("{key}.disabled")),
+                            format!("expected a boolean, got '{value}'"),
+                        );
+                        continue;
+                    }
+                },
+                _ => {
+                    self.report(
+                        DiagnosticSeverity::Warning,
+                        &source,
+                        Some(format!("{key}.{field}")),
+                        "unknown provider field, ignored",
+                    );
+                    continue;
+                }
+            };
+
+            let entry = serde_json::json!({ field: value });
+            match serde_json::from_value::<ProviderEntry>(entry) {
+                Ok(entry) => self.apply_provider(&source, &key, &name, entry),
+                Err(e) => self.report(DiagnosticSeverity::Error, &source, Some(key), e.to_string()),
+            }
+        }
+    }
+
+    /// Drop disabled entries and validate what is left
+    fn finish(mut self) -> LoadedConfig {
+        let mut providers = HashMap::new();
+        for (name, (config, source)) in std::mem::take(&mut self.providers) {
+            if self.disabled_providers.contains(&name) {
+                continue;
+            }
+            if let ProviderApiType::Custom(type_name) = &config.api_type {
+                let message = match suggest_api_type(type_name) {
+                    Some(known) => format!("unknown type '{type_name}', did you mean '{known}'?"),
+                    None => format!(
+                        "custom type '{type_name}' needs a factory registered with ProviderRegistry::register_factory"
+                    ),
+                };
+                self.report(
+                    DiagnosticSeverity::Warning,
+                    &source,
+                    Some(format!("providers.{name}.type")),
+                    message,
+                );
+            }
+            if let Some(base_url) = &config.base_url
+                && !(base_url.starts_with("http://") || base_url.starts_with("https://"))
+            {
+                self.report(
+                    DiagnosticSeverity::Error,
+                    &source,
+                    Some(format!("providers.{name}.base_url")),
+                    format!("'{base_url}' is not an http(s) URL"),
+                );
+            }
+            providers.insert(name, config);
+        }
+
+        let mut models = Vec::new();
+        for (model, source) in std::mem::take(&mut self.models) {
+            let identity = (model.provider.clone(), model.id.clone());
+            if self.disabled_models.contains(&identity)
+                || self.disabled_providers.contains(&model.provider)
+            {
+                continue;
+            }
+            if !providers.contains_key(&model.provider) {
+                self.report(
+                    DiagnosticSeverity::Warning,
+                    &source,
+                    Some(format!("models.{}.provider", model.id)),
+                    format!("unknown provider '{}'", model.provider),
+                );
+            }
+            models.push(model);
+        }
+
+        let mut aliases = HashMap::new();
+        for (alias, (target, source)) in std::mem::take(&mut self.aliases) {
+            let id = target.split_once('/').map_or(target.as_str(), |(_, id)| id);
+            let key = Some(format!("models.{id}.aliases"));
+            if models.iter().any(|m| m.id == alias) {
+                self.report(
+                    DiagnosticSeverity::Error,
+                    &source,
+                    key,
+                    format!("alias '{alias}' shadows a model ID"),
+                );
+            } else if models
+                .iter()
+                .any(|m| format!("{}/{}", m.provider, m.id) == target)
+            {
+                aliases.insert(alias, target);
+            } else {
+                self.report(
+                    DiagnosticSeverity::Warning,
+                    &source,
+                    key,
+                    format!("alias '{alias}' points at disabled model {target}"),
+                );
+            }
+        }
+
+        LoadedConfig {
+            providers,
+            models,
+            aliases,
+            files: self.files,
+            diagnostics: self.diagnostics,
+        }
+    }
+}
+
+/// Built-in provider type closest to a misspelled one, if any is close
+fn suggest_api_type(type_name: &str) -> Option<&'static str> {
+    const BUILT_IN: [&str; 8] = [
+        "anthropic",
+        "openai",
+        "openai-responses",
+        "google",
+        "groq",
+        "mistral",
+        "ollama",
+        "synapse",
+    ];
+    let type_name = type_name.to_lowercase();
+    BUILT_IN
+        .into_iter()
+        .map(|known| (edit_distance(&type_name, known), known))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, known)| known)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// 1-based line number of a byte offset
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("config-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(dir.join(".omni")).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, content: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn loader(dir: &TempDir) -> ConfigLoader {
+        ConfigLoader::new()
+            .with_global_dir(&dir.0)
+            .with_project_root(&dir.0)
+            .without_env()
+    }
+
+    #[test]
+    fn layers_override_in_order() {
+        let dir = TempDir::new();
+        dir.write(
+            "config.toml",
+            r#"
+            [providers.groq]
+            disabled = true
+
+            [providers.openai]
+            base_url = "https://global.example.com/v1"
+
+            [providers.proxy]
+            type = "openai"
+            base_url = "https://proxy.example.com/v1"
+
+            [models."llama3.2"]
+            provider = "ollama"
+            context_window = 131072
+            supports_tools = true
+            aliases = ["local"]
+
+            [models.gpt-4o]
+            aliases = ["smart"]
+            "#,
+        );
+        dir.write(
+            ".omni/config.json",
+            r#"{"models": {"llama3.2": {"context_window": 32768}, "o1-mini": {"disabled": true}}}"#,
+        );
+
+        let config = loader(&dir)
+            .with_env([(
+                "OMNI_PROVIDER__OPENAI__BASE_URL".to_string(),
+                "https://env.example.com/v1".to_string(),
+            )])
+            .load();
+
+        assert!(config.diagnostics.is_empty(), "{:?}", config.diagnostics);
+        assert_eq!(config.files.len(), 2);
+        assert!(!config.providers.contains_key("groq"));
+        assert!(config.model("llama-3.3-70b-versatile").is_none());
+        assert!(config.model("o1-mini").is_none());
+        assert_eq!(
+            config.providers["openai"].base_url.as_deref(),
+            Some("https://env.example.com/v1")
+        );
+        assert_eq!(config.providers["proxy"].api_type, ProviderApiType::OpenAi);
+
+        let local = config.model("local").unwrap();
+        assert_eq!(local.id, "llama3.2");
+        assert_eq!(local.context_window, Some(32768));
+        assert!(local.supports_tools);
+        assert_eq!(config.model("smart").unwrap().id, "gpt-4o");
+    }
+
+    #[test]
+    fn reports_diagnostics_with_locations() {
+        let dir = TempDir::new();
+        let global = dir.write("config.toml", "[providers.proxy\nbase_url = 1\n");
+        let project = dir.write(
+            ".omni/config.json",
+            r#"{
+                "providers": {
+                    "proxy": {"base_url": "https://x"},
+                    "openai": {"base_ur": "https://x", "api_key": "sk"},
+                    "local": {"type": "openai", "base_url": "localhost:8080"},
+                    "typo": {"type": "opneai"}
+                },
+                "models": {
+                    "mystery": {"context_window": 10},
+                    "custom": {"provider": "nowhere", "aliases": ["gpt-4o"]}
+                },
+                "profiles": {}
+            }"#,
+        );
+
+        let config = loader(&dir)
+            .with_env([(
+                "OMNI_PROVIDER__OPENAI__DISABLED".to_string(),
+                "maybe".to_string(),
+            )])
+            .load();
+        assert!(config.has_errors());
+
+        let find = |key: &str| {
+            config
+                .diagnostics
+                .iter()
+                .find(|d| d.key.as_deref() == Some(key))
+                .unwrap_or_else(|| panic!("no diagnostic for {key}: {:?}", config.diagnostics))
+        };
+
+        let syntax = config
+            .diagnostics
+            .iter()
+            .find(|d| d.source == ConfigSource::File(global.clone()))
+            .unwrap();
+        assert_eq!(syntax.line, Some(1));
+
+        assert_eq!(
+            find("providers.proxy.type").source,
+            ConfigSource::File(project)
+        );
+        assert!(find("providers.openai").message.contains("base_ur"));
+        assert_eq!(
+            find("providers.local.base_url").severity,
+            DiagnosticSeverity::Error
+        );
+        assert_eq!(
+            find("models.mystery.provider").severity,
+            DiagnosticSeverity::Error
+        );
+        assert_eq!(
+            find("models.custom.provider").severity,
+            DiagnosticSeverity::Warning
+        );
+        assert_eq!(
+            find("models.custom.aliases").severity,
+            DiagnosticSeverity::Error
+        );
+        assert!(
+            find("providers.typo.type")
+                .message
+                .contains("did you mean 'openai'")
+        );
+        assert_eq!(find("profiles").severity, DiagnosticSeverity::Warning);
+        assert!(matches!(
+            &find("providers.openai.disabled").source,
+            ConfigSource::Env(var) if var == "OMNI_PROVIDER__OPENAI__DISABLED"
+        ));
+        assert!(
+            find("profiles")
+                .to_string()
+                .contains("config.json: profiles")
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::collections::HashMap;
 
-use super::types::{ModelInfo, ProviderApiType, ProviderConfig};
+use super::types::{ModelInfo, ModelPricing, ProviderApiType, ProviderConfig};
 
 /// Build a `ProviderConfig` with optional base URL and env var
 fn provider(
@@ -14,7 +14,7 @@ fn provider(
         api_type,
         base_url: base_url.map(String::from),
         api_key_env: api_key_env.map(String::from),
-        api_key: None,
+        ..Default::default()
     }
 }
 
@@ -33,8 +33,8 @@ pub fn default_providers() -> HashMap<String, ProviderConfig> {
         (
             "ollama".into(),
             provider(
-                ProviderApiType::OpenAi,
-                Some("http://localhost:11434/v1"),
+                ProviderApiType::Ollama,
+                Some("http://localhost:11434"),
                 None,
             ),
         ),
@@ -94,96 +94,109 @@ pub fn default_providers() -> HashMap<String, ProviderConfig> {
 }
 
 /// Get the default model definitions
+///
+/// Limits and capabilities follow each provider's published model
+/// documentation. Pricing reflects published list prices in USD per
+/// million tokens and is left unset where no stable list price exists
 #[must_use]
 pub fn default_models() -> Vec<ModelInfo> {
     vec![
         // Anthropic
-        ModelInfo {
-            id: "claude-sonnet-4-20250514".to_string(),
-            provider: "anthropic".to_string(),
-        },
-        ModelInfo {
-            id: "claude-opus-4-20250514".to_string(),
-            provider: "anthropic".to_string(),
-        },
-        ModelInfo {
-            id: "claude-3-5-haiku-20241022".to_string(),
-            provider: "anthropic".to_string(),
-        },
+        ModelInfo::new("claude-sonnet-4-20250514", "anthropic")
+            .with_limits(200_000, 64_000)
+            .with_tools()
+            .with_vision()
+            .with_reasoning()
+            .with_pricing(ModelPricing::new(3.0, 15.0).with_cache(3.75, 0.3)),
+        ModelInfo::new("claude-opus-4-20250514", "anthropic")
+            .with_limits(200_000, 32_000)
+            .with_tools()
+            .with_vision()
+            .with_reasoning()
+            .with_pricing(ModelPricing::new(15.0, 75.0).with_cache(18.75, 1.5)),
+        ModelInfo::new("claude-3-5-haiku-20241022", "anthropic")
+            .with_limits(200_000, 8_192)
+            .with_tools()
+            .with_vision()
+            .with_pricing(ModelPricing::new(0.8, 4.0).with_cache(1.0, 0.08)),
         // OpenAI
-        ModelInfo {
-            id: "gpt-4o".to_string(),
-            provider: "openai".to_string(),
-        },
-        ModelInfo {
-            id: "gpt-4-turbo".to_string(),
-            provider: "openai".to_string(),
-        },
-        ModelInfo {
-            id: "gpt-3.5-turbo".to_string(),
-            provider: "openai".to_string(),
-        },
-        ModelInfo {
-            id: "o1".to_string(),
-            provider: "openai".to_string(),
-        },
-        ModelInfo {
-            id: "o1-mini".to_string(),
-            provider: "openai".to_string(),
-        },
+        ModelInfo::new("gpt-4o", "openai")
+            .with_limits(128_000, 16_384)
+            .with_tools()
+            .with_vision()
+            .with_pricing(ModelPricing::new(2.5, 10.0).with_cache(2.5, 1.25)),
+        ModelInfo::new("gpt-4-turbo", "openai")
+            .with_limits(128_000, 4_096)
+            .with_tools()
+            .with_vision()
+            .with_pricing(ModelPricing::new(10.0, 30.0)),
+        ModelInfo::new("gpt-3.5-turbo", "openai")
+            .with_limits(16_385, 4_096)
+            .with_tools()
+            .with_pricing(ModelPricing::new(0.5, 1.5)),
+        ModelInfo::new("o1", "openai")
+            .with_limits(200_000, 100_000)
+            .with_tools()
+            .with_vision()
+            .with_reasoning()
+            .with_pricing(ModelPricing::new(15.0, 60.0).with_cache(15.0, 7.5)),
+        ModelInfo::new("o1-mini", "openai")
+            .with_limits(128_000, 65_536)
+            .with_reasoning()
+            .with_pricing(ModelPricing::new(1.1, 4.4).with_cache(1.1, 0.55)),
         // Groq (fast inference)
-        ModelInfo {
-            id: "llama-3.3-70b-versatile".to_string(),
-            provider: "groq".to_string(),
-        },
-        ModelInfo {
-            id: "llama-3.1-8b-instant".to_string(),
-            provider: "groq".to_string(),
-        },
-        ModelInfo {
-            id: "mixtral-8x7b-32768".to_string(),
-            provider: "groq".to_string(),
-        },
+        ModelInfo::new("llama-3.3-70b-versatile", "groq")
+            .with_limits(131_072, 32_768)
+            .with_tools()
+            .with_pricing(ModelPricing::new(0.59, 0.79)),
+        ModelInfo::new("llama-3.1-8b-instant", "groq")
+            .with_limits(131_072, 131_072)
+            .with_tools()
+            .with_pricing(ModelPricing::new(0.05, 0.08)),
+        ModelInfo::new("mixtral-8x7b-32768", "groq")
+            .with_limits(32_768, 32_768)
+            .with_tools()
+            .with_pricing(ModelPricing::new(0.24, 0.24)),
         // Google
-        ModelInfo {
-            id: "gemini-2.0-flash".to_string(),
-            provider: "google".to_string(),
-        },
-        ModelInfo {
-            id: "gemini-1.5-pro".to_string(),
-            provider: "google".to_string(),
-        },
-        // Mistral
-        ModelInfo {
-            id: "mistral-large-latest".to_string(),
-            provider: "mistral".to_string(),
-        },
-        ModelInfo {
-            id: "codestral-latest".to_string(),
-            provider: "mistral".to_string(),
-        },
+        ModelInfo::new("gemini-2.0-flash", "google")
+            .with_limits(1_048_576, 8_192)
+            .with_tools()
+            .with_vision()
+            .with_pricing(ModelPricing::new(0.1, 0.4).with_cache(0.1, 0.025)),
+        ModelInfo::new("gemini-1.5-pro", "google")
+            .with_limits(2_097_152, 8_192)
+            .with_tools()
+            .with_vision()
+            .with_pricing(ModelPricing::new(1.25, 5.0)),
+        // Mistral (output is bounded by the context window only)
+        ModelInfo::new("mistral-large-latest", "mistral")
+            .with_limits(131_072, 131_072)
+            .with_tools()
+            .with_pricing(ModelPricing::new(2.0, 6.0)),
+        ModelInfo::new("codestral-latest", "mistral")
+            .with_limits(256_000, 256_000)
+            .with_tools()
+            .with_pricing(ModelPricing::new(0.3, 0.9)),
         // Together
-        ModelInfo {
-            id: "meta-llama/Llama-3.3-70B-Instruct-Turbo".to_string(),
-            provider: "together".to_string(),
-        },
-        ModelInfo {
-            id: "Qwen/Qwen2.5-Coder-32B-Instruct".to_string(),
-            provider: "together".to_string(),
-        },
-        // Kimi (Moonshot AI)
-        ModelInfo {
-            id: "kimi-k2.5".to_string(),
-            provider: "kimi".to_string(),
-        },
-        ModelInfo {
-            id: "moonshot-v1-128k".to_string(),
-            provider: "kimi".to_string(),
-        },
-        ModelInfo {
-            id: "moonshot-v1-32k".to_string(),
-            provider: "kimi".to_string(),
-        },
+        ModelInfo::new("meta-llama/Llama-3.3-70B-Instruct-Turbo", "together")
+            .with_limits(131_072, 131_072)
+            .with_tools()
+            .with_pricing(ModelPricing::new(0.88, 0.88)),
+        ModelInfo::new("Qwen/Qwen2.5-Coder-32B-Instruct", "together")
+            .with_limits(32_768, 32_768)
+            .with_pricing(ModelPricing::new(0.8, 0.8)),
+        // Kimi (Moonshot AI, output is bounded by the context window only)
+        ModelInfo::new("kimi-k2.5", "kimi")
+            .with_limits(262_144, 262_144)
+            .with_tools()
+            .with_vision()
+            .with_reasoning(),
+        ModelInfo::new("moonshot-v1-128k", "kimi")
+            .with_limits(131_072, 131_072)
+            .with_tools(),
+        ModelInfo::new("moonshot-v1-32k", "kimi")
+            .with_limits(32_768, 32_768)
+            .with_tools(),
     ]
 }
 
@@ -248,6 +261,17 @@ mod tests {
         assert_eq!(models.len(), 20);
     }
 
+    #[test]
+    fn default_models_have_limits() {
+        for model in default_models() {
+            let (Some(window), Some(output)) = (model.context_window, model.max_output_tokens)
+            else {
+                panic!("missing limits for {}", model.id);
+            };
+            assert!(output <= window, "output exceeds window for {}", model.id);
+        }
+    }
+
     #[test]
     fn detect_provider_by_prefix_known() {
         assert_eq!(
@@ -0,0 +1,669 @@
+//! Model discovery from provider model-list endpoints
+//!
+//! Queries what a provider actually serves and enriches each entry with
+//! limits, capabilities and pricing from the static catalog. Results are
+//! cached on disk per provider so repeated lookups stay offline until the
+//! cache expires
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::defaults::default_models;
+use super::factory::{http_client, resolve_api_key};
+use super::types::{ModelInfo, ModelPricing, ProviderApiType, ProviderConfig};
+use crate::providers::{OllamaProvider, RetryPolicy};
+
+/// How long discovered model lists are reused by default
+pub const DEFAULT_DISCOVERY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const GROQ_BASE_URL: &str = "https://api.groq.com/openai/v1";
+const MISTRAL_BASE_URL: &str = "https://api.mistral.ai/v1";
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
+const GOOGLE_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Discover the models a provider serves, using the default cache
+///
+/// # Errors
+///
+/// Returns error if the provider type has no model-list endpoint, the
+/// API key is missing, or the request fails
+pub async fn discover_models(
+    name: &str,
+    config: &ProviderConfig,
+) -> anyhow::Result<Vec<ModelInfo>> {
+    ModelDiscovery::new().discover(name, config).await
+}
+
+/// Model discovery with an on-disk cache
+#[derive(Debug, Clone)]
+pub struct ModelDiscovery {
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    cache_dir: Option<PathBuf>,
+    ttl: Duration,
+    catalog: Vec<ModelInfo>,
+}
+
+impl Default for ModelDiscovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModelDiscovery {
+    /// Create a discovery client caching under the user cache dir and
+    /// enriching from [`default_models`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            retry: RetryPolicy::default(),
+            cache_dir: directories::BaseDirs::new()
+                .map(|base| base.cache_dir().join("omni").join("models")),
+            ttl: DEFAULT_DISCOVERY_TTL,
+            catalog: default_models(),
+        }
+    }
+
+    /// Cache model lists in `dir`
+    #[must_use]
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Always query the provider
+    #[must_use]
+    pub fn without_cache(mut self) -> Self {
+        self.cache_dir = None;
+        self
+    }
+
+    /// Set how long cached lists stay fresh
+    #[must_use]
+    pub const fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Enrich discovered models from `catalog` instead of the defaults
+    #[must_use]
+    pub fn with_catalog(mut self, catalog: Vec<ModelInfo>) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    /// Use a preconfigured HTTP client
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Set the retry policy for list requests
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Discover models, reusing a fresh cached list when available
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the provider type has no model-list endpoint, the
+    /// API key is missing, or the request fails
+    pub async fn discover(
+        &self,
+        name: &str,
+        config: &ProviderConfig,
+    ) -> anyhow::Result<Vec<ModelInfo>> {
+        let discovered = match self.cached(name, config).await {
+            Some(models) => models,
+            None => self.fetch_and_cache(name, config).await?,
+        };
+        Ok(merge_with_catalog(name, discovered, &self.catalog))
+    }
+
+    /// Discover models, ignoring any cached list
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the provider type has no model-list endpoint, the
+    /// API key is missing, or the request fails
+    pub async fn refresh(
+        &self,
+        name: &str,
+        config: &ProviderConfig,
+    ) -> anyhow::Result<Vec<ModelInfo>> {
+        let discovered = self.fetch_and_cache(name, config).await?;
+        Ok(merge_with_catalog(name, discovered, &self.catalog))
+    }
+
+    async fn fetch_and_cache(
+        &self,
+        name: &str,
+        config: &ProviderConfig,
+    ) -> anyhow::Result<Vec<ModelInfo>> {
+        let models = self.fetch(name, config).await?;
+        self.store(name, config, &models).await;
+        Ok(models)
+    }
+
+    /// Query the provider's model-list endpoint
+    ///
+    /// Providers with transport settings are queried through a client
+    /// built from them
+    async fn fetch(&self, name: &str, config: &ProviderConfig) -> anyhow::Result<Vec<ModelInfo>> {
+        if config.has_transport_settings() {
+            let configured = Self {
+                http: http_client(config)?,
+                ..self.clone()
+            };
+            return Box::pin(configured.fetch_with_client(name, config)).await;
+        }
+        self.fetch_with_client(name, config).await
+    }
+
+    async fn fetch_with_client(
+        &self,
+        name: &str,
+        config: &ProviderConfig,
+    ) -> anyhow::Result<Vec<ModelInfo>> {
+        let base_url = |default: &str| {
+            config
+                .base_url
+                .as_deref()
+                .unwrap_or(default)
+                .trim_end_matches('/')
+                .to_string()
+        };
+        let required_key = || {
+            resolve_api_key(config)
+                .ok_or_else(|| anyhow::anyhow!("API key not set for provider '{name}'"))
+        };
+
+        match &config.api_type {
+            ProviderApiType::OpenAi | ProviderApiType::OpenAiResponses => {
+                let key = resolve_api_key(config);
+                self.fetch_compatible(name, &base_url(OPENAI_BASE_URL), key.as_deref())
+                    .await
+            }
+            ProviderApiType::Groq => {
+                let key = required_key()?;
+                self.fetch_compatible(name, &base_url(GROQ_BASE_URL), Some(&key))
+                    .await
+            }
+            ProviderApiType::Mistral => {
+                let key = required_key()?;
+                self.fetch_compatible(name, &base_url(MISTRAL_BASE_URL), Some(&key))
+                    .await
+            }
+            ProviderApiType::Anthropic => {
+                let key = required_key()?;
+                self.fetch_anthropic(name, &base_url(ANTHROPIC_BASE_URL), &key)
+                    .await
+            }
+            ProviderApiType::Google => {
+                let key = required_key()?;
+                self.fetch_google(name, &base_url(GOOGLE_BASE_URL), &key)
+                    .await
+            }
+            ProviderApiType::Ollama => {
+                let mut ollama = OllamaProvider::new().with_http_client(self.http.clone());
+                if let Some(base_url) = &config.base_url {
+                    ollama = ollama.with_base_url(base_url);
+                }
+                Ok(ollama
+                    .list_models()
+                    .await?
+                    .into_iter()
+                    .map(|model| ModelInfo::new(model.name, name))
+                    .collect())
+            }
+            ProviderApiType::Synapse | ProviderApiType::Custom(_) => anyhow::bail!(
+                "model discovery is not supported for provider type '{}'",
+                config.api_type
+            ),
+        }
+    }
+
+    /// `GET {base}/models` on an OpenAI-compatible API
+    async fn fetch_compatible(
+        &self,
+        name: &str,
+        base_url: &str,
+        api_key: Option<&str>,
+    ) -> anyhow::Result<Vec<ModelInfo>> {
+        let url = format!("{base_url}/models");
+        let response = self
+            .retry
+            .send(name, || {
+                let request = self.http.get(&url);
+                match api_key {
+                    Some(key) => request.bearer_auth(key),
+                    None => request,
+                }
+            })
+            .await?;
+        parse_compatible(name, &response.text().await?)
+    }
+
+    /// `GET {base}/v1/models` on the Anthropic API, following pages
+    async fn fetch_anthropic(
+        &self,
+        name: &str,
+        base_url: &str,
+        api_key: &str,
+    ) -> anyhow::Result<Vec<ModelInfo>> {
+        #[derive(Deserialize)]
+        struct Page {
+            data: Vec<AnthropicModel>,
+            #[serde(default)]
+            has_more: bool,
+            #[serde(default)]
+            last_id: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct AnthropicModel {
+            id: String,
+        }
+
+        let url = format!("{base_url}/v1/models");
+        let mut models = Vec::new();
+        let mut after_id: Option<String> = None;
+        loop {
+            let response = self
+                .retry
+                .send(name, || {
+                    let mut request = self
+                        .http
+                        .get(&url)
+                        .header("x-api-key", api_key)
+                        .header("anthropic-version", "2023-06-01")
+                        .query(&[("limit", "1000")]);
+                    if let Some(after_id) = &after_id {
+                        request = request.query(&[("after_id", after_id)]);
+                    }
+                    request
+                })
+                .await?;
+            let page: Page = response.json().await?;
+            models.extend(page.data.into_iter().map(|m| ModelInfo::new(m.id, name)));
+            match page.last_id {
+                Some(last_id) if page.has_more => after_id = Some(last_id),
+                _ => return Ok(models),
+            }
+        }
+    }
+
+    /// `GET {base}/models` on the Gemini API, following pages
+    async fn fetch_google(
+        &self,
+        name: &str,
+        base_url: &str,
+        api_key: &str,
+    ) -> anyhow::Result<Vec<ModelInfo>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Page {
+            #[serde(default)]
+            models: Vec<GoogleModel>,
+            #[serde(default)]
+            next_page_token: Option<String>,
+        }
+
+        let url = format!("{base_url}/models");
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let response = self
+                .retry
+                .send(name, || {
+                    let mut request = self
+                        .http
+                        .get(&url)
+                        .header("x-goog-api-key", api_key)
+                        .query(&[("pageSize", "1000")]);
+                    if let Some(page_token) = &page_token {
+                        request = request.query(&[("pageToken", page_token)]);
+                    }
+                    request
+                })
+                .await?;
+            let page: Page = response.json().await?;
+            models.extend(page.models.into_iter().filter_map(|m| m.into_info(name)));
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => return Ok(models),
+            }
+        }
+    }
+
+    fn cache_path(&self, name: &str) -> Option<PathBuf> {
+        let file: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        Some(self.cache_dir.as_ref()?.join(format!("{file}.json")))
+    }
+
+    /// Read a cached list that is fresh and was fetched from the same URL
+    async fn cached(&self, name: &str, config: &ProviderConfig) -> Option<Vec<ModelInfo>> {
+        let content = tokio::fs::read_to_string(self.cache_path(name)?)
+            .await
+            .ok()?;
+        let cached: CachedModels = serde_json::from_str(&content).ok()?;
+        let age = Utc::now()
+            .signed_duration_since(cached.fetched_at)
+            .to_std()
+            .ok()?;
+        (age < self.ttl && cached.base_url == config.base_url).then_some(cached.models)
+    }
+
+    /// Write a list to the cache; failures only cost a refetch
+    async fn store(&self, name: &str, config: &ProviderConfig, models: &[ModelInfo]) {
+        let Some(path) = self.cache_path(name) else {
+            return;
+        };
+        let cached = CachedModels {
+            fetched_at: Utc::now(),
+            base_url: config.base_url.clone(),
+            models: models.to_vec(),
+        };
+        let result = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let json = serde_json::to_string(&cached).map_err(std::io::Error::other)?;
+            tokio::fs::write(&path, json).await
+        };
+        if let Err(e) = result.await {
+            tracing::warn!(path = %path.display(), error = %e, "failed to cache model list");
+        }
+    }
+}
+
+/// Cached model list of one provider
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedModels {
+    fetched_at: DateTime<Utc>,
+    base_url: Option<String>,
+    models: Vec<ModelInfo>,
+}
+
+/// Model entry from an OpenAI-compatible list
+///
+/// Covers the extensions of Groq, Mistral, `OpenRouter` and Together
+#[derive(Debug, Deserialize)]
+struct CompatibleModel {
+    id: String,
+    #[serde(default, alias = "context_length", alias = "max_context_length")]
+    context_window: Option<u32>,
+    #[serde(default)]
+    max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    top_provider: Option<TopProvider>,
+    #[serde(default)]
+    capabilities: Option<CompatibleCapabilities>,
+    #[serde(default)]
+    architecture: Option<Architecture>,
+    #[serde(default)]
+    supported_parameters: Vec<String>,
+    #[serde(default)]
+    pricing: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopProvider {
+    #[serde(default)]
+    max_completion_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompatibleCapabilities {
+    #[serde(default)]
+    function_calling: bool,
+    #[serde(default)]
+    vision: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Architecture {
+    #[serde(default)]
+    input_modalities: Vec<String>,
+}
+
+impl CompatibleModel {
+    fn into_info(self, provider: &str) -> ModelInfo {
+        let supports = |parameter: &str| self.supported_parameters.iter().any(|p| p == parameter);
+        ModelInfo {
+            supports_tools: supports("tools")
+                || self
+                    .capabilities
+                    .as_ref()
+                    .is_some_and(|c| c.function_calling),
+            supports_vision: self.capabilities.as_ref().is_some_and(|c| c.vision)
+                || self
+                    .architecture
+                    .as_ref()
+                    .is_some_and(|a| a.input_modalities.iter().any(|m| m == "image")),
+            supports_reasoning: supports("reasoning"),
+            context_window: self.context_window,
+            max_output_tokens: self
+                .max_completion_tokens
+                .or_else(|| self.top_provider.as_ref()?.max_completion_tokens),
+            pricing: self.pricing.as_ref().and_then(parse_pricing),
+            ..ModelInfo::new(&self.id, provider)
+        }
+    }
+}
+
+/// Parse list pricing
+///
+/// `OpenRouter` quotes USD per token as strings, Together USD per
+/// million tokens as numbers. Negative rates mean variable pricing
+fn parse_pricing(pricing: &Value) -> Option<ModelPricing> {
+    let rate = |key: &str| match pricing.get(key)? {
+        Value::String(s) => s.parse::<f64>().ok().map(|r| r * 1_000_000.0),
+        Value::Number(n) => n.as_f64(),
+        _ => None,
+    };
+    let (input, output) = rate("prompt")
+        .zip(rate("completion"))
+        .or_else(|| rate("input").zip(rate("output")))?;
+    (input >= 0.0 && output >= 0.0).then(|| ModelPricing::new(input, output))
+}
+
+/// Parse an OpenAI-compatible list, wrapped in `data` or bare
+fn parse_compatible(provider: &str, body: &str) -> anyhow::Result<Vec<ModelInfo>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Wrapped { data: Vec<CompatibleModel> },
+        Bare(Vec<CompatibleModel>),
+    }
+
+    let list: List = serde_json::from_str(body)
+        .map_err(|e| anyhow::anyhow!("invalid model list from '{provider}': {e}"))?;
+    let (List::Wrapped { data: models } | List::Bare(models)) = list;
+    Ok(models.into_iter().map(|m| m.into_info(provider)).collect())
+}
+
+/// Model entry from the Gemini API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleModel {
+    name: String,
+    #[serde(default)]
+    input_token_limit: Option<u32>,
+    #[serde(default)]
+    output_token_limit: Option<u32>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+    #[serde(default)]
+    thinking: bool,
+}
+
+impl GoogleModel {
+    /// Convert to a model entry, skipping models that cannot chat
+    fn into_info(self, provider: &str) -> Option<ModelInfo> {
+        if !self
+            .supported_generation_methods
+            .iter()
+            .any(|m| m == "generateContent")
+        {
+            return None;
+        }
+        let id = self.name.strip_prefix("models/").unwrap_or(&self.name);
+        let mut info = ModelInfo::new(id, provider);
+        info.context_window = self
+            .input_token_limit
+            .map(|input| input.saturating_add(self.output_token_limit.unwrap_or(0)));
+        info.max_output_tokens = self.output_token_limit;
+        info.supports_reasoning = self.thinking;
+        Some(info)
+    }
+}
+
+/// Enrich discovered models from the static catalog
+///
+/// Catalog entries match on the same provider and ID first, then on the
+/// ID alone, then on the ID without a vendor prefix such as `openai/`.
+/// Limits and pricing reported by the endpoint win over the catalog, and
+/// catalog pricing is only taken from an entry of the same provider,
+/// since another vendor's list price says nothing about this one
+fn merge_with_catalog(
+    provider: &str,
+    discovered: Vec<ModelInfo>,
+    catalog: &[ModelInfo],
+) -> Vec<ModelInfo> {
+    discovered
+        .into_iter()
+        .map(|model| {
+            let bare = model.id.rsplit('/').next().unwrap_or(&model.id);
+            let known = catalog
+                .iter()
+                .find(|m| m.provider == provider && m.id == model.id)
+                .or_else(|| catalog.iter().find(|m| m.id == model.id))
+                .or_else(|| catalog.iter().find(|m| m.id == bare));
+            let Some(known) = known else {
+                return model;
+            };
+            ModelInfo {
+                context_window: model.context_window.or(known.context_window),
+                max_output_tokens: model.max_output_tokens.or(known.max_output_tokens),
+                supports_tools: model.supports_tools || known.supports_tools,
+                supports_vision: model.supports_vision || known.supports_vision,
+                supports_reasoning: model.supports_reasoning || known.supports_reasoning,
+                pricing: model
+                    .pricing
+                    .or_else(|| known.pricing.filter(|_| known.provider == provider)),
+                id: model.id,
+                provider: model.provider,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_compatible_lists() {
+        let openrouter = r#"{"data": [{
+            "id": "openai/gpt-4o",
+            "context_length": 128000,
+            "top_provider": {"max_completion_tokens": 16384},
+            "architecture": {"input_modalities": ["text", "image"]},
+            "supported_parameters": ["tools", "temperature"],
+            "pricing": {"prompt": "0.0000025", "completion": "0.00001"}
+        }]}"#;
+        let models = parse_compatible("openrouter", openrouter).unwrap();
+        let model = &models[0];
+        assert_eq!(model.provider, "openrouter");
+        assert_eq!(model.context_window, Some(128_000));
+        assert_eq!(model.max_output_tokens, Some(16_384));
+        assert!(model.supports_tools && model.supports_vision && !model.supports_reasoning);
+        let pricing = model.pricing.unwrap();
+        assert!((pricing.input_per_mtok - 2.5).abs() < 1e-9);
+
+        let together = r#"[{"id": "Qwen/Qwen3", "context_length": 40960,
+            "pricing": {"input": 0.2, "output": 0.6}}]"#;
+        let models = parse_compatible("together", together).unwrap();
+        assert_eq!(models[0].context_window, Some(40_960));
+        assert!(models[0].pricing.is_some());
+
+        assert!(parse_compatible("x", "{}").is_err());
+    }
+
+    #[test]
+    fn merges_catalog_metadata_into_discovered_models() {
+        let discovered = vec![
+            ModelInfo::new("openai/gpt-4o", "openrouter"),
+            ModelInfo::new("gpt-3.5-turbo", "openai").with_limits(8_000, 1_000),
+            ModelInfo::new("brand-new", "openai"),
+        ];
+
+        let merged = merge_with_catalog("openrouter", discovered, &default_models());
+
+        assert_eq!(merged[0].provider, "openrouter");
+        assert!(merged[0].supports_vision);
+        assert_eq!(merged[0].context_window, Some(128_000));
+        assert_eq!(merged[1].context_window, Some(8_000));
+        assert!(merged[1].supports_tools);
+        assert_eq!(merged[2], ModelInfo::new("brand-new", "openai"));
+        assert!(merged.iter().all(|m| m.pricing.is_none()));
+
+        let direct = merge_with_catalog(
+            "openai",
+            vec![ModelInfo::new("gpt-4o", "openai")],
+            &default_models(),
+        );
+        assert!(direct[0].pricing.is_some());
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_cache_without_network() {
+        let dir = std::env::temp_dir().join(format!("discovery-{}", uuid::Uuid::new_v4()));
+        let config = ProviderConfig {
+            api_type: ProviderApiType::OpenAi,
+            // Nothing listens on the discard port
+            base_url: Some("http://127.0.0.1:9/v1".to_string()),
+            ..Default::default()
+        };
+        let discovery = ModelDiscovery::new()
+            .with_cache_dir(&dir)
+            .with_retry_policy(RetryPolicy::none());
+        discovery
+            .store("lmstudio", &config, &[ModelInfo::new("gpt-4o", "lmstudio")])
+            .await;
+
+        let models = discovery.discover("lmstudio", &config).await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert!(models[0].supports_tools);
+
+        // A different endpoint or an expired entry goes to the network
+        let moved = ProviderConfig {
+            base_url: Some("http://127.0.0.1:9/other".to_string()),
+            ..config.clone()
+        };
+        assert!(discovery.discover("lmstudio", &moved).await.is_err());
+        let expired = discovery.with_ttl(Duration::ZERO);
+        assert!(expired.discover("lmstudio", &config).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
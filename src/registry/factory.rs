@@ -1,18 +1,30 @@
 //! Provider factory with extension support
 
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::types::{ProviderApiType, ProviderConfig};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use super::defaults::default_models;
+use super::types::{FallbackTarget, ModelInfo, ProviderApiType, ProviderConfig};
 use crate::provider::LlmProvider;
-use crate::providers::{AnthropicProvider, OpenAiProvider, UnifiedProvider};
+use crate::providers::{
+    AnthropicProvider, FallbackProvider, OllamaProvider, OpenAiProvider, OpenAiResponsesProvider,
+    UnifiedProvider,
+};
 
 /// Factory function for creating provider instances
 pub type ProviderFactoryFn =
     Box<dyn Fn(&str, &ProviderConfig) -> anyhow::Result<Box<dyn LlmProvider>> + Send + Sync>;
 
 /// Provider registry with built-in and custom factory support
+///
+/// Also holds the model catalog used to look up context windows,
+/// output limits and capabilities
 pub struct ProviderRegistry {
     custom_factories: HashMap<String, ProviderFactoryFn>,
+    models: Vec<ModelInfo>,
 }
 
 impl std::fmt::Debug for ProviderRegistry {
@@ -22,6 +34,7 @@ impl std::fmt::Debug for ProviderRegistry {
                 "custom_factories",
                 &self.custom_factories.keys().collect::<Vec<_>>(),
             )
+            .field("models", &self.models.len())
             .finish()
     }
 }
@@ -33,14 +46,58 @@ impl Default for ProviderRegistry {
 }
 
 impl ProviderRegistry {
-    /// Create a new empty registry
+    /// Create a registry without custom factories, seeded with the
+    /// default model catalog
     #[must_use]
     pub fn new() -> Self {
         Self {
             custom_factories: HashMap::new(),
+            models: default_models(),
+        }
+    }
+
+    /// Add models to the catalog
+    ///
+    /// An entry replaces any existing entry with the same provider and ID
+    pub fn register_models(&mut self, models: impl IntoIterator<Item = ModelInfo>) {
+        for model in models {
+            match self
+                .models
+                .iter_mut()
+                .find(|m| m.provider == model.provider && m.id == model.id)
+            {
+                Some(existing) => *existing = model,
+                None => self.models.push(model),
+            }
         }
     }
 
+    /// Get the model catalog
+    #[must_use]
+    pub fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    /// Look up a model by ID
+    ///
+    /// Accepts `provider/id` to pick one provider's entry when several
+    /// providers serve the same ID; a bare ID returns the first match
+    #[must_use]
+    pub fn model(&self, id: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|m| m.id == id).or_else(|| {
+            let (provider, id) = id.split_once('/')?;
+            self.provider_model(provider, id)
+        })
+    }
+
+    /// Look up a model served by a specific provider
+    #[must_use]
+    pub fn provider_model(&self, provider: &str, id: &str) -> Option<&ModelInfo> {
+        self.models
+            .iter()
+            .find(|m| m.provider == provider && m.id == id)
+    }
+
     /// Register a factory for a custom provider type
     ///
     /// The `type_name` should match the string used in `Custom(type_name)`
@@ -51,42 +108,95 @@ impl ProviderRegistry {
     /// Create a provider instance from a name and config
     ///
     /// Built-in types are handled directly; `Custom` types delegate
-    /// to registered factories.
+    /// to registered factories, which can apply the transport settings
+    /// with [`http_client`].
+    ///
+    /// Google, Groq and Mistral are served through the `llm` crate,
+    /// which has no transport hooks. When headers, timeouts, a proxy or
+    /// a CA are configured they are reached through their
+    /// OpenAI-compatible endpoints instead.
     ///
     /// # Errors
     ///
     /// Returns error if the provider type is unknown, no factory is
-    /// registered for a custom type, or provider creation fails
+    /// registered for a custom type, transport settings are invalid, or
+    /// provider creation fails
     pub fn create_provider(
         &self,
         name: &str,
         config: &ProviderConfig,
     ) -> anyhow::Result<Box<dyn LlmProvider>> {
+        let http = config
+            .has_transport_settings()
+            .then(|| http_client(config))
+            .transpose()?;
+        let required_key = || {
+            resolve_api_key(config)
+                .ok_or_else(|| anyhow::anyhow!("API key not set for provider '{name}'"))
+        };
+
         match &config.api_type {
             ProviderApiType::Anthropic => {
-                let key = resolve_api_key(config)
-                    .ok_or_else(|| anyhow::anyhow!("API key not set for provider '{name}'"))?;
-                Ok(Box::new(AnthropicProvider::new(key)?))
+                let mut provider = AnthropicProvider::new(required_key()?)?;
+                if let Some(base_url) = &config.base_url {
+                    provider = provider.with_base_url(base_url);
+                }
+                if let Some(http) = http {
+                    provider = provider.with_http_client(http);
+                }
+                Ok(Box::new(provider))
             }
             ProviderApiType::OpenAi => {
                 let api_key = resolve_api_key(config);
                 let base_url = config.base_url.clone();
-                Ok(Box::new(OpenAiProvider::with_config(api_key, base_url)?))
+                let mut provider = OpenAiProvider::with_config(api_key, base_url)?;
+                if let Some(http) = http {
+                    provider = provider.with_http_client(http);
+                }
+                Ok(Box::new(provider))
             }
-            ProviderApiType::Google => {
-                let key = resolve_api_key(config)
-                    .ok_or_else(|| anyhow::anyhow!("API key not set for provider '{name}'"))?;
-                Ok(Box::new(UnifiedProvider::google(key)?))
+            ProviderApiType::OpenAiResponses => {
+                let api_key = resolve_api_key(config);
+                let base_url = config.base_url.clone();
+                let mut provider = OpenAiResponsesProvider::with_config(api_key, base_url)?;
+                if let Some(http) = http {
+                    provider = provider.with_http_client(http);
+                }
+                Ok(Box::new(provider))
             }
-            ProviderApiType::Groq => {
-                let key = resolve_api_key(config)
-                    .ok_or_else(|| anyhow::anyhow!("API key not set for provider '{name}'"))?;
-                Ok(Box::new(UnifiedProvider::groq(key)?))
+            ProviderApiType::Google | ProviderApiType::Groq | ProviderApiType::Mistral => {
+                let key = required_key()?;
+                if let Some(http) = http {
+                    let base_url = config
+                        .base_url
+                        .clone()
+                        .unwrap_or_else(|| compatible_base_url(&config.api_type).to_string());
+                    tracing::debug!(
+                        provider = %name,
+                        api_type = %config.api_type,
+                        base_url = %base_url,
+                        "transport settings configured, using OpenAI-compatible endpoint"
+                    );
+                    let provider = OpenAiProvider::with_config(Some(key), Some(base_url))?
+                        .with_http_client(http);
+                    return Ok(Box::new(provider));
+                }
+                let provider = match config.api_type {
+                    ProviderApiType::Google => UnifiedProvider::google(key)?,
+                    ProviderApiType::Groq => UnifiedProvider::groq(key)?,
+                    _ => UnifiedProvider::mistral(key)?,
+                };
+                Ok(Box::new(provider))
             }
-            ProviderApiType::Mistral => {
-                let key = resolve_api_key(config)
-                    .ok_or_else(|| anyhow::anyhow!("API key not set for provider '{name}'"))?;
-                Ok(Box::new(UnifiedProvider::mistral(key)?))
+            ProviderApiType::Ollama => {
+                let mut provider = OllamaProvider::new();
+                if let Some(base_url) = &config.base_url {
+                    provider = provider.with_base_url(base_url);
+                }
+                if let Some(http) = http {
+                    provider = provider.with_http_client(http);
+                }
+                Ok(Box::new(provider))
             }
             ProviderApiType::Synapse => {
                 let factory = self.custom_factories.get("synapse").ok_or_else(|| {
@@ -107,6 +217,101 @@ impl ProviderRegistry {
     }
 }
 
+/// OpenAI-compatible endpoint of a provider served through the `llm` crate
+const fn compatible_base_url(api_type: &ProviderApiType) -> &'static str {
+    match api_type {
+        ProviderApiType::Google => "https://generativelanguage.googleapis.com/v1beta/openai",
+        ProviderApiType::Groq => "https://api.groq.com/openai/v1",
+        _ => "https://api.mistral.ai/v1",
+    }
+}
+
+/// Build an HTTP client with a provider's transport settings
+///
+/// Configured headers are sent with every request unless the provider
+/// sets the same header itself, so they cannot replace authentication
+///
+/// # Errors
+///
+/// Returns error if a header is invalid, the proxy URL cannot be parsed,
+/// or the CA file cannot be read or holds no PEM certificate
+pub fn http_client(config: &ProviderConfig) -> anyhow::Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let header = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| anyhow::anyhow!("invalid header name '{name}': {e}"))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| anyhow::anyhow!("invalid value for header '{name}': {e}"))?;
+        headers.insert(header, value);
+    }
+
+    let mut builder = reqwest::Client::builder().default_headers(headers);
+    if let Some(secs) = config.connect_timeout_secs {
+        builder = builder.connect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = config.read_timeout_secs {
+        builder = builder.read_timeout(Duration::from_secs(secs));
+    }
+    if let Some(proxy) = &config.proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| anyhow::anyhow!("invalid proxy '{proxy}': {e}"))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &config.ca_cert {
+        let pem = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("cannot read CA file {}: {e}", path.display()))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow::anyhow!("invalid CA file {}: {e}", path.display()))?;
+        if certs.is_empty() {
+            anyhow::bail!("CA file {} holds no certificates", path.display());
+        }
+        builder = builder.tls_certs_merge(certs);
+    }
+
+    Ok(builder.build()?)
+}
+
+impl ProviderRegistry {
+    /// Create a fallback chain from provider configs
+    ///
+    /// Each target's provider is looked up in `providers` by name and
+    /// created once, even if several targets share it
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the chain is empty, a target names an unknown
+    /// provider, or provider creation fails
+    pub fn create_fallback(
+        &self,
+        chain: &[FallbackTarget],
+        providers: &HashMap<String, ProviderConfig>,
+    ) -> anyhow::Result<FallbackProvider> {
+        if chain.is_empty() {
+            anyhow::bail!("fallback chain is empty");
+        }
+
+        let mut created: HashMap<&str, Arc<dyn LlmProvider>> = HashMap::new();
+        let mut fallback = FallbackProvider::new();
+
+        for target in chain {
+            let name = target.provider.as_str();
+            let provider = if let Some(provider) = created.get(name) {
+                Arc::clone(provider)
+            } else {
+                let config = providers.get(name).ok_or_else(|| {
+                    anyhow::anyhow!("unknown provider '{name}' in fallback chain")
+                })?;
+                let provider: Arc<dyn LlmProvider> = Arc::from(self.create_provider(name, config)?);
+                created.insert(name, Arc::clone(&provider));
+                provider
+            };
+            fallback = fallback.with_backend(name, provider, &target.model);
+        }
+
+        Ok(fallback)
+    }
+}
+
 /// Resolve API key for a provider config
 ///
 /// Checks the environment variable first, then falls back to the
@@ -125,7 +330,11 @@ pub fn resolve_api_key(config: &ProviderConfig) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
     use super::*;
+    use crate::test_support::{StubServer, response};
 
     #[test]
     fn resolve_api_key_from_direct_value() {
@@ -134,6 +343,7 @@ mod tests {
             base_url: None,
             api_key_env: None,
             api_key: Some("sk-direct".to_string()),
+            ..Default::default()
         };
         assert_eq!(resolve_api_key(&config), Some("sk-direct".to_string()));
     }
@@ -169,8 +379,155 @@ mod tests {
     }
 
     #[test]
-    fn registry_default_is_empty() {
+    fn create_fallback_builds_chain_in_order() {
+        let registry = ProviderRegistry::new();
+        let providers = HashMap::from([
+            (
+                "local".to_string(),
+                ProviderConfig {
+                    api_type: ProviderApiType::OpenAi,
+                    base_url: Some("http://localhost:11434/v1".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "claude".to_string(),
+                ProviderConfig {
+                    api_type: ProviderApiType::Anthropic,
+                    api_key: Some("sk-test".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let target = |provider: &str, model: &str| FallbackTarget {
+            provider: provider.to_string(),
+            model: model.to_string(),
+        };
+
+        let chain = registry
+            .create_fallback(
+                &[
+                    target("claude", "claude-sonnet-4-5"),
+                    target("local", "llama3.2"),
+                    target("claude", "claude-haiku-4-5"),
+                ],
+                &providers,
+            )
+            .unwrap();
+        assert_eq!(chain.len(), 3);
+
+        let unknown = registry.create_fallback(&[target("missing", "m")], &providers);
+        assert!(unknown.err().unwrap().to_string().contains("missing"));
+        assert!(registry.create_fallback(&[], &providers).is_err());
+    }
+
+    #[test]
+    fn model_lookup_and_overrides() {
+        let mut registry = ProviderRegistry::new();
+        let gpt = registry.model("gpt-4o").unwrap();
+        assert_eq!(gpt.context_window, Some(128_000));
+        assert!(gpt.supports_vision);
+        assert!(!registry.model("gpt-3.5-turbo").unwrap().supports_vision);
+        assert!(registry.model("openai/gpt-4o").is_some());
+        assert!(registry.model("unknown").is_none());
+
+        registry.register_models([
+            ModelInfo::new("gpt-4o", "openai").with_limits(64_000, 4_096),
+            ModelInfo::new("llama3.2", "ollama").with_limits(131_072, 131_072),
+        ]);
+        assert_eq!(
+            registry.model("gpt-4o").unwrap().context_window,
+            Some(64_000)
+        );
+        assert!(registry.provider_model("ollama", "llama3.2").is_some());
+        assert_eq!(registry.models().len(), default_models().len() + 1);
+    }
+
+    #[test]
+    fn transport_settings_apply_to_every_builtin_type() {
+        let registry = ProviderRegistry::new();
+        let config = |api_type| ProviderConfig {
+            api_type,
+            api_key: Some("sk-test".to_string()),
+            headers: BTreeMap::from([("x-org-id".to_string(), "acme".to_string())]),
+            connect_timeout_secs: Some(5),
+            read_timeout_secs: Some(60),
+            proxy: Some("http://proxy.corp:3128".to_string()),
+            ..Default::default()
+        };
+
+        for api_type in [
+            ProviderApiType::Anthropic,
+            ProviderApiType::OpenAi,
+            ProviderApiType::OpenAiResponses,
+            ProviderApiType::Google,
+            ProviderApiType::Groq,
+            ProviderApiType::Mistral,
+            ProviderApiType::Ollama,
+        ] {
+            let name = api_type.to_string();
+            assert!(
+                registry.create_provider(&name, &config(api_type)).is_ok(),
+                "{name}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_headers_reach_the_wire() {
+        let server =
+            StubServer::start(|_, _| Some(response("400 Bad Request", "application/json", "{}")))
+                .await;
+
+        let config = ProviderConfig {
+            api_type: ProviderApiType::OpenAi,
+            base_url: Some(format!("{}/v1", server.url())),
+            api_key: Some("sk-test".to_string()),
+            headers: BTreeMap::from([("x-org-id".to_string(), "acme".to_string())]),
+            read_timeout_secs: Some(5),
+            ..Default::default()
+        };
+        let provider = ProviderRegistry::new()
+            .create_provider("proxy", &config)
+            .unwrap();
+        let request = crate::provider::CompletionRequest {
+            model: "gpt-4o".to_string(),
+            max_tokens: 16,
+            ..Default::default()
+        };
+        let _ = provider.stream(request).await;
+
+        let head = &server.requests()[0].head;
+        assert!(head.contains("x-org-id: acme"), "{head}");
+    }
+
+    #[test]
+    fn http_client_rejects_invalid_settings() {
+        let bad_header = ProviderConfig {
+            headers: BTreeMap::from([("bad header".to_string(), "v".to_string())]),
+            ..Default::default()
+        };
+        assert!(http_client(&bad_header).is_err());
+
+        let bad_proxy = ProviderConfig {
+            proxy: Some("not a url".to_string()),
+            ..Default::default()
+        };
+        assert!(http_client(&bad_proxy).is_err());
+
+        let missing_ca = ProviderConfig {
+            ca_cert: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..Default::default()
+        };
+        let err = http_client(&missing_ca).unwrap_err().to_string();
+        assert!(err.contains("ca.pem"), "{err}");
+    }
+
+    #[test]
+    fn default_registry_has_catalog_models_but_no_custom_providers() {
         let registry = ProviderRegistry::default();
         assert!(registry.custom_factories.is_empty());
+        assert!(!registry.models().is_empty());
+        assert!(registry.model("gpt-4o").is_some());
     }
 }
@@ -0,0 +1,359 @@
+//! Provider health checks
+//!
+//! Checks make one cheap authenticated call, listing the provider's
+//! models, and classify the outcome so setup wizards and `doctor`
+//! commands can explain what is wrong before the first prompt fails.
+//! `OpenRouter` lists models without a key, so its key is checked first
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+use super::discovery::ModelDiscovery;
+use super::factory::{http_client, resolve_api_key};
+use super::types::{ProviderApiType, ProviderConfig};
+use crate::error::AgentError;
+use crate::providers::RetryPolicy;
+
+/// Time allowed for one health check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+
+/// Outcome of a health check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// Credentials work and the requested model, if any, is served
+    Ok,
+    /// No API key is configured
+    MissingKey,
+    /// The provider rejected the API key
+    BadKey,
+    /// The endpoint could not be reached or does not exist
+    Unreachable,
+    /// The requested model is not served
+    UnknownModel,
+    /// The provider is rate limiting requests
+    RateLimited,
+    /// The provider type cannot be checked
+    Unsupported,
+    /// Any other failure
+    Error,
+}
+
+impl HealthStatus {
+    /// Whether the provider is usable
+    #[must_use]
+    pub const fn is_ok(self) -> bool {
+        matches!(self, Self::Ok)
+    }
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::Ok => "ok",
+            Self::MissingKey => "missing API key",
+            Self::BadKey => "invalid API key",
+            Self::Unreachable => "unreachable",
+            Self::UnknownModel => "unknown model",
+            Self::RateLimited => "rate limited",
+            Self::Unsupported => "not checkable",
+            Self::Error => "error",
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// Result of checking one provider
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// Provider name
+    pub provider: String,
+    /// Classified outcome
+    pub status: HealthStatus,
+    /// Underlying error or explanation, if any
+    pub detail: Option<String>,
+    /// Number of models the provider lists, when the call succeeded
+    pub model_count: Option<usize>,
+    /// Time the check took
+    pub latency: Duration,
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.provider, self.status)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({detail})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check one provider, optionally confirming it serves `model`
+pub async fn check_provider(
+    name: &str,
+    config: &ProviderConfig,
+    model: Option<&str>,
+) -> HealthReport {
+    let start = Instant::now();
+    let report = |status, detail: Option<String>, model_count| HealthReport {
+        provider: name.to_string(),
+        status,
+        detail,
+        model_count,
+        latency: start.elapsed(),
+    };
+
+    if matches!(
+        config.api_type,
+        ProviderApiType::Synapse | ProviderApiType::Custom(_)
+    ) {
+        return report(
+            HealthStatus::Unsupported,
+            Some(format!("no health check for type '{}'", config.api_type)),
+            None,
+        );
+    }
+    let openrouter = is_openrouter(name, config);
+    if (requires_key(config) || openrouter) && resolve_api_key(config).is_none() {
+        let detail = config.api_key_env.as_ref().map(|env| format!("set {env}"));
+        return report(HealthStatus::MissingKey, detail, None);
+    }
+
+    let discovery = ModelDiscovery::new()
+        .without_cache()
+        .with_retry_policy(RetryPolicy::none());
+    let checks = async {
+        if openrouter {
+            check_openrouter_key(config).await?;
+        }
+        discovery.refresh(name, config).await
+    };
+    let models = match tokio::time::timeout(CHECK_TIMEOUT, checks).await {
+        Ok(Ok(models)) => models,
+        Ok(Err(e)) => return report(classify(&e), Some(e.to_string()), None),
+        Err(_) => {
+            return report(
+                HealthStatus::Unreachable,
+                Some(format!("no response within {}s", CHECK_TIMEOUT.as_secs())),
+                None,
+            );
+        }
+    };
+
+    let count = Some(models.len());
+    match model {
+        Some(model)
+            if !models
+                .iter()
+                .any(|m| m.id == model || m.id == format!("{model}:latest")) =>
+        {
+            report(
+                HealthStatus::UnknownModel,
+                Some(format!(
+                    "'{model}' is not among {} listed models",
+                    models.len()
+                )),
+                count,
+            )
+        }
+        _ => report(HealthStatus::Ok, None, count),
+    }
+}
+
+/// Check every provider concurrently, sorted by name
+///
+/// Pass [`default_providers`](super::default_providers) to diagnose the
+/// built-in set
+pub async fn check_providers<S: BuildHasher + Sync>(
+    providers: &HashMap<String, ProviderConfig, S>,
+) -> Vec<HealthReport> {
+    let checks = providers
+        .iter()
+        .map(|(name, config)| check_provider(name, config, None));
+    let mut reports = futures::future::join_all(checks).await;
+    reports.sort_by(|a, b| a.provider.cmp(&b.provider));
+    reports
+}
+
+/// Whether the provider cannot be called without a key
+///
+/// OpenAI-compatible servers only need one when an env var is named
+const fn requires_key(config: &ProviderConfig) -> bool {
+    match config.api_type {
+        ProviderApiType::Anthropic
+        | ProviderApiType::Google
+        | ProviderApiType::Groq
+        | ProviderApiType::Mistral => true,
+        ProviderApiType::OpenAi | ProviderApiType::OpenAiResponses => config.api_key_env.is_some(),
+        _ => false,
+    }
+}
+
+/// Whether the provider is `OpenRouter`, by name or endpoint
+fn is_openrouter(name: &str, config: &ProviderConfig) -> bool {
+    name == "openrouter"
+        || config
+            .base_url
+            .as_deref()
+            .is_some_and(|url| url.contains("openrouter.ai"))
+}
+
+/// Confirm an `OpenRouter` key
+///
+/// Its model list is public, so listing models proves nothing about
+/// the key; the key endpoint rejects an invalid one
+async fn check_openrouter_key(config: &ProviderConfig) -> anyhow::Result<()> {
+    let base_url = config
+        .base_url
+        .as_deref()
+        .unwrap_or(OPENROUTER_BASE_URL)
+        .trim_end_matches('/');
+    let response = http_client(config)?
+        .get(format!("{base_url}/key"))
+        .bearer_auth(resolve_api_key(config).unwrap_or_default())
+        .send()
+        .await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(AgentError::Api {
+        status: status.as_u16(),
+        message: response.text().await.unwrap_or_default(),
+    }
+    .into())
+}
+
+/// Classify a failed model-list call
+fn classify(error: &anyhow::Error) -> HealthStatus {
+    match error.downcast_ref::<AgentError>() {
+        Some(AgentError::ApiKeyMissing) => HealthStatus::MissingKey,
+        Some(AgentError::Api { status, .. }) => classify_status(*status),
+        Some(AgentError::Http(e)) => classify_http(e),
+        Some(_) => HealthStatus::Error,
+        None => error
+            .downcast_ref::<reqwest::Error>()
+            .map_or(HealthStatus::Error, classify_http),
+    }
+}
+
+const fn classify_status(status: u16) -> HealthStatus {
+    match status {
+        401 | 403 => HealthStatus::BadKey,
+        404 => HealthStatus::Unreachable,
+        429 => HealthStatus::RateLimited,
+        _ => HealthStatus::Error,
+    }
+}
+
+fn classify_http(error: &reqwest::Error) -> HealthStatus {
+    if let Some(status) = error.status() {
+        classify_status(status.as_u16())
+    } else if error.is_connect() || error.is_timeout() || error.is_request() {
+        HealthStatus::Unreachable
+    } else {
+        HealthStatus::Error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{StubServer, response};
+
+    /// Serve one canned JSON response to every request
+    async fn stub_server(status: &'static str, body: &'static str) -> String {
+        let server =
+            StubServer::start(move |_, _| Some(response(status, "application/json", body))).await;
+        format!("{}/v1", server.url())
+    }
+
+    fn openai(base_url: String) -> ProviderConfig {
+        ProviderConfig {
+            api_type: ProviderApiType::OpenAi,
+            base_url: Some(base_url),
+            api_key: Some("sk-test".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn classifies_provider_responses() {
+        let listing = stub_server("200 OK", r#"{"data":[{"id":"gpt-4o"}]}"#).await;
+        let ok = check_provider("a", &openai(listing.clone()), Some("gpt-4o")).await;
+        assert_eq!(ok.status, HealthStatus::Ok);
+        assert_eq!(ok.model_count, Some(1));
+        let unknown = check_provider("a", &openai(listing), Some("gpt-5")).await;
+        assert_eq!(unknown.status, HealthStatus::UnknownModel);
+
+        let rejected = stub_server("401 Unauthorized", r#"{"error":"bad key"}"#).await;
+        let bad_key = check_provider("b", &openai(rejected), None).await;
+        assert_eq!(bad_key.status, HealthStatus::BadKey);
+
+        let limited = stub_server("429 Too Many Requests", "{}").await;
+        let rate = check_provider("c", &openai(limited), None).await;
+        assert_eq!(rate.status, HealthStatus::RateLimited);
+
+        // Nothing listens on the discard port
+        let down = check_provider("d", &openai("http://127.0.0.1:9/v1".into()), None).await;
+        assert_eq!(down.status, HealthStatus::Unreachable);
+    }
+
+    #[tokio::test]
+    async fn checks_openrouter_keys_beyond_the_public_model_list() {
+        let server = StubServer::start(|request, _| {
+            Some(match request.path.as_str() {
+                "/v1/models" => response("200 OK", "application/json", r#"{"data":[]}"#),
+                _ if request.head.contains("bearer sk-good") => {
+                    response("200 OK", "application/json", r#"{"data":{}}"#)
+                }
+                _ => response("401 Unauthorized", "application/json", "{}"),
+            })
+        })
+        .await;
+        let config = |key: &str| ProviderConfig {
+            api_key: Some(key.to_string()),
+            ..openai(format!("{}/v1", server.url()))
+        };
+
+        let bad = check_provider("openrouter", &config("sk-bad"), None).await;
+        assert_eq!(bad.status, HealthStatus::BadKey);
+        let good = check_provider("openrouter", &config("sk-good"), None).await;
+        assert_eq!(good.status, HealthStatus::Ok);
+        assert!(server.requests().iter().any(|r| r.path == "/v1/key"));
+    }
+
+    #[tokio::test]
+    async fn reports_missing_keys_without_network() {
+        let providers = HashMap::from([
+            (
+                "anthropic".to_string(),
+                ProviderConfig {
+                    api_type: ProviderApiType::Anthropic,
+                    api_key_env: Some("HEALTH_TEST_UNSET_KEY".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "custom".to_string(),
+                ProviderConfig {
+                    api_type: ProviderApiType::Custom("x".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let reports = check_providers(&providers).await;
+
+        assert_eq!(reports[0].provider, "anthropic");
+        assert_eq!(reports[0].status, HealthStatus::MissingKey);
+        assert_eq!(
+            reports[0].to_string(),
+            "anthropic: missing API key (set HEALTH_TEST_UNSET_KEY)"
+        );
+        assert_eq!(reports[1].status, HealthStatus::Unsupported);
+    }
+}
@@ -2,12 +2,18 @@
 //!
 //! Provides default provider definitions, model catalogs, and a
 //! factory for creating provider instances with extension support
-//! for consumer-specific providers
+//! for consumer-specific providers, plus a layered config loader
 
+mod config;
 mod defaults;
+mod discovery;
 mod factory;
+mod health;
 mod types;
 
+pub use config::{ConfigDiagnostic, ConfigLoader, ConfigSource, DiagnosticSeverity, LoadedConfig};
 pub use defaults::{default_models, default_providers, detect_provider_by_prefix};
-pub use factory::{ProviderFactoryFn, ProviderRegistry, resolve_api_key};
-pub use types::{ModelInfo, ProviderApiType, ProviderConfig};
+pub use discovery::{DEFAULT_DISCOVERY_TTL, ModelDiscovery, discover_models};
+pub use factory::{ProviderFactoryFn, ProviderRegistry, http_client, resolve_api_key};
+pub use health::{HealthReport, HealthStatus, check_provider, check_providers};
+pub use types::{FallbackTarget, ModelInfo, ModelPricing, ProviderApiType, ProviderConfig};
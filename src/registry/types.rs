@@ -1,9 +1,13 @@
 //! Provider registry types
 
+use std::collections::BTreeMap;
 use std::fmt;
+use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::types::Usage;
+
 /// Provider API type
 ///
 /// Determines which API format to use for communication. Known providers
@@ -15,12 +19,16 @@ pub enum ProviderApiType {
     Anthropic,
     /// `OpenAI` Chat Completions API (also used by compatible providers)
     OpenAi,
+    /// `OpenAI` Responses API
+    OpenAiResponses,
     /// Google Gemini API
     Google,
     /// Groq API
     Groq,
     /// Mistral API
     Mistral,
+    /// Native Ollama API
+    Ollama,
     /// Synapse AI router (unified LLM gateway)
     Synapse,
     /// Extension point for consumer-specific providers
@@ -32,9 +40,11 @@ impl Serialize for ProviderApiType {
         match self {
             Self::Anthropic => serializer.serialize_str("anthropic"),
             Self::OpenAi => serializer.serialize_str("openai"),
+            Self::OpenAiResponses => serializer.serialize_str("openai-responses"),
             Self::Google => serializer.serialize_str("google"),
             Self::Groq => serializer.serialize_str("groq"),
             Self::Mistral => serializer.serialize_str("mistral"),
+            Self::Ollama => serializer.serialize_str("ollama"),
             Self::Synapse => serializer.serialize_str("synapse"),
             Self::Custom(s) => serializer.serialize_str(s),
         }
@@ -53,9 +63,11 @@ impl From<&str> for ProviderApiType {
         match s {
             "anthropic" => Self::Anthropic,
             "openai" => Self::OpenAi,
+            "openai-responses" => Self::OpenAiResponses,
             "google" => Self::Google,
             "groq" => Self::Groq,
             "mistral" => Self::Mistral,
+            "ollama" => Self::Ollama,
             "synapse" => Self::Synapse,
             other => Self::Custom(other.to_string()),
         }
@@ -67,9 +79,11 @@ impl fmt::Display for ProviderApiType {
         match self {
             Self::Anthropic => write!(f, "anthropic"),
             Self::OpenAi => write!(f, "openai"),
+            Self::OpenAiResponses => write!(f, "openai-responses"),
             Self::Google => write!(f, "google"),
             Self::Groq => write!(f, "groq"),
             Self::Mistral => write!(f, "mistral"),
+            Self::Ollama => write!(f, "ollama"),
             Self::Synapse => write!(f, "synapse"),
             Self::Custom(s) => write!(f, "{s}"),
         }
@@ -94,29 +108,230 @@ pub struct ProviderConfig {
     /// Direct API key (discouraged, prefer `api_key_env`)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_key: Option<String>,
+
+    /// Extra headers sent with every request (org IDs, beta flags,
+    /// gateway routing tokens)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+
+    /// Seconds allowed for establishing a connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Seconds allowed between reads of a response, so long streams are
+    /// not cut off while they make progress
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<u64>,
+
+    /// Proxy URL for all requests (e.g. `http://proxy.corp:3128`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// PEM file of extra CA certificates to trust alongside the built-in
+    /// roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl ProviderConfig {
+    /// Whether any HTTP transport setting differs from the defaults
+    #[must_use]
+    pub fn has_transport_settings(&self) -> bool {
+        !self.headers.is_empty()
+            || self.connect_timeout_secs.is_some()
+            || self.read_timeout_secs.is_some()
+            || self.proxy.is_some()
+            || self.ca_cert.is_some()
+    }
+}
+
+/// Entry in a fallback chain
+///
+/// Names a provider from the provider table and the model to request
+/// from it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FallbackTarget {
+    /// Provider name (key in the provider table)
+    pub provider: String,
+    /// Model identifier to request from that provider
+    pub model: String,
+}
+
+/// Per-model token rates in US dollars per million tokens
+///
+/// Cache rates fall back to the input rate when the provider does not
+/// price cached tokens separately
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    /// Rate for uncached input tokens
+    pub input_per_mtok: f64,
+    /// Rate for output tokens
+    pub output_per_mtok: f64,
+    /// Rate for input tokens written to the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write_per_mtok: Option<f64>,
+    /// Rate for input tokens read from the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_per_mtok: Option<f64>,
+}
+
+impl ModelPricing {
+    /// Create pricing with input and output rates only
+    #[must_use]
+    pub const fn new(input_per_mtok: f64, output_per_mtok: f64) -> Self {
+        Self {
+            input_per_mtok,
+            output_per_mtok,
+            cache_write_per_mtok: None,
+            cache_read_per_mtok: None,
+        }
+    }
+
+    /// Set the cache write and cache read rates
+    #[must_use]
+    pub const fn with_cache(mut self, write_per_mtok: f64, read_per_mtok: f64) -> Self {
+        self.cache_write_per_mtok = Some(write_per_mtok);
+        self.cache_read_per_mtok = Some(read_per_mtok);
+        self
+    }
+
+    /// Price a usage report in US dollars
+    #[must_use]
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let write = self.cache_write_per_mtok.unwrap_or(self.input_per_mtok);
+        let read = self.cache_read_per_mtok.unwrap_or(self.input_per_mtok);
+        let cached = f64::from(usage.cache_creation_input_tokens)
+            .mul_add(write, f64::from(usage.cache_read_input_tokens) * read);
+        let total = f64::from(usage.input_tokens).mul_add(
+            self.input_per_mtok,
+            f64::from(usage.output_tokens).mul_add(self.output_per_mtok, cached),
+        );
+        total / 1_000_000.0
+    }
 }
 
 /// Model information with provider association
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Capability flags default to `false` and limits to unknown, so entries
+/// from older catalogs deserialize as conservative text-only models
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct ModelInfo {
     /// Model identifier (e.g., "claude-sonnet-4-20250514", "gpt-4o")
     pub id: String,
     /// Provider name (e.g., "anthropic", "openai")
     pub provider: String,
+    /// Maximum input plus output tokens per request, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    /// Maximum output tokens per request, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    /// Whether the model accepts tool definitions
+    #[serde(default)]
+    pub supports_tools: bool,
+    /// Whether the model accepts image input
+    #[serde(default)]
+    pub supports_vision: bool,
+    /// Whether the model produces reasoning before answering
+    #[serde(default)]
+    pub supports_reasoning: bool,
+    /// Token rates, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<ModelPricing>,
+}
+
+impl ModelInfo {
+    /// Create an entry with unknown limits and no capabilities
+    #[must_use]
+    pub fn new(id: impl Into<String>, provider: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            provider: provider.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the context window and output limit
+    #[must_use]
+    pub const fn with_limits(mut self, context_window: u32, max_output_tokens: u32) -> Self {
+        self.context_window = Some(context_window);
+        self.max_output_tokens = Some(max_output_tokens);
+        self
+    }
+
+    /// Mark the model as accepting tool definitions
+    #[must_use]
+    pub const fn with_tools(mut self) -> Self {
+        self.supports_tools = true;
+        self
+    }
+
+    /// Mark the model as accepting image input
+    #[must_use]
+    pub const fn with_vision(mut self) -> Self {
+        self.supports_vision = true;
+        self
+    }
+
+    /// Mark the model as producing reasoning
+    #[must_use]
+    pub const fn with_reasoning(mut self) -> Self {
+        self.supports_reasoning = true;
+        self
+    }
+
+    /// Set the token rates
+    #[must_use]
+    pub const fn with_pricing(mut self, pricing: ModelPricing) -> Self {
+        self.pricing = Some(pricing);
+        self
+    }
+
+    /// Clamp a requested output budget to the model's output limit
+    #[must_use]
+    pub fn clamp_max_tokens(&self, requested: u32) -> u32 {
+        self.max_output_tokens
+            .map_or(requested, |max| requested.min(max))
+    }
+
+    /// Input tokens left for history once `max_tokens` is reserved for
+    /// output, if the context window is known
+    #[must_use]
+    pub fn input_budget(&self, max_tokens: u32) -> Option<u32> {
+        self.context_window
+            .map(|window| window.saturating_sub(self.clamp_max_tokens(max_tokens)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn model_info_limits_and_legacy_entries() {
+        let model = ModelInfo::new("m", "p")
+            .with_limits(10_000, 2_000)
+            .with_vision();
+        assert_eq!(model.clamp_max_tokens(8_000), 2_000);
+        assert_eq!(model.clamp_max_tokens(500), 500);
+        assert_eq!(model.input_budget(8_000), Some(8_000));
+
+        let legacy: ModelInfo = serde_json::from_str(r#"{"id":"m","provider":"p"}"#).unwrap();
+        assert!(!legacy.supports_tools && !legacy.supports_vision);
+        assert_eq!(legacy.clamp_max_tokens(8_000), 8_000);
+        assert_eq!(legacy.input_budget(8_000), None);
+    }
+
     #[test]
     fn serde_round_trip_known_variants() {
         let variants = [
             (ProviderApiType::Anthropic, "\"anthropic\""),
             (ProviderApiType::OpenAi, "\"openai\""),
+            (ProviderApiType::OpenAiResponses, "\"openai-responses\""),
             (ProviderApiType::Google, "\"google\""),
             (ProviderApiType::Groq, "\"groq\""),
             (ProviderApiType::Mistral, "\"mistral\""),
+            (ProviderApiType::Ollama, "\"ollama\""),
             (ProviderApiType::Synapse, "\"synapse\""),
         ];
 
@@ -165,12 +380,53 @@ mod tests {
         let config = ProviderConfig {
             api_type: ProviderApiType::Synapse,
             base_url: Some("http://localhost:6111".to_string()),
-            api_key_env: None,
-            api_key: None,
+            headers: BTreeMap::from([("anthropic-beta".to_string(), "flag".to_string())]),
+            read_timeout_secs: Some(120),
+            ca_cert: Some(PathBuf::from("/etc/ssl/corp.pem")),
+            ..Default::default()
         };
 
         let toml_str = toml::to_string(&config).unwrap();
         let deserialized: ProviderConfig = toml::from_str(&toml_str).unwrap();
         assert_eq!(deserialized, config);
     }
+
+    #[test]
+    fn pricing_covers_cached_tokens() {
+        let pricing = ModelPricing::new(3.0, 15.0).with_cache(3.75, 0.3);
+        let usage = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 100_000,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: 1_000_000,
+        };
+        assert!((pricing.cost(&usage) - 4.8).abs() < 1e-9);
+
+        // Uncached rates apply when cache rates are unknown
+        assert!((ModelPricing::new(3.0, 15.0).cost(&usage) - 7.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fallback_chain_deserializes_from_toml() {
+        #[derive(Deserialize)]
+        struct Config {
+            fallback: Vec<FallbackTarget>,
+        }
+
+        let config: Config = toml::from_str(
+            r#"
+            [[fallback]]
+            provider = "anthropic"
+            model = "claude-sonnet-4-5"
+
+            [[fallback]]
+            provider = "openai"
+            model = "gpt-4o"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.fallback.len(), 2);
+        assert_eq!(config.fallback[1].provider, "openai");
+    }
 }
@@ -0,0 +1,518 @@
+//! Append-only session logs.
+//!
+//! A [`SessionLog`] persists a [`Conversation`] as JSON Lines: each call
+//! to [`SessionLog::append`] writes only what changed since the last one,
+//! so saving a turn costs the size of the turn rather than the history,
+//! and the file can be tailed while a session runs. A line cut short by a
+//! crash is dropped on load. [`SessionLog::snapshot`] rewrites the log as
+//! one record per message and swaps it in with an atomic rename.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::conversation::{Conversation, MessageId, Node, StoredConversation};
+
+/// Version written in the header of new logs.
+const FORMAT_VERSION: u32 = 1;
+
+/// When appended records are flushed to stable storage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Fsync after every append.
+    #[default]
+    Always,
+    /// Fsync an append once this long has passed since the last fsync.
+    Interval(Duration),
+    /// Leave flushing to the operating system.
+    Never,
+}
+
+/// One line of a session log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    /// First line of every log.
+    Header { version: u32 },
+    /// System prompt.
+    System { system: Option<String> },
+    /// A message, replacing any earlier record with the same ID.
+    Message(Node),
+    /// Messages deleted by a rewind or clear.
+    Remove { ids: Vec<MessageId> },
+    /// Last message of the active branch.
+    Head { head: Option<MessageId> },
+    /// Full set of pinned messages.
+    Pins { pinned: BTreeSet<MessageId> },
+    /// Record type from a newer version, skipped.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Append-only JSON Lines log of a conversation.
+#[derive(Debug)]
+pub struct SessionLog {
+    path: PathBuf,
+    file: File,
+    policy: SyncPolicy,
+    last_sync: Instant,
+}
+
+impl SessionLog {
+    /// Open a log, creating an empty one if the file does not exist.
+    ///
+    /// Returns the log with the conversation replayed from it. A final
+    /// line without a newline that does not parse is treated as a write
+    /// cut short by a crash and truncated away.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or written, or if a
+    /// complete line is not a valid record.
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<(Self, Conversation)> {
+        let path = path.into();
+        if !path.exists() {
+            let mut conversation = Conversation::new();
+            let log = Self::create(path, &mut conversation)?;
+            return Ok((log, conversation));
+        }
+
+        let bytes = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        let (mut conversation, valid_len) =
+            replay(&bytes).with_context(|| format!("loading {}", path.display()))?;
+        conversation.take_journal();
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        if valid_len < bytes.len() {
+            tracing::warn!(path = %path.display(), "session: dropping truncated last record");
+            file.set_len(valid_len as u64)?;
+            file.sync_all()?;
+        }
+        let mut log = Self {
+            path,
+            file,
+            policy: SyncPolicy::default(),
+            last_sync: Instant::now(),
+        };
+        if valid_len > 0 && bytes[valid_len - 1] != b'\n' {
+            log.file.write_all(b"\n")?;
+        }
+        Ok((log, conversation))
+    }
+
+    /// Create a log holding a conversation, replacing any existing file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn create(
+        path: impl Into<PathBuf>,
+        conversation: &mut Conversation,
+    ) -> anyhow::Result<Self> {
+        let path = path.into();
+        write_atomic(&path, &snapshot_lines(conversation)?)?;
+        conversation.take_journal();
+        Ok(Self {
+            file: OpenOptions::new().append(true).open(&path)?,
+            path,
+            policy: SyncPolicy::default(),
+            last_sync: Instant::now(),
+        })
+    }
+
+    /// Convert a conversation saved with [`Conversation::save`] to a log.
+    ///
+    /// The JSON file is left in place.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON file cannot be loaded or the log
+    /// cannot be written.
+    pub fn migrate(json: &Path, path: impl Into<PathBuf>) -> anyhow::Result<(Self, Conversation)> {
+        let mut conversation = Conversation::load(json)?;
+        let log = Self::create(path, &mut conversation)?;
+        Ok((log, conversation))
+    }
+
+    /// Set when appends are flushed to stable storage.
+    #[must_use]
+    pub const fn with_sync_policy(mut self, policy: SyncPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Get the log file path.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append the changes made to a conversation since the last append.
+    ///
+    /// Returns the number of records written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the records cannot be written or synced.
+    pub fn append(&mut self, conversation: &mut Conversation) -> anyhow::Result<usize> {
+        let journal = conversation.take_journal();
+        let mut records = Vec::new();
+        if journal.system {
+            records.push(Record::System {
+                system: conversation.system().map(String::from),
+            });
+        }
+        records.extend(
+            parents_first(conversation, &journal.upserted)
+                .into_iter()
+                .map(|node| Record::Message(node.clone())),
+        );
+        // The head moves off removed messages before they go, so a log cut
+        // short between the two records still replays
+        if journal.head {
+            records.push(Record::Head {
+                head: conversation.head(),
+            });
+        }
+        if !journal.removed.is_empty() {
+            records.push(Record::Remove {
+                ids: journal.removed.into_iter().collect(),
+            });
+        }
+        if journal.pinned {
+            records.push(pins(conversation));
+        }
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        self.file.write_all(&encode(&records)?)?;
+        self.sync()?;
+        Ok(records.len())
+    }
+
+    /// Rewrite the log as a snapshot of the conversation.
+    ///
+    /// The snapshot is written to a temporary file, synced and renamed
+    /// over the log, so a crash leaves either the old or the new log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot cannot be written.
+    pub fn snapshot(&mut self, conversation: &mut Conversation) -> anyhow::Result<()> {
+        write_atomic(&self.path, &snapshot_lines(conversation)?)?;
+        conversation.take_journal();
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Apply the sync policy after an append.
+    fn sync(&mut self) -> anyhow::Result<()> {
+        let due = match self.policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+            SyncPolicy::Never => false,
+        };
+        if due {
+            self.file.sync_data()?;
+            self.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+/// Changed nodes ordered so each comes after its parent.
+///
+/// Moved messages can have older IDs than their new parents, and a log
+/// cut short must never hold a message whose parent is missing.
+fn parents_first<'a>(conversation: &'a Conversation, ids: &BTreeSet<MessageId>) -> Vec<&'a Node> {
+    let mut ordered: Vec<&Node> = Vec::with_capacity(ids.len());
+    let mut placed = HashSet::new();
+    for &id in ids {
+        let mut chain = Vec::new();
+        let mut cursor = conversation.node(id);
+        while let Some(node) = cursor
+            && ids.contains(&node.id)
+            && !placed.contains(&node.id)
+        {
+            placed.insert(node.id);
+            chain.push(node);
+            cursor = node.parent.and_then(|parent| conversation.node(parent));
+        }
+        ordered.extend(chain.into_iter().rev());
+    }
+    ordered
+}
+
+/// Record of the pinned messages.
+fn pins(conversation: &Conversation) -> Record {
+    Record::Pins {
+        pinned: conversation.pinned_ids().clone(),
+    }
+}
+
+/// Serialize a whole conversation as log lines.
+fn snapshot_lines(conversation: &Conversation) -> anyhow::Result<Vec<u8>> {
+    let mut records = vec![
+        Record::Header {
+            version: FORMAT_VERSION,
+        },
+        Record::System {
+            system: conversation.system().map(String::from),
+        },
+    ];
+    records.extend(
+        conversation
+            .nodes()
+            .iter()
+            .map(|node| Record::Message(node.clone())),
+    );
+    records.push(Record::Head {
+        head: conversation.head(),
+    });
+    records.push(pins(conversation));
+    encode(&records)
+}
+
+/// Serialize records as newline-terminated JSON lines.
+fn encode(records: &[Record]) -> anyhow::Result<Vec<u8>> {
+    let mut lines = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut lines, record)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+/// Rebuild a conversation from log bytes.
+///
+/// Returns the conversation and the length of the valid prefix, which
+/// excludes a truncated last line.
+fn replay(bytes: &[u8]) -> anyhow::Result<(Conversation, usize)> {
+    let mut system = None;
+    let mut nodes = BTreeMap::new();
+    let mut head = None;
+    let mut pinned = BTreeSet::new();
+    let mut next_id = 0;
+    let mut valid_len = 0;
+
+    let mut offset = 0;
+    for (number, line) in bytes.split_inclusive(|&b| b == b'\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let complete = line.ends_with(b"\n");
+        if line.iter().all(u8::is_ascii_whitespace) {
+            valid_len = offset;
+            continue;
+        }
+
+        let record: Record = match serde_json::from_slice(line) {
+            Ok(record) => record,
+            Err(_) if !complete => {
+                valid_len = start;
+                break;
+            }
+            Err(e) => bail!("line {}: {e}", number + 1),
+        };
+        valid_len = offset;
+
+        match record {
+            Record::Header { version } if version > FORMAT_VERSION => {
+                bail!("unsupported session log version {version}");
+            }
+            Record::Header { .. } | Record::Unknown => {}
+            Record::System { system: s } => system = s,
+            Record::Message(node) => {
+                next_id = next_id.max(node.id.next());
+                nodes.insert(node.id, node);
+            }
+            Record::Remove { ids } => {
+                for id in ids {
+                    next_id = next_id.max(id.next());
+                    nodes.remove(&id);
+                }
+            }
+            Record::Head { head: h } => head = h,
+            Record::Pins { pinned: p } => pinned = p,
+        }
+    }
+
+    pinned.retain(|id| nodes.contains_key(id));
+    let stored = StoredConversation::from_parts(
+        system,
+        nodes.into_values().collect(),
+        head,
+        next_id,
+        pinned,
+    );
+    let conversation = Conversation::try_from(stored).map_err(anyhow::Error::msg)?;
+    Ok((conversation, valid_len))
+}
+
+/// Replace a file's contents atomically.
+///
+/// Writes a temporary file beside it, syncs it and renames it into
+/// place, then syncs the directory so the rename survives a crash.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+    if let Some(parent) = parent {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temp = path.with_file_name(name);
+
+    let mut file = File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&temp, path)?;
+
+    #[cfg(unix)]
+    File::open(parent.unwrap_or_else(|| Path::new(".")))?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ContentBlock;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("session-test-{}", uuid::Uuid::new_v4()));
+        dir.join(name)
+    }
+
+    fn texts(conv: &Conversation) -> Vec<String> {
+        conv.messages().iter().map(|m| m.content.text()).collect()
+    }
+
+    #[test]
+    fn appends_changes_and_replays_them() {
+        let path = temp_path("session.jsonl");
+        let (log, mut conv) = SessionLog::open(&path).unwrap();
+        let mut log = log.with_sync_policy(SyncPolicy::Never);
+
+        conv.set_system("Be brief.");
+        let question = conv.add_user_message("q");
+        conv.add_assistant_blocks(vec![ContentBlock::ToolUse {
+            id: "call_1".into(),
+            name: "ls".into(),
+            input: serde_json::json!({}),
+        }]);
+        conv.add_tool_result("call_1".into(), "a".into(), false);
+        assert_eq!(log.append(&mut conv).unwrap(), 5);
+        let before = std::fs::metadata(&path).unwrap().len();
+
+        // Only the merged result message is rewritten
+        conv.add_tool_result("call_2".into(), "b".into(), false);
+        conv.pin(question);
+        assert_eq!(log.append(&mut conv).unwrap(), 2);
+        assert_eq!(log.append(&mut conv).unwrap(), 0);
+
+        let answer = conv.add_assistant_message("done");
+        conv.fork_at(answer);
+        conv.add_assistant_message("retry");
+        conv.switch_to(answer);
+        conv.rewind_to(question);
+        log.append(&mut conv).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > before);
+
+        let (_, loaded) = SessionLog::open(&path).unwrap();
+        assert_eq!(loaded.system(), Some("Be brief."));
+        assert_eq!(texts(&loaded), ["q"]);
+        assert!(loaded.is_pinned(question));
+        assert!(loaded.message(answer).is_none());
+        assert_eq!(loaded.head(), conv.head());
+
+        // IDs deleted by the rewind are not reused
+        let mut loaded = loaded;
+        assert!(loaded.add_user_message("next") > answer);
+    }
+
+    #[test]
+    fn drops_a_truncated_last_line() {
+        let path = temp_path("session.jsonl");
+        let (mut log, mut conv) = SessionLog::open(&path).unwrap();
+        conv.add_user_message("kept");
+        log.append(&mut conv).unwrap();
+        drop(log);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"type":"message","id":1,"role":"user","con"#)
+            .unwrap();
+        drop(file);
+
+        let (mut log, mut conv) = SessionLog::open(&path).unwrap();
+        assert_eq!(texts(&conv), ["kept"]);
+        conv.add_user_message("after");
+        log.append(&mut conv).unwrap();
+
+        let (_, loaded) = SessionLog::open(&path).unwrap();
+        assert_eq!(texts(&loaded), ["kept", "after"]);
+
+        // Damage before the last line is an error
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, format!("{{oops\n{contents}")).unwrap();
+        assert!(SessionLog::open(&path).is_err());
+    }
+
+    #[test]
+    fn replays_a_rewind_cut_short_anywhere() {
+        let path = temp_path("session.jsonl");
+        let (log, mut conv) = SessionLog::open(&path).unwrap();
+        let mut log = log.with_sync_policy(SyncPolicy::Never);
+        let question = conv.add_user_message("q");
+        conv.add_assistant_message("a");
+        conv.add_user_message("more");
+        log.append(&mut conv).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        conv.rewind_to(question);
+        conv.add_assistant_message("b");
+        log.append(&mut conv).unwrap();
+        let after = std::fs::read(&path).unwrap();
+        let tail = String::from_utf8_lossy(&after[before.len()..]).into_owned();
+        let head = tail.find(r#""type":"head""#).unwrap();
+        assert!(head < tail.find(r#""type":"remove""#).unwrap());
+
+        // Cut inside every record of the rewind, including the head line
+        for len in before.len()..after.len() {
+            std::fs::write(&path, &after[..len]).unwrap();
+            let (_, loaded) = SessionLog::open(&path).unwrap();
+            assert!(loaded.message(question).is_some());
+        }
+        std::fs::write(&path, &after).unwrap();
+        let (_, loaded) = SessionLog::open(&path).unwrap();
+        assert_eq!(texts(&loaded), ["q", "b"]);
+    }
+
+    #[test]
+    fn migrates_json_and_snapshots() {
+        let json = temp_path("conversation.json");
+        let mut conv = Conversation::with_system("Be brief.");
+        conv.add_user_message("hi");
+        conv.add_assistant_message("hello");
+        conv.save(&json).unwrap();
+
+        let path = json.with_file_name("session.jsonl");
+        let (mut log, mut conv) = SessionLog::migrate(&json, &path).unwrap();
+        assert!(json.exists());
+        conv.add_user_message("again");
+        log.append(&mut conv).unwrap();
+        log.snapshot(&mut conv).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(r#"{"type":"header","version":1}"#));
+        assert_eq!(contents.lines().count(), 7);
+        let (_, loaded) = SessionLog::open(&path).unwrap();
+        assert_eq!(texts(&loaded), ["hi", "hello", "again"]);
+        assert_eq!(loaded.system(), Some("Be brief."));
+    }
+}
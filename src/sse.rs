@@ -0,0 +1,247 @@
+//! Incremental Server-Sent Events decoder.
+//!
+//! Implements the event stream interpretation rules of the HTML
+//! specification: `\n`, `\r\n` and `\r` line endings, multi-line `data`,
+//! `event`, `id` and `retry` fields, comments and a leading byte order
+//! mark. Bytes are buffered until a full line arrives, so multi-byte
+//! characters split across network chunks decode correctly.
+
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+
+use crate::error::{AgentError, Result};
+
+/// One dispatched event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Event type, `message` when the stream names none.
+    pub event: String,
+    /// Data lines joined with `\n`.
+    pub data: String,
+    /// Last event ID seen on the stream, if any.
+    pub id: Option<String>,
+}
+
+/// Incremental event stream decoder.
+///
+/// Feed it network chunks in order; it returns every event completed by
+/// each chunk. An event still pending when the stream ends is discarded,
+/// as the specification requires.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    line: Vec<u8>,
+    /// The previous chunk ended in `\r`, so a leading `\n` continues it.
+    skip_lf: bool,
+    started: bool,
+    event: String,
+    data: String,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl SseDecoder {
+    /// Create a decoder at the start of a stream.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reconnection delay most recently requested by the server.
+    #[must_use]
+    pub const fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Last event ID seen on the stream.
+    #[must_use]
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Decode a chunk and return the events it completes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::Parse`] if a line is not valid UTF-8.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<SseEvent>> {
+        let mut events = Vec::new();
+        for &byte in bytes {
+            if std::mem::take(&mut self.skip_lf) && byte == b'\n' {
+                continue;
+            }
+            if byte == b'\n' || byte == b'\r' {
+                self.skip_lf = byte == b'\r';
+                let line = std::mem::take(&mut self.line);
+                if let Some(event) = self.process_line(&line)? {
+                    events.push(event);
+                }
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(events)
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Result<Option<SseEvent>> {
+        let mut line = std::str::from_utf8(line)
+            .map_err(|e| AgentError::Parse(format!("invalid UTF-8 in event stream: {e}")))?;
+        if !self.started {
+            self.started = true;
+            line = line.strip_prefix('\u{feff}').unwrap_or(line);
+        }
+
+        if line.is_empty() {
+            return Ok(self.dispatch());
+        }
+        if line.starts_with(':') {
+            return Ok(None);
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => value.clone_into(&mut self.event),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => {
+                self.last_event_id = (!value.is_empty()).then(|| value.to_string());
+            }
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// Complete the pending event at a blank line.
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = std::mem::take(&mut self.event);
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        Some(SseEvent {
+            event: if event.is_empty() {
+                "message".to_string()
+            } else {
+                event
+            },
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+/// Decode a byte stream, such as a response body, into events.
+///
+/// The stream ends after the first transport or decoding error.
+pub fn sse_events<S, B, E>(bytes: S) -> impl Stream<Item = Result<SseEvent>> + Send
+where
+    S: Stream<Item = std::result::Result<B, E>> + Send,
+    B: AsRef<[u8]> + Send,
+    E: Into<AgentError> + Send,
+{
+    async_stream::stream! {
+        let mut decoder = SseDecoder::new();
+        futures::pin_mut!(bytes);
+        while let Some(chunk) = bytes.next().await {
+            let events = chunk
+                .map_err(Into::into)
+                .and_then(|chunk| decoder.feed(chunk.as_ref()));
+            match events {
+                Ok(events) => {
+                    for event in events {
+                        yield Ok(event);
+                    }
+                }
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        chunks
+            .iter()
+            .flat_map(|chunk| decoder.feed(chunk).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn handles_line_endings_and_multi_line_data() {
+        let events = decode(&[
+            b"\xEF\xBB\xBFevent: update\r\ndata: one\r\ndata:two\r",
+            b"\nid: 7\r\n\r\n: comment\n\ndata\rdata: x\r\r",
+        ]);
+
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: "update".into(),
+                    data: "one\ntwo".into(),
+                    id: Some("7".into()),
+                },
+                SseEvent {
+                    event: "message".into(),
+                    data: "\nx".into(),
+                    id: Some("7".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn buffers_characters_split_across_chunks() {
+        let text = "data: héllo ✓\n\n".as_bytes();
+        let chunks: Vec<&[u8]> = text.chunks(1).collect();
+        assert_eq!(decode(&chunks)[0].data, "héllo ✓");
+    }
+
+    #[test]
+    fn tracks_retry_and_drops_incomplete_events() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder
+            .feed(b"retry: 1500\nretry: soon\nevent: ping\n\ndata: cut")
+            .unwrap();
+        assert!(events.is_empty());
+        assert_eq!(decoder.retry(), Some(Duration::from_millis(1500)));
+        assert_eq!(decoder.last_event_id(), None);
+
+        assert!(matches!(
+            SseDecoder::new().feed(b"data: \xFF\n\n"),
+            Err(AgentError::Parse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stream_ends_after_transport_error() {
+        let chunks: Vec<std::result::Result<&[u8], AgentError>> = vec![
+            Ok(b"data: a\n\n"),
+            Err(AgentError::StreamEnded),
+            Ok(b"data: b\n\n"),
+        ];
+        let events: Vec<_> = sse_events(futures::stream::iter(chunks)).collect().await;
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].as_ref().unwrap().data, "a");
+        assert!(matches!(events[1], Err(AgentError::StreamEnded)));
+    }
+}
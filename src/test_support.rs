@@ -0,0 +1,152 @@
+//! Local HTTP stub server shared by provider tests.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parking_lot::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A request received by a [`StubServer`].
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    /// Request path, including any query string.
+    pub path: String,
+    /// Request line and headers, lowercased.
+    pub head: String,
+    /// Request body.
+    pub body: String,
+}
+
+/// Format a complete HTTP response that closes the connection.
+pub fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Handler choosing the raw response to a request, given its index.
+///
+/// Returning `None` holds the connection open without replying.
+type Respond = dyn Fn(&StubRequest, usize) -> Option<String> + Send + Sync;
+
+/// HTTP server on a local port answering with canned responses.
+pub struct StubServer {
+    url: String,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+    hits: Arc<AtomicUsize>,
+}
+
+impl StubServer {
+    /// Start a server that answers each request with `respond`.
+    pub async fn start(
+        respond: impl Fn(&StubRequest, usize) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let hits = Arc::new(AtomicUsize::new(0));
+        let respond: Arc<Respond> = Arc::new(respond);
+
+        let (seen, counter) = (Arc::clone(&requests), Arc::clone(&hits));
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let index = counter.fetch_add(1, Ordering::SeqCst);
+                let (seen, respond) = (Arc::clone(&seen), Arc::clone(&respond));
+                tokio::spawn(serve(socket, index, seen, respond));
+            }
+        });
+
+        Self {
+            url,
+            requests,
+            hits,
+        }
+    }
+
+    /// Serve the responses in order, repeating the last one.
+    pub async fn sequence(responses: Vec<String>) -> Self {
+        Self::start(move |_, index| Some(responses[index.min(responses.len() - 1)].clone())).await
+    }
+
+    /// Serve a body per request path, and 404 for any other path.
+    pub async fn routes(content_type: &'static str, routes: Vec<(&'static str, String)>) -> Self {
+        Self::start(move |request, _| {
+            let body = routes.iter().find(|(path, _)| *path == request.path);
+            Some(body.map_or_else(
+                || response("404 Not Found", "text/plain", ""),
+                |(_, body)| response("200 OK", content_type, body),
+            ))
+        })
+        .await
+    }
+
+    /// Base URL of the server, without a trailing slash.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Requests received so far, in arrival order.
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().clone()
+    }
+
+    /// Number of connections accepted so far.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+}
+
+/// Read one request from a connection and answer it.
+async fn serve(
+    mut socket: TcpStream,
+    index: usize,
+    seen: Arc<Mutex<Vec<StubRequest>>>,
+    respond: Arc<Respond>,
+) {
+    let mut raw = Vec::new();
+    let mut buf = [0; 8192];
+    // Read headers, then as much body as content-length says
+    let body_start = loop {
+        let n = socket.read(&mut buf).await.unwrap_or(0);
+        raw.extend_from_slice(&buf[..n]);
+        if let Some(pos) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if n == 0 {
+            return;
+        }
+    };
+    let head = String::from_utf8_lossy(&raw[..body_start]).to_lowercase();
+    let length = head
+        .lines()
+        .find_map(|l| l.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while raw.len() < body_start + length {
+        let n = socket.read(&mut buf).await.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+        raw.extend_from_slice(&buf[..n]);
+    }
+
+    let request = StubRequest {
+        path: String::from_utf8_lossy(&raw[..body_start])
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or_default()
+            .to_string(),
+        head,
+        body: String::from_utf8_lossy(&raw[body_start..]).into_owned(),
+    };
+    seen.lock().push(request.clone());
+
+    if let Some(reply) = respond(&request, index) {
+        let _ = socket.write_all(reply.as_bytes()).await;
+        let _ = socket.shutdown().await;
+    } else {
+        while matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {}
+    }
+}
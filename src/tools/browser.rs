@@ -79,6 +79,14 @@ pub struct Screenshot {
     pub format: &'static str,
 }
 
+impl Screenshot {
+    /// Convert the screenshot into an image content block.
+    #[must_use]
+    pub fn to_content_block(&self) -> crate::types::ContentBlock {
+        crate::types::ContentBlock::image(format!("image/{}", self.format), &self.data)
+    }
+}
+
 /// Page content result
 #[derive(Debug)]
 pub struct PageContent {
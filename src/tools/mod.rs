@@ -9,22 +9,17 @@ pub mod shell;
 pub mod web;
 
 /// Classification for tool execution strategy
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum ToolKind {
     /// Safe to run fully in parallel (read-only operations)
     Read,
     /// Serialized among mutating tools, parallel with reads
+    #[default]
     Mutate,
     /// Requires user response before batch continues
     Interactive,
 }
 
-impl Default for ToolKind {
-    fn default() -> Self {
-        Self::Mutate
-    }
-}
-
 /// Provider of executable tools
 ///
 /// Implement this trait to define a set of tools that can be
@@ -36,13 +31,37 @@ pub trait ToolProvider: Send + Sync {
 
     /// Execute a named tool with JSON-string arguments
     ///
+    /// Implementations stop work and clean up when `cancel` fires,
+    /// returning [`AgentError::Cancelled`](crate::error::AgentError::Cancelled)
+    /// so callers can tell cancellation apart from failure.
+    ///
     /// # Errors
     ///
-    /// Returns error if the tool name is unknown or execution fails.
-    async fn execute(&self, name: &str, arguments: &str) -> anyhow::Result<String>;
+    /// Returns error if the tool name is unknown, execution fails, or the
+    /// call is cancelled.
+    async fn execute(
+        &self,
+        name: &str,
+        arguments: &str,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String>;
 
     /// Classify a tool for execution strategy
     fn kind(&self, _name: &str) -> ToolKind {
         ToolKind::Mutate
     }
+
+    /// Describe the permission a tool call needs before it runs
+    ///
+    /// Returns `None` when the call needs no permission check.
+    fn permission(
+        &self,
+        _name: &str,
+        _arguments: &str,
+    ) -> Option<(
+        crate::permission::PermissionAction,
+        crate::permission::PermissionContext,
+    )> {
+        None
+    }
 }
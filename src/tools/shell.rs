@@ -1,12 +1,17 @@
 //! Sandboxed shell execution tool.
 //!
 //! Runs commands via `/bin/sh -c`, captures stdout/stderr,
-//! enforces timeouts, and augments `PATH`.
+//! enforces timeouts, and augments `PATH`. Each command runs in its own
+//! process group, which is killed as a whole on timeout or cancellation.
 
 use std::path::PathBuf;
 use std::time::Duration;
 
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio_util::sync::CancellationToken;
+
+use crate::error::AgentError;
 
 /// Default command timeout in seconds.
 const DEFAULT_TIMEOUT_SECS: u64 = 120;
@@ -29,6 +34,9 @@ pub enum ShellError {
     /// Command timed out.
     #[error("command timed out after {0}s")]
     Timeout(u64),
+    /// Command was cancelled.
+    #[error("command cancelled")]
+    Cancelled,
 }
 
 /// Result of a shell command execution.
@@ -81,11 +89,13 @@ impl ShellTool {
     ///
     /// # Errors
     ///
-    /// Returns error if the command is empty, fails to spawn, or times out.
+    /// Returns error if the command is empty, fails to spawn, times out,
+    /// or is cancelled.
     pub async fn execute(
         &self,
         command: &str,
         timeout_secs: Option<u64>,
+        cancel: &CancellationToken,
     ) -> Result<ShellOutput, ShellError> {
         let command = command.trim();
         if command.is_empty() {
@@ -100,7 +110,7 @@ impl ShellTool {
 
         tracing::debug!(command = %command, timeout_secs, "shell: executing command");
 
-        let child = {
+        let mut child = {
             let mut cmd = Command::new("/bin/sh");
             cmd.arg("-c")
                 .arg(command)
@@ -116,35 +126,70 @@ impl ShellTool {
             cmd.spawn()?
         };
 
-        let result =
-            tokio::time::timeout(Duration::from_secs(timeout_secs), child.wait_with_output()).await;
-
-        match result {
-            Ok(Ok(output)) => {
-                let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
-                let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
-                let exit_code = output.status.code().unwrap_or(-1);
-
-                tracing::debug!(
-                    exit_code,
-                    stdout_len = stdout.len(),
-                    stderr_len = stderr.len(),
-                    "shell: command completed"
-                );
-
-                Ok(ShellOutput {
-                    exit_code,
-                    stdout,
-                    stderr,
-                })
-            }
-            Ok(Err(e)) => Err(ShellError::Spawn(e)),
-            Err(_) => {
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        // Background jobs can hold the pipes open after the shell exits,
+        // so reading them is bounded by the same timeout and cancellation
+        let (status, stdout, stderr) = tokio::select! {
+            (status, stdout, stderr) = async {
+                tokio::join!(child.wait(), read_pipe(stdout), read_pipe(stderr))
+            } => (status?, stdout, stderr),
+            () = tokio::time::sleep(Duration::from_secs(timeout_secs)) => {
                 tracing::warn!(command = %command, timeout_secs, "shell: command timed out");
-                Err(ShellError::Timeout(timeout_secs))
+                kill_process_group(&mut child).await;
+                return Err(ShellError::Timeout(timeout_secs));
             }
-        }
+            () = cancel.cancelled() => {
+                tracing::debug!(command = %command, "shell: command cancelled");
+                kill_process_group(&mut child).await;
+                return Err(ShellError::Cancelled);
+            }
+        };
+
+        let stdout = String::from_utf8_lossy(&stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+        let exit_code = status.code().unwrap_or(-1);
+
+        tracing::debug!(
+            exit_code,
+            stdout_len = stdout.len(),
+            stderr_len = stderr.len(),
+            "shell: command completed"
+        );
+
+        Ok(ShellOutput {
+            exit_code,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// Read a child pipe to the end.
+async fn read_pipe(pipe: Option<impl AsyncRead + Unpin>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut buf).await;
+    }
+    buf
+}
+
+/// Kill a command and every process it started.
+///
+/// The shell leads its own process group, so signalling the group also
+/// reaches background jobs and pipelines that would outlive the shell.
+async fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let _ = Command::new("kill")
+            .args(["-s", "KILL", "--", &format!("-{pid}")])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await;
     }
+    let _ = child.kill().await;
 }
 
 #[async_trait::async_trait]
@@ -170,7 +215,12 @@ impl super::ToolProvider for ShellTool {
         }]
     }
 
-    async fn execute(&self, name: &str, arguments: &str) -> anyhow::Result<String> {
+    async fn execute(
+        &self,
+        name: &str,
+        arguments: &str,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<String> {
         if name != "Bash" {
             anyhow::bail!("unknown tool: {name}");
         }
@@ -181,7 +231,11 @@ impl super::ToolProvider for ShellTool {
             .ok_or_else(|| anyhow::anyhow!("missing 'command' argument"))?;
         let timeout = args["timeout"].as_u64();
 
-        let output = Self::execute(self, command, timeout).await?;
+        let output = match Self::execute(self, command, timeout, cancel).await {
+            Ok(output) => output,
+            Err(ShellError::Cancelled) => return Err(AgentError::Cancelled.into()),
+            Err(e) => return Err(e.into()),
+        };
 
         Ok(serde_json::to_string(&output)?)
     }
@@ -189,6 +243,26 @@ impl super::ToolProvider for ShellTool {
     fn kind(&self, _name: &str) -> super::ToolKind {
         super::ToolKind::Mutate
     }
+
+    fn permission(
+        &self,
+        _name: &str,
+        arguments: &str,
+    ) -> Option<(
+        crate::permission::PermissionAction,
+        crate::permission::PermissionContext,
+    )> {
+        let args: serde_json::Value = serde_json::from_str(arguments).ok()?;
+        let command = args["command"].as_str()?.to_string();
+
+        Some((
+            crate::permission::PermissionAction::Execute,
+            crate::permission::PermissionContext::Bash {
+                command,
+                working_dir: self.working_dir.clone(),
+            },
+        ))
+    }
 }
 
 impl Default for ShellTool {
@@ -216,7 +290,10 @@ mod tests {
     #[tokio::test]
     async fn simple_command_returns_stdout() {
         let tool = make_tool();
-        let result = tool.execute("echo hello", None).await.unwrap();
+        let result = tool
+            .execute("echo hello", None, &CancellationToken::new())
+            .await
+            .unwrap();
         assert_eq!(result.exit_code, 0);
         assert!(result.stdout.contains("hello"));
     }
@@ -224,29 +301,76 @@ mod tests {
     #[tokio::test]
     async fn stderr_is_captured() {
         let tool = make_tool();
-        let result = tool.execute("echo err >&2", None).await.unwrap();
+        let result = tool
+            .execute("echo err >&2", None, &CancellationToken::new())
+            .await
+            .unwrap();
         assert!(result.stderr.contains("err"));
     }
 
     #[tokio::test]
     async fn exit_code_is_reported() {
         let tool = make_tool();
-        let result = tool.execute("exit 42", None).await.unwrap();
+        let result = tool
+            .execute("exit 42", None, &CancellationToken::new())
+            .await
+            .unwrap();
         assert_eq!(result.exit_code, 42);
     }
 
     #[tokio::test]
     async fn timeout_is_enforced() {
         let tool = make_tool();
-        let result = tool.execute("sleep 10", Some(1)).await;
+        let result = tool
+            .execute("sleep 10", Some(1), &CancellationToken::new())
+            .await;
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), ShellError::Timeout(1)));
     }
 
+    #[tokio::test]
+    async fn timeout_covers_background_jobs_holding_pipes() {
+        let tool = make_tool();
+        let start = std::time::Instant::now();
+        let result = tool
+            .execute("sleep 30 & echo hi", Some(1), &CancellationToken::new())
+            .await;
+        assert!(matches!(result, Err(ShellError::Timeout(1))));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn cancel_kills_process_group() {
+        let tool = make_tool();
+        let marker = std::env::temp_dir().join(format!("shell-cancel-{}", uuid::Uuid::new_v4()));
+        let command = format!("(sleep 1; touch {}) & sleep 10", marker.display());
+        let cancel = CancellationToken::new();
+
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            trigger.cancel();
+        });
+        let result = tool.execute(&command, None, &cancel).await;
+        assert!(matches!(result, Err(ShellError::Cancelled)));
+
+        // The background job was killed along with the shell
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists());
+    }
+
     #[tokio::test]
     async fn empty_command_is_rejected() {
         let tool = make_tool();
-        assert!(tool.execute("", None).await.is_err());
-        assert!(tool.execute("   ", None).await.is_err());
+        assert!(
+            tool.execute("", None, &CancellationToken::new())
+                .await
+                .is_err()
+        );
+        assert!(
+            tool.execute("   ", None, &CancellationToken::new())
+                .await
+                .is_err()
+        );
     }
 }
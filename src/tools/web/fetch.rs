@@ -273,7 +273,12 @@ impl crate::tools::ToolProvider for WebFetchTool {
         }]
     }
 
-    async fn execute(&self, name: &str, arguments: &str) -> anyhow::Result<String> {
+    async fn execute(
+        &self,
+        name: &str,
+        arguments: &str,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String> {
         if name != "WebFetch" {
             anyhow::bail!("unknown tool: {name}");
         }
@@ -284,7 +289,7 @@ impl crate::tools::ToolProvider for WebFetchTool {
             .ok_or_else(|| anyhow::anyhow!("missing 'url' argument"))?;
         let method = args["method"].as_str();
 
-        let response = self.fetch(url, method).await?;
+        let response = crate::provider::cancellable(Some(cancel), self.fetch(url, method)).await?;
 
         Ok(serde_json::json!({
             "status": response.status,
@@ -297,6 +302,23 @@ impl crate::tools::ToolProvider for WebFetchTool {
     fn kind(&self, _name: &str) -> crate::tools::ToolKind {
         crate::tools::ToolKind::Read
     }
+
+    fn permission(
+        &self,
+        _name: &str,
+        arguments: &str,
+    ) -> Option<(
+        crate::permission::PermissionAction,
+        crate::permission::PermissionContext,
+    )> {
+        let args: serde_json::Value = serde_json::from_str(arguments).ok()?;
+        let url = args["url"].as_str()?.to_string();
+
+        Some((
+            crate::permission::PermissionAction::WebFetch,
+            crate::permission::PermissionContext::WebFetch { url },
+        ))
+    }
 }
 
 #[cfg(test)]
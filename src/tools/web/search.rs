@@ -213,7 +213,12 @@ impl crate::tools::ToolProvider for WebSearchTool {
         }]
     }
 
-    async fn execute(&self, name: &str, arguments: &str) -> anyhow::Result<String> {
+    async fn execute(
+        &self,
+        name: &str,
+        arguments: &str,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<String> {
         if name != "WebSearch" {
             anyhow::bail!("unknown tool: {name}");
         }
@@ -222,9 +227,9 @@ impl crate::tools::ToolProvider for WebSearchTool {
         let query = args["query"]
             .as_str()
             .ok_or_else(|| anyhow::anyhow!("missing 'query' argument"))?;
-        let limit = args["limit"].as_u64().map(|n| n as usize);
+        let limit = args["limit"].as_u64().and_then(|n| usize::try_from(n).ok());
 
-        let results = self.search(query, limit).await?;
+        let results = crate::provider::cancellable(Some(cancel), self.search(query, limit)).await?;
 
         Ok(serde_json::to_string(&results)?)
     }
@@ -232,6 +237,23 @@ impl crate::tools::ToolProvider for WebSearchTool {
     fn kind(&self, _name: &str) -> crate::tools::ToolKind {
         crate::tools::ToolKind::Read
     }
+
+    fn permission(
+        &self,
+        _name: &str,
+        arguments: &str,
+    ) -> Option<(
+        crate::permission::PermissionAction,
+        crate::permission::PermissionContext,
+    )> {
+        let args: serde_json::Value = serde_json::from_str(arguments).ok()?;
+        let query = args["query"].as_str()?.to_string();
+
+        Some((
+            crate::permission::PermissionAction::WebSearch,
+            crate::permission::PermissionContext::WebSearch { query },
+        ))
+    }
 }
 
 #[cfg(test)]
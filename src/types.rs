@@ -3,7 +3,7 @@
 use serde::{Deserialize, Serialize};
 
 /// A message in the conversation.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     /// Role: "user" or "assistant".
     pub role: Role,
@@ -21,7 +21,7 @@ pub enum Role {
 }
 
 /// Message content - can be text or structured.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Content {
     /// Simple text content.
@@ -47,15 +47,58 @@ impl Content {
                 .join(""),
         }
     }
+
+    /// Get the image and document blocks of structured content.
+    pub fn media_blocks(&self) -> impl Iterator<Item = &ContentBlock> {
+        let blocks = match self {
+            Self::Blocks(blocks) => blocks.as_slice(),
+            Self::Text(_) => &[],
+        };
+        blocks.iter().filter(|b| {
+            matches!(
+                b,
+                ContentBlock::Image { .. } | ContentBlock::Document { .. }
+            )
+        })
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
 }
 
 /// A content block in a message.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ContentBlock {
     /// Text content.
     Text { text: String },
 
+    /// Image content.
+    Image { source: MediaSource },
+
+    /// Document content (e.g. a PDF).
+    Document {
+        source: MediaSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+
+    /// Model reasoning from assistant.
+    ///
+    /// Signed blocks must be sent back unchanged in later turns so that
+    /// tool use continuations stay valid.
+    Thinking {
+        thinking: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+
+    /// Encrypted reasoning from assistant.
+    RedactedThinking { data: String },
+
     /// Tool use request from assistant.
     ToolUse {
         id: String,
@@ -64,14 +107,68 @@ pub enum ContentBlock {
     },
 
     /// Tool result from user.
+    ///
+    /// Content is plain text or blocks, so results can carry images.
     ToolResult {
         tool_use_id: String,
-        content: String,
+        content: Content,
         #[serde(skip_serializing_if = "Option::is_none")]
         is_error: Option<bool>,
     },
 }
 
+impl ContentBlock {
+    /// Create an image block from raw bytes.
+    #[must_use]
+    pub fn image(media_type: impl Into<String>, bytes: &[u8]) -> Self {
+        Self::Image {
+            source: MediaSource::base64(media_type, bytes),
+        }
+    }
+
+    /// Create a document block from raw bytes.
+    #[must_use]
+    pub fn document(media_type: impl Into<String>, bytes: &[u8], title: Option<String>) -> Self {
+        Self::Document {
+            source: MediaSource::base64(media_type, bytes),
+            title,
+        }
+    }
+}
+
+/// Source of image or document data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MediaSource {
+    /// Inline base64-encoded data.
+    Base64 { media_type: String, data: String },
+
+    /// Data the provider fetches from a URL.
+    Url { url: String },
+}
+
+impl MediaSource {
+    /// Encode raw bytes as a base64 source.
+    #[must_use]
+    pub fn base64(media_type: impl Into<String>, bytes: &[u8]) -> Self {
+        use base64::Engine;
+
+        Self::Base64 {
+            media_type: media_type.into(),
+            data: base64::engine::general_purpose::STANDARD.encode(bytes),
+        }
+    }
+
+    /// Get the source as a URL, using a `data:` URL for inline data.
+    #[must_use]
+    pub fn to_url(&self) -> String {
+        match self {
+            Self::Base64 { media_type, data } => format!("data:{media_type};base64,{data}"),
+            Self::Url { url } => url.clone(),
+        }
+    }
+}
+
 /// Tool definition.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
@@ -90,16 +187,98 @@ pub struct Tool {
 pub struct MessagesRequest {
     pub model: String,
     pub max_tokens: u32,
-    pub messages: Vec<Message>,
+    pub messages: Vec<MessagesMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<Vec<Cached<ContentBlock>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Cached<Tool>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub system: Option<String>,
+    pub stop_sequences: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tools: Option<Vec<Tool>>,
+    pub tool_choice: Option<MessagesToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ThinkingConfig>,
     pub stream: bool,
 }
 
+/// A message in a Messages API request.
+#[derive(Debug, Serialize)]
+pub struct MessagesMessage {
+    pub role: Role,
+    pub content: Vec<Cached<ContentBlock>>,
+}
+
+impl From<Message> for MessagesMessage {
+    fn from(message: Message) -> Self {
+        let blocks = match message.content {
+            Content::Text(text) => vec![ContentBlock::Text { text }],
+            Content::Blocks(blocks) => blocks,
+        };
+        Self {
+            role: message.role,
+            content: blocks.into_iter().map(Cached::from).collect(),
+        }
+    }
+}
+
+/// An item that may carry a prompt cache breakpoint.
+#[derive(Debug, Serialize)]
+pub struct Cached<T> {
+    #[serde(flatten)]
+    pub item: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+impl<T> From<T> for Cached<T> {
+    fn from(item: T) -> Self {
+        Self {
+            item,
+            cache_control: None,
+        }
+    }
+}
+
+/// Prompt cache breakpoint for the Messages API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CacheControl {
+    Ephemeral,
+}
+
+/// Extended thinking configuration for the Messages API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingConfig {
+    Enabled { budget_tokens: u32 },
+}
+
+/// Tool choice for the Messages API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessagesToolChoice {
+    Auto {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    Any {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    Tool {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    None,
+}
+
 /// Why the response stopped.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum StopReason {
     EndTurn,
@@ -109,10 +288,20 @@ pub enum StopReason {
 }
 
 /// Token usage.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Usage {
+    /// Input tokens neither read from nor written to the prompt cache.
+    #[serde(default)]
     pub input_tokens: u32,
+    /// Generated tokens, including reasoning.
+    #[serde(default)]
     pub output_tokens: u32,
+    /// Input tokens written to the prompt cache.
+    #[serde(default)]
+    pub cache_creation_input_tokens: u32,
+    /// Input tokens read from the prompt cache.
+    #[serde(default)]
+    pub cache_read_input_tokens: u32,
 }
 
 /// Streaming event from Anthropic SSE.
@@ -142,6 +331,9 @@ pub enum StreamEvent {
     Error {
         error: ApiError,
     },
+    /// Event type this client does not know.
+    #[serde(other)]
+    Other,
 }
 
 /// Message start metadata.
@@ -155,8 +347,21 @@ pub struct MessageStart {
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Delta {
-    TextDelta { text: String },
-    InputJsonDelta { partial_json: String },
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    /// Delta type this client does not know.
+    #[serde(other)]
+    Other,
 }
 
 /// Message-level delta (stop reason).
@@ -170,3 +375,80 @@ pub struct MessageDelta {
 pub struct ApiError {
     pub message: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_block_serializes_to_messages_format() {
+        let block = ContentBlock::image("image/png", b"png");
+        assert_eq!(
+            serde_json::to_value(&block).unwrap(),
+            serde_json::json!({
+                "type": "image",
+                "source": {"type": "base64", "media_type": "image/png", "data": "cG5n"}
+            })
+        );
+    }
+
+    #[test]
+    fn media_source_data_url() {
+        let source = MediaSource::base64("application/pdf", b"pdf");
+        assert_eq!(source.to_url(), "data:application/pdf;base64,cGRm");
+
+        let url = MediaSource::Url {
+            url: "https://example.com/a.png".to_string(),
+        };
+        assert_eq!(url.to_url(), "https://example.com/a.png");
+    }
+
+    #[test]
+    fn thinking_block_round_trips_signature() {
+        let json = serde_json::json!({
+            "type": "thinking",
+            "thinking": "Let me think",
+            "signature": "sig"
+        });
+        let block: ContentBlock = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&block).unwrap(), json);
+
+        let unsigned: ContentBlock =
+            serde_json::from_value(serde_json::json!({"type": "thinking", "thinking": ""}))
+                .unwrap();
+        assert!(matches!(
+            unsigned,
+            ContentBlock::Thinking {
+                signature: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn tool_result_accepts_text_or_blocks() {
+        let text: ContentBlock = serde_json::from_value(serde_json::json!({
+            "type": "tool_result",
+            "tool_use_id": "t1",
+            "content": "ok"
+        }))
+        .unwrap();
+        assert!(
+            matches!(text, ContentBlock::ToolResult { content: Content::Text(t), .. } if t == "ok")
+        );
+
+        let blocks: ContentBlock = serde_json::from_value(serde_json::json!({
+            "type": "tool_result",
+            "tool_use_id": "t1",
+            "content": [
+                {"type": "text", "text": "see image"},
+                {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}
+            ]
+        }))
+        .unwrap();
+        let ContentBlock::ToolResult { content, .. } = blocks else {
+            panic!("expected tool result");
+        };
+        assert_eq!(content.text(), "see image");
+    }
+}
@@ -0,0 +1,422 @@
+//! Token usage and cost accounting.
+//!
+//! A [`UsageLedger`] records the [`Usage`] reported for every completion
+//! together with the session, provider and model that produced it, prices
+//! it from [`ModelPricing`] rates and keeps running totals. An optional
+//! [`Budget`] turns those totals into a hard ceiling.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AgentError, Result};
+use crate::registry::{ModelInfo, ModelPricing};
+use crate::types::Usage;
+
+/// Approximate bytes per token used by [`estimate_tokens`].
+const BYTES_PER_TOKEN: usize = 4;
+
+/// Estimate the token count of a text.
+///
+/// Uses a flat four bytes per token, which is close for English prose and
+/// code with common tokenizers. Only for sizing prompts against a context
+/// window; the ledger records counted tokens alone.
+#[must_use]
+pub fn estimate_tokens(text: &str) -> u32 {
+    u32::try_from(text.len().div_ceil(BYTES_PER_TOKEN)).unwrap_or(u32::MAX)
+}
+
+/// Usage of a single completion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Session the completion belongs to.
+    pub session: String,
+    /// Provider that served the completion.
+    pub provider: String,
+    /// Model that served the completion.
+    pub model: String,
+    /// Reported token counts.
+    pub usage: Usage,
+    /// Cost in US dollars, if the model has known pricing.
+    pub cost_usd: Option<f64>,
+    /// When the completion was recorded.
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Aggregated usage over a set of records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    /// Number of completions.
+    pub requests: u64,
+    /// Uncached input tokens.
+    pub input_tokens: u64,
+    /// Output tokens.
+    pub output_tokens: u64,
+    /// Input tokens written to the prompt cache.
+    pub cache_creation_input_tokens: u64,
+    /// Input tokens read from the prompt cache.
+    pub cache_read_input_tokens: u64,
+    /// Cost in US dollars of the priced completions.
+    pub cost_usd: f64,
+    /// Completions whose model has no known pricing.
+    pub unpriced_requests: u64,
+}
+
+impl UsageTotals {
+    /// Total tokens of every kind.
+    #[must_use]
+    pub const fn total_tokens(&self) -> u64 {
+        self.input_tokens
+            + self.output_tokens
+            + self.cache_creation_input_tokens
+            + self.cache_read_input_tokens
+    }
+
+    /// Add a record to the totals.
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.input_tokens += u64::from(record.usage.input_tokens);
+        self.output_tokens += u64::from(record.usage.output_tokens);
+        self.cache_creation_input_tokens += u64::from(record.usage.cache_creation_input_tokens);
+        self.cache_read_input_tokens += u64::from(record.usage.cache_read_input_tokens);
+        match record.cost_usd {
+            Some(cost) => self.cost_usd += cost,
+            None => self.unpriced_requests += 1,
+        }
+    }
+}
+
+/// Spending ceiling for a ledger.
+///
+/// Unpriced completions count towards the token ceiling only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    /// Maximum cost in US dollars.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost_usd: Option<f64>,
+    /// Maximum total tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+}
+
+impl Budget {
+    /// Create a budget without limits.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_cost_usd: None,
+            max_tokens: None,
+        }
+    }
+
+    /// Set the dollar ceiling.
+    #[must_use]
+    pub const fn with_max_cost_usd(mut self, max_cost_usd: f64) -> Self {
+        self.max_cost_usd = Some(max_cost_usd);
+        self
+    }
+
+    /// Set the token ceiling.
+    #[must_use]
+    pub const fn with_max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Check totals against the ceilings.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::BudgetExceeded`] once a ceiling is reached.
+    pub fn check(&self, totals: &UsageTotals) -> Result<()> {
+        if let Some(max) = self.max_cost_usd
+            && totals.cost_usd >= max
+        {
+            return Err(AgentError::BudgetExceeded(format!(
+                "spent ${:.4} of ${max:.4}",
+                totals.cost_usd
+            )));
+        }
+        if let Some(max) = self.max_tokens
+            && totals.total_tokens() >= max
+        {
+            return Err(AgentError::BudgetExceeded(format!(
+                "used {} of {max} tokens",
+                totals.total_tokens()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Thread-safe record of token usage and cost.
+///
+/// Share one ledger through an `Arc` to account for several agents or
+/// sessions together; the budget applies to the ledger as a whole.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    /// Rates keyed by provider and model.
+    provider_pricing: HashMap<(String, String), ModelPricing>,
+    /// Rates keyed by model alone, for any provider.
+    pricing: HashMap<String, ModelPricing>,
+    budget: Budget,
+    records: Mutex<Vec<UsageRecord>>,
+}
+
+impl UsageLedger {
+    /// Create an empty ledger without pricing or budget.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take pricing from a model catalog.
+    ///
+    /// Rates apply to the provider each model is listed under. Models
+    /// without pricing are skipped.
+    #[must_use]
+    pub fn with_models<'a>(mut self, models: impl IntoIterator<Item = &'a ModelInfo>) -> Self {
+        for model in models {
+            if let Some(pricing) = model.pricing {
+                self.provider_pricing
+                    .insert((model.provider.clone(), model.id.clone()), pricing);
+            }
+        }
+        self
+    }
+
+    /// Set or replace the pricing of one model on any provider.
+    ///
+    /// Provider-specific pricing takes precedence.
+    #[must_use]
+    pub fn with_pricing(mut self, model: impl Into<String>, pricing: ModelPricing) -> Self {
+        self.pricing.insert(model.into(), pricing);
+        self
+    }
+
+    /// Set or replace the pricing of one model served by one provider.
+    #[must_use]
+    pub fn with_provider_pricing(
+        mut self,
+        provider: impl Into<String>,
+        model: impl Into<String>,
+        pricing: ModelPricing,
+    ) -> Self {
+        self.provider_pricing
+            .insert((provider.into(), model.into()), pricing);
+        self
+    }
+
+    /// Get the rates for a model served by a provider.
+    ///
+    /// Falls back to provider-independent pricing, then to the model id
+    /// under any provider when exactly one provider prices it.
+    fn pricing(&self, provider: &str, model: &str) -> Option<&ModelPricing> {
+        if let Some(pricing) = self
+            .provider_pricing
+            .get(&(provider.to_string(), model.to_string()))
+            .or_else(|| self.pricing.get(model))
+        {
+            return Some(pricing);
+        }
+        let mut matches = self
+            .provider_pricing
+            .iter()
+            .filter(|((_, id), _)| id == model)
+            .map(|(_, pricing)| pricing);
+        let first = matches.next()?;
+        matches.all(|other| other == first).then_some(first)
+    }
+
+    /// Set the spending ceiling.
+    #[must_use]
+    pub const fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Get the spending ceiling.
+    #[must_use]
+    pub const fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    /// Record the usage of one completion and return the priced record.
+    pub fn record(
+        &self,
+        session: impl Into<String>,
+        provider: impl Into<String>,
+        model: impl Into<String>,
+        usage: Usage,
+    ) -> UsageRecord {
+        let provider = provider.into();
+        let model = model.into();
+        let record = UsageRecord {
+            session: session.into(),
+            cost_usd: self.pricing(&provider, &model).map(|p| p.cost(&usage)),
+            provider,
+            model,
+            usage,
+            timestamp: Utc::now(),
+        };
+        self.records.lock().push(record.clone());
+        record
+    }
+
+    /// Get every record in the order it was added.
+    #[must_use]
+    pub fn records(&self) -> Vec<UsageRecord> {
+        self.records.lock().clone()
+    }
+
+    /// Totals over all records.
+    #[must_use]
+    pub fn totals(&self) -> UsageTotals {
+        self.totals_where(|_| true)
+    }
+
+    /// Totals over the records of one session.
+    #[must_use]
+    pub fn session_totals(&self, session: &str) -> UsageTotals {
+        self.totals_where(|r| r.session == session)
+    }
+
+    /// Totals grouped by session.
+    #[must_use]
+    pub fn by_session(&self) -> BTreeMap<String, UsageTotals> {
+        self.group_by(|r| r.session.clone())
+    }
+
+    /// Totals grouped by provider.
+    #[must_use]
+    pub fn by_provider(&self) -> BTreeMap<String, UsageTotals> {
+        self.group_by(|r| r.provider.clone())
+    }
+
+    /// Totals grouped by provider and model.
+    #[must_use]
+    pub fn by_model(&self) -> BTreeMap<(String, String), UsageTotals> {
+        self.group_by(|r| (r.provider.clone(), r.model.clone()))
+    }
+
+    /// Check the totals against the budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentError::BudgetExceeded`] once a ceiling is reached.
+    pub fn check_budget(&self) -> Result<()> {
+        self.budget.check(&self.totals())
+    }
+
+    fn totals_where(&self, filter: impl Fn(&UsageRecord) -> bool) -> UsageTotals {
+        let mut totals = UsageTotals::default();
+        for record in self.records.lock().iter().filter(|r| filter(r)) {
+            totals.add(record);
+        }
+        totals
+    }
+
+    fn group_by<K: Ord>(&self, key: impl Fn(&UsageRecord) -> K) -> BTreeMap<K, UsageTotals> {
+        let mut groups: BTreeMap<K, UsageTotals> = BTreeMap::new();
+        for record in self.records.lock().iter() {
+            groups.entry(key(record)).or_default().add(record);
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::default_models;
+
+    fn usage(input_tokens: u32, output_tokens: u32) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prices_records_from_catalog_and_groups_totals() {
+        let ledger = UsageLedger::new().with_models(&default_models());
+
+        let priced = ledger.record(
+            "s1",
+            "anthropic",
+            "claude-sonnet-4-20250514",
+            usage(1_000_000, 0),
+        );
+        assert_eq!(priced.cost_usd, Some(3.0));
+        ledger.record("s1", "openai", "gpt-4o", usage(0, 100_000));
+        ledger.record("s2", "ollama", "llama3.2", usage(10, 5));
+
+        let totals = ledger.totals();
+        assert_eq!(totals.requests, 3);
+        assert_eq!(totals.unpriced_requests, 1);
+        assert_eq!(totals.total_tokens(), 1_100_015);
+        assert!((totals.cost_usd - 4.0).abs() < 1e-9);
+
+        assert_eq!(ledger.session_totals("s2").requests, 1);
+        assert_eq!(ledger.by_session().len(), 2);
+        assert_eq!(ledger.by_provider()["openai"].output_tokens, 100_000);
+        let ollama = ("ollama".to_string(), "llama3.2".to_string());
+        assert_eq!(ledger.by_model()[&ollama].unpriced_requests, 1);
+    }
+
+    #[test]
+    fn prices_the_same_model_per_provider() {
+        let ledger = UsageLedger::new()
+            .with_provider_pricing("groq", "llama-3.3-70b", ModelPricing::new(0.5, 0.5))
+            .with_provider_pricing("together", "llama-3.3-70b", ModelPricing::new(1.0, 1.0))
+            .with_provider_pricing("openai", "gpt-4o", ModelPricing::new(2.5, 10.0));
+
+        let groq = ledger.record("s", "groq", "llama-3.3-70b", usage(1_000_000, 0));
+        let together = ledger.record("s", "together", "llama-3.3-70b", usage(1_000_000, 0));
+        assert_eq!(groq.cost_usd, Some(0.5));
+        assert_eq!(together.cost_usd, Some(1.0));
+
+        // Unambiguous ids fall back across providers, ambiguous ones do not
+        let proxied = ledger.record("s", "proxy", "gpt-4o", usage(1_000_000, 0));
+        assert_eq!(proxied.cost_usd, Some(2.5));
+        let unknown = ledger.record("s", "proxy", "llama-3.3-70b", usage(1, 0));
+        assert_eq!(unknown.cost_usd, None);
+    }
+
+    #[test]
+    fn budget_trips_on_either_ceiling() {
+        let ledger = UsageLedger::new()
+            .with_pricing("m", ModelPricing::new(1.0, 1.0))
+            .with_budget(
+                Budget::new()
+                    .with_max_cost_usd(1.0)
+                    .with_max_tokens(2_000_000),
+            );
+
+        ledger.record("s", "p", "m", usage(500_000, 0));
+        assert!(ledger.check_budget().is_ok());
+        ledger.record("s", "p", "m", usage(0, 500_000));
+        assert!(matches!(
+            ledger.check_budget(),
+            Err(AgentError::BudgetExceeded(_))
+        ));
+
+        let tokens = Budget::new().with_max_tokens(10);
+        assert!(tokens.check(&UsageTotals::default()).is_ok());
+        let over = UsageTotals {
+            output_tokens: 10,
+            ..Default::default()
+        };
+        assert!(tokens.check(&over).is_err());
+    }
+
+    #[test]
+    fn estimates_four_bytes_per_token() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+}